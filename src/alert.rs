@@ -0,0 +1,458 @@
+use crate::config::AlertConfig;
+use crate::duration;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// Round trip time of the latest sample, timeouts count as infinitely slow
+    Latency,
+    /// Percentage of timeouts over the rule's window
+    Loss,
+    /// Number of consecutive timeouts
+    Timeouts,
+    /// Standard deviation of the round trip times over the rule's window
+    Jitter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Above,
+    Below,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// Window used by loss and jitter rules that don't specify one with `over`.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// A rule such as `latency>100ms for 30s` or `loss>5% over 60s`. The rule
+/// fires once the metric has breached the threshold for the `for` duration.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    source: String,
+    metric: Metric,
+    op: Op,
+    /// Microseconds for latency and jitter, a percentage for loss, a count
+    /// for timeouts
+    threshold: f64,
+    /// Once firing, the rule only resolves when the metric crosses this
+    /// threshold instead of `threshold`, so it doesn't flap around the limit
+    clear: Option<f64>,
+    window: Duration,
+    duration: Duration,
+    /// Hosts the rule applies to, all of them if empty
+    hosts: Vec<String>,
+    severity: Severity,
+}
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (condition, duration) = match s.find(" for ") {
+            Some(idx) => (&s[..idx], duration::parse(&s[idx + 5..])?),
+            None => (s, Duration::from_secs(0)),
+        };
+        let (condition, window) = match condition.find(" over ") {
+            Some(idx) => (&condition[..idx], duration::parse(&condition[idx + 6..])?),
+            None => (condition, DEFAULT_WINDOW),
+        };
+        let (op_idx, op) = match (condition.find('>'), condition.find('<')) {
+            (Some(idx), None) => (idx, Op::Above),
+            (None, Some(idx)) => (idx, Op::Below),
+            _ => return Err(anyhow!("Alert rule {:?} needs one of '>' or '<'", s)),
+        };
+        let metric = match condition[..op_idx].trim() {
+            "latency" => Metric::Latency,
+            "jitter" => Metric::Jitter,
+            "loss" => Metric::Loss,
+            "timeouts" => Metric::Timeouts,
+            other => return Err(anyhow!("Unknown alert metric {:?}", other)),
+        };
+        let threshold = parse_threshold(metric, condition[op_idx + 1..].trim())?;
+        Ok(Rule {
+            source: s.trim().to_string(),
+            metric,
+            op,
+            threshold,
+            clear: None,
+            window,
+            duration,
+            hosts: vec![],
+            severity: Severity::default(),
+        })
+    }
+}
+
+fn parse_threshold(metric: Metric, value: &str) -> Result<f64> {
+    Ok(match metric {
+        Metric::Latency | Metric::Jitter => duration::parse(value)?.as_micros() as f64,
+        Metric::Loss => value
+            .trim_end_matches('%')
+            .parse()
+            .map_err(|_| anyhow!("Invalid loss percentage {:?}", value))?,
+        Metric::Timeouts => value
+            .parse::<u32>()
+            .map_err(|_| anyhow!("Invalid timeout count {:?}", value))?
+            as f64,
+    })
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Rule {
+    pub fn from_config(config: &AlertConfig) -> Result<Self> {
+        let mut rule: Rule = config.rule.parse()?;
+        if let Some(ref clear) = config.clear {
+            rule.clear = Some(parse_threshold(rule.metric, clear)?);
+        }
+        rule.hosts = config.hosts.clone();
+        rule.severity = config.severity;
+        Ok(rule)
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn applies_to(&self, host: &str) -> bool {
+        self.hosts.is_empty() || self.hosts.iter().any(|h| h == host)
+    }
+
+    /// The current value of the rule's metric, or `None` if there isn't
+    /// enough history yet to judge it.
+    fn value(&self, state: &RuleState, sample: Option<Duration>) -> Option<f64> {
+        match self.metric {
+            Metric::Latency => Some(sample.map_or(f64::INFINITY, |rtt| rtt.as_micros() as f64)),
+            Metric::Timeouts => Some(state.consecutive_timeouts as f64),
+            Metric::Loss => {
+                if !state.window_filled {
+                    return None;
+                }
+                let lost = state.history.iter().filter(|(_, s)| s.is_none()).count();
+                Some(lost as f64 * 100.0 / state.history.len() as f64)
+            }
+            Metric::Jitter => {
+                if !state.window_filled {
+                    return None;
+                }
+                let rtts: Vec<f64> = state
+                    .history
+                    .iter()
+                    .filter_map(|(_, s)| s.map(|rtt| rtt.as_micros() as f64))
+                    .collect();
+                if rtts.len() < 2 {
+                    return None;
+                }
+                let mean = rtts.iter().sum::<f64>() / rtts.len() as f64;
+                let variance =
+                    rtts.iter().map(|rtt| (rtt - mean).powi(2)).sum::<f64>() / rtts.len() as f64;
+                Some(variance.sqrt())
+            }
+        }
+    }
+
+    fn breached(&self, value: f64) -> bool {
+        match self.op {
+            Op::Above => value > self.threshold,
+            Op::Below => value < self.threshold,
+        }
+    }
+
+    fn cleared(&self, value: f64) -> bool {
+        match (self.op, self.clear) {
+            (_, None) => !self.breached(value),
+            (Op::Above, Some(clear)) => value <= clear,
+            (Op::Below, Some(clear)) => value >= clear,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    Triggered,
+    Resolved,
+}
+
+#[derive(Debug, Default, Clone)]
+struct RuleState {
+    breach_since: Option<Instant>,
+    firing: bool,
+    consecutive_timeouts: u32,
+    history: VecDeque<(Instant, Option<Duration>)>,
+    window_filled: bool,
+}
+
+impl RuleState {
+    fn record(&mut self, sample: Option<Duration>, window: Duration, now: Instant) {
+        if sample.is_some() {
+            self.consecutive_timeouts = 0;
+        } else {
+            self.consecutive_timeouts += 1;
+        }
+        self.history.push_back((now, sample));
+        // Keep a single sample older than the window so we know it is full
+        if let Some(cutoff) = now.checked_sub(window) {
+            while self.history.len() > 1 && self.history[1].0 <= cutoff {
+                self.history.pop_front();
+            }
+            self.window_filled = self.history[0].0 <= cutoff;
+        }
+    }
+}
+
+pub struct Alerts {
+    rules: Vec<Rule>,
+    hosts: Vec<String>,
+    state: Vec<Vec<RuleState>>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<Rule>, hosts: Vec<String>) -> Self {
+        let state = vec![vec![RuleState::default(); rules.len()]; hosts.len()];
+        Alerts {
+            rules,
+            hosts,
+            state,
+        }
+    }
+
+    pub fn add_host(&mut self, host: String) {
+        self.hosts.push(host);
+        self.state
+            .push(vec![RuleState::default(); self.rules.len()]);
+    }
+
+    /// Feed a sample for a host and return the rules whose state changed.
+    pub fn sample(
+        &mut self,
+        host_id: usize,
+        sample: Option<Duration>,
+        now: Instant,
+    ) -> Vec<(&Rule, Transition)> {
+        let mut transitions = vec![];
+        let host = &self.hosts[host_id];
+        for (rule, state) in self.rules.iter().zip(self.state[host_id].iter_mut()) {
+            if !rule.applies_to(host) {
+                continue;
+            }
+            state.record(sample, rule.window, now);
+            let value = match rule.value(state, sample) {
+                Some(value) => value,
+                None => continue,
+            };
+            if rule.breached(value) {
+                let since = *state.breach_since.get_or_insert(now);
+                if !state.firing && now.duration_since(since) >= rule.duration {
+                    state.firing = true;
+                    transitions.push((rule, Transition::Triggered));
+                }
+            } else {
+                state.breach_since = None;
+                if state.firing && rule.cleared(value) {
+                    state.firing = false;
+                    transitions.push((rule, Transition::Resolved));
+                }
+            }
+        }
+        transitions
+    }
+
+    pub fn is_firing(&self, host_id: usize) -> bool {
+        self.state[host_id].iter().any(|state| state.firing)
+    }
+}
+
+/// Number of consecutive timeouts after which a host is considered down.
+const DOWN_AFTER: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Up,
+    Down,
+}
+
+/// Tracks whether each host is answering at all, independently of any rules.
+pub struct Reachability {
+    timeouts: Vec<u32>,
+    down: Vec<bool>,
+}
+
+impl Reachability {
+    pub fn new(host_count: usize) -> Self {
+        Reachability {
+            timeouts: vec![0; host_count],
+            down: vec![false; host_count],
+        }
+    }
+
+    pub fn add_host(&mut self) {
+        self.timeouts.push(0);
+        self.down.push(false);
+    }
+
+    /// Feed a sample for a host, returning its new status if it changed.
+    pub fn sample(&mut self, host_id: usize, sample: Option<Duration>) -> Option<Status> {
+        match sample {
+            Some(_) => {
+                self.timeouts[host_id] = 0;
+                if self.down[host_id] {
+                    self.down[host_id] = false;
+                    return Some(Status::Up);
+                }
+            }
+            None => {
+                self.timeouts[host_id] += 1;
+                if !self.down[host_id] && self.timeouts[host_id] >= DOWN_AFTER {
+                    self.down[host_id] = true;
+                    return Some(Status::Down);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Alerts, Metric, Reachability, Rule, Severity, Status, Transition};
+    use crate::config::AlertConfig;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    pub fn test_parse_rule() {
+        let rule: Rule = "latency>100ms for 30s".parse().unwrap();
+        assert_eq!(rule.threshold, 100_000.0);
+        assert_eq!(rule.duration, Duration::from_secs(30));
+        let rule: Rule = "loss>5% over 2m".parse().unwrap();
+        assert_eq!(rule.metric, Metric::Loss);
+        assert_eq!(rule.threshold, 5.0);
+        assert_eq!(rule.window, Duration::from_secs(120));
+        assert!("timeouts>three".parse::<Rule>().is_err());
+        assert!("latency=100ms".parse::<Rule>().is_err());
+        assert!("speed>100ms".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    pub fn test_alert_for_duration() {
+        let rule: Rule = "latency>100ms for 2s".parse().unwrap();
+        let mut alerts = Alerts::new(vec![rule], vec!["host".to_string()]);
+        let start = Instant::now();
+        let slow = Some(Duration::from_millis(200));
+        assert!(alerts.sample(0, slow, start).is_empty());
+        assert!(alerts
+            .sample(0, slow, start + Duration::from_secs(1))
+            .is_empty());
+        let transitions = alerts.sample(0, slow, start + Duration::from_secs(2));
+        assert_eq!(transitions[0].1, Transition::Triggered);
+        assert!(alerts.is_firing(0));
+        let at = |secs| start + Duration::from_secs(secs);
+        let transitions = alerts.sample(0, Some(Duration::from_millis(10)), at(3));
+        assert_eq!(transitions[0].1, Transition::Resolved);
+        assert!(!alerts.is_firing(0));
+        // A breach has to last the whole time again after clearing
+        assert!(alerts.sample(0, slow, at(4)).is_empty());
+        assert!(alerts.sample(0, slow, at(5)).is_empty());
+        assert!(!alerts.is_firing(0));
+        assert_eq!(alerts.sample(0, slow, at(6))[0].1, Transition::Triggered);
+    }
+
+    #[test]
+    pub fn test_loss_over_window() {
+        let rule: Rule = "loss>15% over 10s".parse().unwrap();
+        let mut alerts = Alerts::new(vec![rule], vec!["host".to_string()]);
+        let start = Instant::now();
+        // A single early timeout isn't judged until the window has filled
+        assert!(alerts.sample(0, None, start).is_empty());
+        let pong = Some(Duration::from_millis(10));
+        for secs in 1..10 {
+            let now = start + Duration::from_secs(secs);
+            assert!(alerts.sample(0, pong, now).is_empty());
+        }
+        // 2 of 11 samples lost
+        let transitions = alerts.sample(0, None, start + Duration::from_secs(10));
+        assert_eq!(transitions[0].1, Transition::Triggered);
+    }
+
+    #[test]
+    pub fn test_jitter() {
+        let rule: Rule = "jitter>20ms over 4s".parse().unwrap();
+        assert_eq!(rule.metric, Metric::Jitter);
+        let mut alerts = Alerts::new(vec![rule], vec!["host".to_string()]);
+        let start = Instant::now();
+        let steady = Some(Duration::from_millis(80));
+        for secs in 0..5 {
+            let now = start + Duration::from_secs(secs);
+            assert!(alerts.sample(0, steady, now).is_empty());
+        }
+        for secs in 5..10 {
+            let rtt = Duration::from_millis(if secs % 2 == 0 { 5 } else { 80 });
+            let now = start + Duration::from_secs(secs);
+            let transitions = alerts.sample(0, Some(rtt), now);
+            if !transitions.is_empty() {
+                assert_eq!(transitions[0].1, Transition::Triggered);
+                return;
+            }
+        }
+        panic!("Jitter alert never triggered");
+    }
+
+    #[test]
+    pub fn test_hysteresis() {
+        let config = AlertConfig {
+            rule: "latency>100ms".to_string(),
+            clear: Some("50ms".to_string()),
+            hosts: vec!["a".to_string()],
+            severity: Severity::Critical,
+        };
+        let rule = Rule::from_config(&config).unwrap();
+        assert_eq!(rule.severity(), Severity::Critical);
+        let mut alerts = Alerts::new(vec![rule], vec!["a".to_string(), "b".to_string()]);
+        let now = Instant::now();
+        let rtt = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(alerts.sample(0, rtt(150), now)[0].1, Transition::Triggered);
+        assert!(alerts.sample(0, rtt(80), now).is_empty());
+        assert!(alerts.is_firing(0));
+        assert_eq!(alerts.sample(0, rtt(40), now)[0].1, Transition::Resolved);
+        // The rule is scoped to host "a"
+        assert!(alerts.sample(1, rtt(150), now).is_empty());
+    }
+
+    #[test]
+    pub fn test_reachability() {
+        let mut reachability = Reachability::new(1);
+        assert_eq!(reachability.sample(0, None), None);
+        assert_eq!(reachability.sample(0, None), None);
+        assert_eq!(reachability.sample(0, None), Some(Status::Down));
+        assert_eq!(reachability.sample(0, None), None);
+        let pong = Some(Duration::from_millis(10));
+        assert_eq!(reachability.sample(0, pong), Some(Status::Up));
+        assert_eq!(reachability.sample(0, pong), None);
+    }
+}
@@ -0,0 +1,106 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Host aliases from `~/.ssh/config`, mapped to their `HostName`. Aliases
+/// without one map to themselves. Patterns containing wildcards are skipped.
+pub fn from_ssh_config() -> Vec<(String, String)> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+    match home {
+        Some(home) => {
+            let path = PathBuf::from(home).join(".ssh").join("config");
+            parse_ssh_config(&fs::read_to_string(path).unwrap_or_default())
+        }
+        None => vec![],
+    }
+}
+
+/// Names from the system hosts file, mapped to their address.
+pub fn from_hosts_file() -> Vec<(String, String)> {
+    let path = if cfg!(windows) {
+        r"C:\Windows\System32\drivers\etc\hosts"
+    } else {
+        "/etc/hosts"
+    };
+    parse_hosts_file(&fs::read_to_string(path).unwrap_or_default())
+}
+
+fn parse_ssh_config(text: &str) -> Vec<(String, String)> {
+    let mut aliases: Vec<(String, String)> = vec![];
+    // Index of the first alias of the current Host block
+    let mut block_start = aliases.len();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(2, |c: char| c.is_whitespace() || c == '=');
+        let keyword = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let value = parts
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches('=')
+            .trim();
+        match keyword.as_str() {
+            "host" => {
+                block_start = aliases.len();
+                for pattern in value.split_whitespace() {
+                    if !pattern.contains(['*', '?', '!']) {
+                        aliases.push((pattern.to_string(), pattern.to_string()));
+                    }
+                }
+            }
+            "match" => block_start = aliases.len(),
+            "hostname" => {
+                for alias in aliases[block_start..].iter_mut() {
+                    alias.1 = value.to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+    aliases
+}
+
+fn parse_hosts_file(text: &str) -> Vec<(String, String)> {
+    let mut aliases = vec![];
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        if let Some(ip) = fields.next() {
+            for name in fields {
+                aliases.push((name.to_string(), ip.to_string()));
+            }
+        }
+    }
+    aliases
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_hosts_file, parse_ssh_config};
+
+    #[test]
+    pub fn test_parse_ssh_config() {
+        let aliases = parse_ssh_config(
+            "Host prod-db db1\n  HostName 10.0.0.5\n  User admin\n\n\
+             Host *.internal\n  User me\n\nHost bastion\nHostname=jump.example.com\n",
+        );
+        assert_eq!(
+            aliases,
+            vec![
+                ("prod-db".to_string(), "10.0.0.5".to_string()),
+                ("db1".to_string(), "10.0.0.5".to_string()),
+                ("bastion".to_string(), "jump.example.com".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_parse_hosts_file() {
+        let aliases =
+            parse_hosts_file("# comment\n127.0.0.1 localhost\n10.0.0.7 nas nas.lan # x\n");
+        assert_eq!(aliases.len(), 3);
+        assert_eq!(aliases[2], ("nas.lan".to_string(), "10.0.0.7".to_string()));
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// What's drawn to the terminal, saved as an asciicast v2 recording for
+/// `--asciicast`: a header line, then a line of JSON per screen update.
+pub struct Cast<W: Write> {
+    out: W,
+    started: Instant,
+}
+
+impl Cast<BufWriter<File>> {
+    pub fn create(path: &Path, (width, height): (u16, u16)) -> Result<Self> {
+        let file = File::create(path)
+            .map_err(|e| anyhow!("Could not create {}: {}", path.display(), e))?;
+        Ok(Cast::new(BufWriter::new(file), width, height)?)
+    }
+}
+
+impl<W: Write> Cast<W> {
+    fn new(mut out: W, width: u16, height: u16) -> io::Result<Self> {
+        let now = Local::now();
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": now.timestamp(),
+            "title": format!("gping {}", now.format("%Y-%m-%d %H:%M:%S")),
+        });
+        writeln!(out, "{}", header)?;
+        Ok(Cast {
+            out,
+            started: Instant::now(),
+        })
+    }
+
+    /// Flushed straight away so the recording can be played however gping
+    /// stops.
+    fn output(&mut self, at: Duration, data: &[u8]) -> io::Result<()> {
+        let event = json!([at.as_secs_f64(), "o", String::from_utf8_lossy(data)]);
+        writeln!(self.out, "{}", event)?;
+        self.out.flush()
+    }
+}
+
+/// Writes to the terminal, and to a recording if there is one. Each flush
+/// of the terminal, once a frame has been drawn, is an event in the
+/// recording, so escape sequences and characters aren't split between them.
+pub struct Tee<T: Write, W: Write> {
+    terminal: T,
+    cast: Option<Cast<W>>,
+    pending: Vec<u8>,
+}
+
+impl<T: Write, W: Write> Tee<T, W> {
+    pub fn new(terminal: T, cast: Option<Cast<W>>) -> Self {
+        Tee {
+            terminal,
+            cast,
+            pending: vec![],
+        }
+    }
+}
+
+impl<T: Write, W: Write> Write for Tee<T, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.terminal.write(buf)?;
+        if self.cast.is_some() {
+            self.pending.extend_from_slice(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.terminal.flush()?;
+        if let Some(ref mut cast) = self.cast {
+            if !self.pending.is_empty() {
+                let at = cast.started.elapsed();
+                cast.output(at, &self.pending)?;
+                self.pending.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cast, Tee};
+    use std::io::Write;
+
+    #[test]
+    pub fn test_tee() {
+        let cast = Cast::new(vec![], 80, 24).unwrap();
+        let mut tee = Tee::new(vec![], Some(cast));
+        tee.write_all("\x1b[2J".as_bytes()).unwrap();
+        tee.write_all("⠁⠂ 12ms".as_bytes()).unwrap();
+        tee.flush().unwrap();
+        // Nothing new to record
+        tee.flush().unwrap();
+        assert_eq!(tee.terminal, "\x1b[2J⠁⠂ 12ms".as_bytes());
+        let recording = String::from_utf8(tee.cast.unwrap().out).unwrap();
+        let lines: Vec<serde_json::Value> = recording
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 80);
+        assert_eq!(lines[0]["height"], 24);
+        assert!(lines[1][0].as_f64().unwrap() >= 0.0);
+        assert_eq!(lines[1][1], "o");
+        assert_eq!(lines[1][2], "\x1b[2J⠁⠂ 12ms");
+    }
+}
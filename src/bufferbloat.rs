@@ -0,0 +1,113 @@
+use histogram::Histogram;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Idle,
+    Load,
+}
+
+/// Latency while the link is idle compared with while it is loaded by
+/// throughput transfers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    pub idle: Duration,
+    pub load: Duration,
+}
+
+impl Report {
+    pub fn increase(&self) -> Duration {
+        self.load.checked_sub(self.idle).unwrap_or_default()
+    }
+
+    /// The grade the DSLReports speed test gives for this much added latency.
+    pub fn grade(&self) -> &'static str {
+        match self.increase().as_millis() {
+            0..=4 => "A+",
+            5..=29 => "A",
+            30..=59 => "B",
+            60..=199 => "C",
+            200..=399 => "D",
+            _ => "F",
+        }
+    }
+}
+
+/// Sorts each host's replies by the phase they arrived in.
+pub struct Tracker {
+    phase: Option<Phase>,
+    idle: Vec<Histogram>,
+    load: Vec<Histogram>,
+}
+
+impl Tracker {
+    pub fn new(hosts: usize) -> Self {
+        Tracker {
+            phase: None,
+            idle: (0..hosts).map(|_| Histogram::new()).collect(),
+            load: (0..hosts).map(|_| Histogram::new()).collect(),
+        }
+    }
+
+    pub fn add_host(&mut self) {
+        self.idle.push(Histogram::new());
+        self.load.push(Histogram::new());
+    }
+
+    pub fn set_phase(&mut self, phase: Phase) {
+        self.phase = Some(phase);
+    }
+
+    pub fn record(&mut self, host_id: usize, rtt: Duration) {
+        let hist = match self.phase {
+            Some(Phase::Idle) => &mut self.idle[host_id],
+            Some(Phase::Load) => &mut self.load[host_id],
+            None => return,
+        };
+        hist.increment(rtt.as_micros() as u64).unwrap_or(());
+    }
+
+    /// Median latency in each phase, once both have replies.
+    pub fn report(&self, host_id: usize) -> Option<Report> {
+        let idle = self.idle[host_id].percentile(50.0).ok()?;
+        let load = self.load[host_id].percentile(50.0).ok()?;
+        Some(Report {
+            idle: Duration::from_micros(idle),
+            load: Duration::from_micros(load),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Phase, Report, Tracker};
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_tracker() {
+        let mut tracker = Tracker::new(1);
+        // Replies before the first phase starts aren't counted
+        tracker.record(0, Duration::from_millis(500));
+        tracker.set_phase(Phase::Idle);
+        tracker.record(0, Duration::from_millis(10));
+        assert_eq!(tracker.report(0), None);
+        tracker.set_phase(Phase::Load);
+        tracker.record(0, Duration::from_millis(90));
+        let report = tracker.report(0).unwrap();
+        // The histogram keeps three significant figures
+        assert_eq!(report.increase().as_millis(), 80);
+        assert_eq!(report.grade(), "C");
+    }
+
+    #[test]
+    pub fn test_grade() {
+        let report = |idle, load| Report {
+            idle: Duration::from_millis(idle),
+            load: Duration::from_millis(load),
+        };
+        assert_eq!(report(20, 22).grade(), "A+");
+        assert_eq!(report(20, 45).grade(), "A");
+        assert_eq!(report(20, 500).grade(), "F");
+        assert_eq!(report(20, 10).grade(), "A+");
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// How far the wall clock may run ahead of the monotonic clock between two
+/// checks before we decide the machine was asleep.
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Notices the machine having been suspended. The monotonic clock stops while
+/// asleep on Linux and macOS but the wall clock keeps going, so a sleep shows
+/// up as the wall clock jumping ahead of it.
+pub struct SleepDetector {
+    wall: SystemTime,
+    monotonic: Instant,
+}
+
+impl SleepDetector {
+    pub fn new() -> Self {
+        SleepDetector {
+            wall: SystemTime::now(),
+            monotonic: Instant::now(),
+        }
+    }
+
+    /// How long the machine slept since the previous check, if it did.
+    pub fn check(&mut self) -> Option<Duration> {
+        self.check_at(SystemTime::now(), Instant::now())
+    }
+
+    fn check_at(&mut self, wall: SystemTime, monotonic: Instant) -> Option<Duration> {
+        // A wall clock stepped backwards, e.g. by NTP, isn't a sleep
+        let wall_elapsed = wall.duration_since(self.wall).unwrap_or_default();
+        let monotonic_elapsed = monotonic.saturating_duration_since(self.monotonic);
+        self.wall = wall;
+        self.monotonic = monotonic;
+        let slept = wall_elapsed.checked_sub(monotonic_elapsed)?;
+        if slept >= SLEEP_THRESHOLD {
+            Some(slept)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SleepDetector;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_sleep_detector() {
+        let mut detector = SleepDetector::new();
+        let (mut wall, mut monotonic) = (detector.wall, detector.monotonic);
+
+        wall += Duration::from_secs(1);
+        monotonic += Duration::from_secs(1);
+        assert_eq!(detector.check_at(wall, monotonic), None);
+
+        wall += Duration::from_secs(600);
+        monotonic += Duration::from_secs(1);
+        assert_eq!(
+            detector.check_at(wall, monotonic),
+            Some(Duration::from_secs(599))
+        );
+
+        // The wall clock being set back is ignored
+        wall -= Duration::from_secs(3600);
+        monotonic += Duration::from_secs(1);
+        assert_eq!(detector.check_at(wall, monotonic), None);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::probe::PingResult;
+use crate::replay::{Recorded, Recording};
+use crate::summary::{self, Row};
+use histogram::Histogram;
+use std::time::Duration;
+
+/// Two recordings played back together for `--compare`, each target's line
+/// labelled with the recording it came from. The second is moved in time to
+/// start with the first, so the sessions line up on the chart.
+pub fn merge(before: Recording, after: Recording, labels: [&str; 2]) -> Recording {
+    let start = |recording: &Recording| recording.samples.first().map_or(0, |s| s.time_ms);
+    let shift = start(&before) - start(&after);
+    let offset = before.targets.len();
+    let targets = before
+        .targets
+        .iter()
+        .map(|target| format!("{} ({})", target, labels[0]))
+        .chain(
+            after
+                .targets
+                .iter()
+                .map(|target| format!("{} ({})", target, labels[1])),
+        )
+        .collect();
+    let mut samples = before.samples;
+    samples.extend(after.samples.into_iter().map(|sample| Recorded {
+        target: sample.target + offset,
+        time_ms: sample.time_ms + shift,
+        result: sample.result,
+    }));
+    // Stable, so each recording's samples stay in the order they were taken
+    samples.sort_by_key(|sample| sample.time_ms);
+    Recording { targets, samples }
+}
+
+/// Each target's line on a chart of `recording`, as seconds since it started
+/// and round trips in microseconds, with 0 for probes that got no reply.
+pub fn points(recording: &Recording) -> Vec<Vec<(f64, f64)>> {
+    let start = recording.samples.first().map_or(0, |s| s.time_ms);
+    let mut points = vec![vec![]; recording.targets.len()];
+    for sample in &recording.samples {
+        let rtt = match sample.result {
+            PingResult::Pong(rtt) => rtt.as_micros() as f64,
+            _ => 0f64,
+        };
+        let secs = (sample.time_ms - start) as f64 / 1000.0;
+        points[sample.target].push((secs, rtt));
+    }
+    points
+}
+
+/// How each target did over the whole of `recording`.
+pub fn rows(recording: &Recording) -> Vec<Row> {
+    let mut sent = vec![0; recording.targets.len()];
+    let mut replies: Vec<_> = recording.targets.iter().map(|_| Histogram::new()).collect();
+    for sample in &recording.samples {
+        sent[sample.target] += 1;
+        if let PingResult::Pong(rtt) = sample.result {
+            replies[sample.target]
+                .increment(rtt.as_micros() as u64)
+                .unwrap_or(());
+        }
+    }
+    recording
+        .targets
+        .iter()
+        .enumerate()
+        .map(|(target, name)| Row::new(name, sent[target], &replies[target]))
+        .collect()
+}
+
+/// A table of how each target in both recordings changed from one to the
+/// other.
+pub fn table(before: &[Row], after: &[Row]) -> String {
+    let header = [
+        "target",
+        "avg before",
+        "after",
+        "change",
+        "p95 before",
+        "after",
+        "change",
+        "loss before",
+        "after",
+        "change",
+    ];
+    let mut cells = vec![header
+        .iter()
+        .map(|cell| cell.to_string())
+        .collect::<Vec<_>>()];
+    for old in before {
+        let new = match after.iter().find(|row| row.target == old.target) {
+            Some(new) => new,
+            None => continue,
+        };
+        cells.push(vec![
+            old.target.clone(),
+            summary::millis(old.avg),
+            summary::millis(new.avg),
+            change(old.avg, new.avg),
+            summary::millis(old.p95),
+            summary::millis(new.p95),
+            change(old.p95, new.p95),
+            format!("{:.1}%", old.loss()),
+            format!("{:.1}%", new.loss()),
+            format!("{:+.1}%", new.loss() - old.loss()),
+        ]);
+    }
+    summary::align(cells).trim_end().to_string()
+}
+
+fn change(before: Option<Duration>, after: Option<Duration>) -> String {
+    match (before, after) {
+        (Some(before), Some(after)) => format!(
+            "{:+.1}ms",
+            (after.as_secs_f64() - before.as_secs_f64()) * 1000.0
+        ),
+        _ => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{merge, points, rows, table};
+    use crate::probe::PingResult;
+    use crate::replay::{Recorded, Recording};
+    use std::time::Duration;
+
+    fn recording(start_ms: i64, rtts_ms: &[Option<u64>]) -> Recording {
+        Recording {
+            targets: vec!["example.com".to_string()],
+            samples: rtts_ms
+                .iter()
+                .enumerate()
+                .map(|(n, rtt)| Recorded {
+                    target: 0,
+                    time_ms: start_ms + n as i64 * 1000,
+                    result: rtt.map_or(PingResult::Timeout, |rtt| {
+                        PingResult::Pong(Duration::from_millis(rtt))
+                    }),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    pub fn test_merge() {
+        let before = recording(1_000_000, &[Some(10), Some(12)]);
+        let after = recording(5_000_000, &[Some(20)]);
+        let merged = merge(before, after, ["isp-a.db", "isp-b.db"]);
+        assert_eq!(
+            merged.targets,
+            vec!["example.com (isp-a.db)", "example.com (isp-b.db)"]
+        );
+        let samples: Vec<_> = merged
+            .samples
+            .iter()
+            .map(|sample| (sample.target, sample.time_ms))
+            .collect();
+        assert_eq!(
+            samples,
+            vec![(0, 1_000_000), (1, 1_000_000), (0, 1_001_000)]
+        );
+        assert_eq!(
+            points(&merged),
+            vec![
+                vec![(0.0, 10_000.0), (1.0, 12_000.0)],
+                vec![(0.0, 20_000.0)]
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_table() {
+        let before = rows(&recording(0, &[Some(10), Some(20), None, Some(30)]));
+        let after = rows(&recording(0, &[Some(15), Some(25), Some(35), Some(45)]));
+        assert_eq!(before[0].sent, 4);
+        assert_eq!(before[0].received, 3);
+        assert_eq!(
+            table(&before, &after),
+            "\
+target       avg before   after   change  p95 before   after   change  loss before  after  change
+example.com      20.0ms  30.0ms  +10.0ms      30.0ms  45.0ms  +15.0ms        25.0%   0.0%  -25.0%"
+        );
+    }
+}
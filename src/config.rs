@@ -0,0 +1,179 @@
+use crate::alert::Severity;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings read from the file given with `--config`, for options that are
+/// too unwieldy to pass on the command line.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub smtp: Option<SmtpConfig>,
+    pub pagerduty: Option<PagerDutyConfig>,
+    pub opsgenie: Option<OpsgenieConfig>,
+    #[serde(default)]
+    pub alert: Vec<AlertConfig>,
+    pub dashboard: Option<DashboardConfig>,
+    pub schedule: Option<ScheduleConfig>,
+}
+
+/// A `[schedule]` table with `[[schedule.window]]` entries, for probing only
+/// in those windows when running headless.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleConfig {
+    /// A directory to write each window's report to as JSON
+    pub reports: Option<PathBuf>,
+    pub window: Vec<WindowConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WindowConfig {
+    /// When the window opens, like `0 3 * * *` for every night at 3am
+    pub cron: String,
+    /// How long it stays open, like `30m`
+    pub duration: String,
+}
+
+/// A `[dashboard]` table with `[[dashboard.pane]]` entries, laying the screen
+/// out as a grid of panes instead of a single chart.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DashboardConfig {
+    #[serde(default = "default_columns")]
+    pub columns: usize,
+    pub pane: Vec<PaneConfig>,
+}
+
+fn default_columns() -> usize {
+    2
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaneConfig {
+    pub title: String,
+    /// Hosts shown in this pane, which are pinged as if given on the command line
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub view: PaneView,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaneView {
+    /// Host rows above a latency chart, like the default screen
+    #[default]
+    Chart,
+    /// Just the statistics of each host
+    Stats,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PagerDutyConfig {
+    /// Integration key of an Events API v2 integration
+    pub routing_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpsgenieConfig {
+    pub api_key: String,
+    /// Use the EU instance of the API
+    #[serde(default)]
+    pub eu: bool,
+}
+
+/// An `[[alert]]` entry. `rule` uses the same syntax as `--alert`, `clear`
+/// is the threshold the metric must cross before a firing alert resolves.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertConfig {
+    pub rule: String,
+    pub clear: Option<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SmtpConfig {
+    pub server: String,
+    pub port: Option<u16>,
+    /// Use STARTTLS on the submission port instead of implicit TLS.
+    #[serde(default)]
+    pub starttls: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("Could not parse config file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, Severity};
+
+    #[test]
+    pub fn test_parse_smtp() {
+        let config: Config = toml::from_str(
+            r#"
+            [smtp]
+            server = "smtp.example.com"
+            from = "gping <gping@example.com>"
+            to = ["ops@example.com"]
+            "#,
+        )
+        .unwrap();
+        let smtp = config.smtp.unwrap();
+        assert_eq!(smtp.server, "smtp.example.com");
+        assert!(!smtp.starttls);
+        assert_eq!(smtp.to, vec!["ops@example.com"]);
+        assert!(config.alert.is_empty());
+        assert!(config.pagerduty.is_none());
+    }
+
+    #[test]
+    pub fn test_parse_alerts() {
+        let config: Config = toml::from_str(
+            r#"
+            [[alert]]
+            rule = "latency>100ms for 30s"
+            clear = "80ms"
+            hosts = ["example.com"]
+            severity = "critical"
+
+            [[alert]]
+            rule = "loss>5% over 60s"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.alert.len(), 2);
+        assert_eq!(config.alert[0].severity, Severity::Critical);
+        assert_eq!(config.alert[1].severity, Severity::Warning);
+        assert!(config.alert[1].hosts.is_empty());
+    }
+
+    #[test]
+    pub fn test_parse_schedule() {
+        let config: Config = toml::from_str(
+            r#"
+            [schedule]
+            reports = "/var/log/gping"
+
+            [[schedule.window]]
+            cron = "0 3 * * *"
+            duration = "30m"
+            "#,
+        )
+        .unwrap();
+        let schedule = config.schedule.unwrap();
+        assert_eq!(schedule.reports.unwrap().to_str(), Some("/var/log/gping"));
+        assert_eq!(schedule.window[0].cron, "0 3 * * *");
+        assert_eq!(schedule.window[0].duration, "30m");
+    }
+}
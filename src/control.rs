@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// A command sent to the control socket, one per line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `annotate "deploy v1.2"` marks the chart
+    Annotate(String),
+    /// `add-host example.com` starts pinging another host
+    AddHost(String),
+    /// `pause` stops adding samples to the chart until `resume`
+    Pause,
+    Resume,
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, arg) = match line.find(char::is_whitespace) {
+            Some(idx) => (&line[..idx], line[idx..].trim()),
+            None => (line, ""),
+        };
+        let arg = arg.trim_matches('"');
+        let require_arg = || {
+            if arg.is_empty() {
+                Err(anyhow!("{} needs an argument", name))
+            } else {
+                Ok(arg.to_string())
+            }
+        };
+        match name {
+            "annotate" => Ok(Command::Annotate(require_arg()?)),
+            "add-host" => Ok(Command::AddHost(require_arg()?)),
+            "pause" => Ok(Command::Pause),
+            "resume" => Ok(Command::Resume),
+            _ => Err(anyhow!("Unknown command {:?}", name)),
+        }
+    }
+}
+
+/// Accept connections on a Unix socket at `path` until `killed` is set,
+/// passing each command to `handle` and answering `ok` or `error: ...`.
+#[cfg(unix)]
+pub fn listen(
+    path: &Path,
+    killed: Arc<AtomicBool>,
+    mut handle: impl FnMut(Command) -> Result<()>,
+) -> Result<()> {
+    use std::io::{BufRead, BufReader, ErrorKind, Write};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    // A socket left behind by a previous run would stop us binding, but
+    // anything else there is more likely a mistyped path
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!(
+                "{} already exists and is not a socket",
+                path.display()
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|e| anyhow!("Could not listen on {}: {}", path.display(), e))?;
+    // Poll so the thread notices when gping exits
+    listener.set_nonblocking(true)?;
+    while !killed.load(Ordering::Acquire) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match line.parse().and_then(&mut handle) {
+                Ok(()) => "ok".to_string(),
+                Err(e) => format!("error: {}", e),
+            };
+            // The client may not wait for the reply
+            writeln!(writer, "{}", reply).unwrap_or(());
+        }
+    }
+    std::fs::remove_file(path).unwrap_or(());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen(
+    _path: &Path,
+    _killed: Arc<AtomicBool>,
+    _handle: impl FnMut(Command) -> Result<()>,
+) -> Result<()> {
+    Err(anyhow!("The control socket is only supported on Unix"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Command;
+
+    #[test]
+    pub fn test_parse() {
+        assert_eq!(
+            "annotate \"deploy v1.2\"".parse::<Command>().unwrap(),
+            Command::Annotate("deploy v1.2".to_string())
+        );
+        assert_eq!(
+            "add-host example.com\n".parse::<Command>().unwrap(),
+            Command::AddHost("example.com".to_string())
+        );
+        assert_eq!("pause".parse::<Command>().unwrap(), Command::Pause);
+        assert!("annotate".parse::<Command>().is_err());
+        assert!("reboot".parse::<Command>().is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::config::{DashboardConfig, PaneView};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+/// One cell of the dashboard grid.
+#[derive(Debug, Clone)]
+pub struct Pane {
+    pub title: String,
+    pub view: PaneView,
+    pub host_ids: Vec<usize>,
+}
+
+/// Match each configured pane's hosts to the series being pinged. A host
+/// pinged at several addresses contributes every one of its series.
+pub fn panes(config: &DashboardConfig, hosts: &[String]) -> Vec<Pane> {
+    let mut panes = config
+        .pane
+        .iter()
+        .map(|pane| Pane {
+            title: pane.title.clone(),
+            view: pane.view,
+            host_ids: vec![],
+        })
+        .collect();
+    for (host_id, host) in hosts.iter().enumerate() {
+        add_host(&mut panes, config, host_id, host);
+    }
+    panes
+}
+
+/// Show a host in the panes configured with it. Hosts in none of them are
+/// charted in a pane after the others, rather than pinged unseen.
+pub fn add_host(panes: &mut Vec<Pane>, config: &DashboardConfig, host_id: usize, host: &str) {
+    // Without any panes configured the usual screen is shown instead
+    if config.pane.is_empty() {
+        return;
+    }
+    let mut shown = false;
+    for (pane, configured) in panes.iter_mut().zip(&config.pane) {
+        if configured.hosts.iter().any(|configured| configured == host) {
+            pane.host_ids.push(host_id);
+            shown = true;
+        }
+    }
+    if shown {
+        return;
+    }
+    if panes.len() == config.pane.len() {
+        panes.push(Pane {
+            title: "Other".to_string(),
+            view: PaneView::Chart,
+            host_ids: vec![],
+        });
+    }
+    panes.last_mut().unwrap().host_ids.push(host_id);
+}
+
+/// Split `area` into `count` cells, `columns` to a row. Rows share the height
+/// equally and a short last row is stretched across the full width.
+pub fn grid(area: Rect, count: usize, columns: usize) -> Vec<Rect> {
+    let columns = columns.max(1);
+    let rows = count.div_ceil(columns);
+    if rows == 0 {
+        return vec![];
+    }
+    let row_areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Ratio(1, rows as u32); rows])
+        .split(area);
+    row_areas
+        .into_iter()
+        .enumerate()
+        .flat_map(|(row, row_area)| {
+            let cells = (count - row * columns).min(columns);
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Ratio(1, cells as u32); cells])
+                .split(row_area)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{add_host, grid, panes};
+    use crate::config::{Config, PaneView};
+    use tui::layout::Rect;
+
+    #[test]
+    pub fn test_grid() {
+        let cells = grid(Rect::new(0, 0, 100, 40), 3, 2);
+        assert_eq!(cells.len(), 3);
+        assert_eq!(cells[0], Rect::new(0, 0, 50, 20));
+        assert_eq!(cells[1], Rect::new(50, 0, 50, 20));
+        assert_eq!(cells[2], Rect::new(0, 20, 100, 20));
+        assert!(grid(Rect::new(0, 0, 100, 40), 0, 2).is_empty());
+    }
+
+    #[test]
+    pub fn test_panes() {
+        let config: Config = toml::from_str(
+            r#"
+            [[dashboard.pane]]
+            title = "WAN"
+            hosts = ["1.1.1.1", "example.com"]
+
+            [[dashboard.pane]]
+            title = "Gateway"
+            hosts = ["192.168.1.1"]
+            view = "stats"
+            "#,
+        )
+        .unwrap();
+        let hosts = vec![
+            "example.com".to_string(),
+            "192.168.1.1".to_string(),
+            "example.com".to_string(),
+        ];
+        let dashboard = config.dashboard.unwrap();
+        let mut panes = panes(&dashboard, &hosts);
+        assert_eq!(panes.len(), 2);
+        assert_eq!(panes[0].host_ids, vec![0, 2]);
+        assert_eq!(panes[1].host_ids, vec![1]);
+        // Hosts in no pane get one of their own
+        add_host(&mut panes, &dashboard, 3, "10.0.0.1");
+        add_host(&mut panes, &dashboard, 4, "1.1.1.1");
+        add_host(&mut panes, &dashboard, 5, "10.0.0.2");
+        assert_eq!(panes.len(), 3);
+        assert_eq!(panes[0].host_ids, vec![0, 2, 4]);
+        assert_eq!(panes[2].title, "Other");
+        assert_eq!(panes[2].view, PaneView::Chart);
+        assert_eq!(panes[2].host_ids, vec![3, 5]);
+    }
+}
@@ -0,0 +1,41 @@
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+
+/// Parse a human friendly duration such as `500ms`, `30s`, `5m` or `1.5h`.
+/// A bare number is interpreted as seconds.
+pub fn parse(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration {:?}", input))?;
+    let multiplier = match unit.trim() {
+        "us" | "µs" => 0.000_001,
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(anyhow!("Invalid duration unit {:?} in {:?}", unit, input)),
+    };
+    Ok(Duration::from_secs_f64(value * multiplier))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_parse() {
+        assert_eq!(parse("100ms").unwrap(), Duration::from_millis(100));
+        assert_eq!(parse("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse("2").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse("0.5").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse("5m").unwrap(), Duration::from_secs(300));
+        assert!(parse("5 parsecs").is_err());
+        assert!(parse("ms").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use anyhow::{anyhow, Result};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::Color as _;
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+use tui::style::Color;
+
+/// Size of exported images, in pixels.
+const SIZE: (u32, u32) = (1280, 720);
+
+/// Image formats the chart can be exported as, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Svg,
+    Png,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("svg") => Ok(Format::Svg),
+            Some("png") => Ok(Format::Png),
+            _ => Err(anyhow!(
+                "Can only export the chart to .svg or .png files, not {}",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// A host's line on the chart, its y values in microseconds with 0 for
+/// probes that got no reply.
+pub struct Series {
+    pub name: String,
+    pub color: Color,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// What's on screen, to draw again as an image.
+pub struct Chart {
+    pub title: String,
+    pub series: Vec<Series>,
+    pub x_bounds: [f64; 2],
+    pub y_bounds: [f64; 2],
+}
+
+/// Write `chart` to `path` as an SVG or PNG image, by its extension.
+pub fn write(chart: &Chart, path: &Path) -> Result<()> {
+    let drawn = match Format::of(path)? {
+        Format::Svg => draw(SVGBackend::new(path, SIZE).into_drawing_area(), chart),
+        Format::Png => draw(BitMapBackend::new(path, SIZE).into_drawing_area(), chart),
+    };
+    drawn.map_err(|e| anyhow!("Could not export the chart to {}: {}", path.display(), e))
+}
+
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    chart: &Chart,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let [x_min, x_max] = chart.x_bounds;
+    let [y_min, y_max] = chart.y_bounds;
+    let mut context = ChartBuilder::on(&root)
+        .caption(&chart.title, ("sans-serif", 24))
+        .margin(16)
+        .x_label_area_size(8)
+        .y_label_area_size(80)
+        .build_cartesian_2d(x_min..x_max.max(x_min + 1.0), y_min..y_max)?;
+    context
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(0)
+        .y_label_formatter(&|&micros| format!("{:?}", Duration::from_micros(micros as u64)))
+        .draw()?;
+    for series in &chart.series {
+        let color = rgb(series.color);
+        let mut lines = segments(&series.points).into_iter();
+        // Labelled once, by whichever piece of the line is drawn first
+        let first = lines.next().unwrap_or_default();
+        context
+            .draw_series(LineSeries::new(first, color.stroke_width(2)))?
+            .label(&series.name)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.filled()));
+        for line in lines {
+            context.draw_series(LineSeries::new(line, color.stroke_width(2)))?;
+        }
+        // Probes without a reply are marked along the bottom, as on screen
+        let missed = series.points.iter().filter(|point| point.1 == 0.0);
+        context.draw_series(missed.map(|&(x, _)| Cross::new((x, y_min), 4, color.filled())))?;
+    }
+    context
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    root.present()?;
+    Ok(())
+}
+
+/// Split a line where probes got no reply, so it has gaps rather than
+/// dropping to 0.
+fn segments(points: &[(f64, f64)]) -> Vec<Vec<(f64, f64)>> {
+    points
+        .split(|point| point.1 == 0.0)
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.to_vec())
+        .collect()
+}
+
+/// The RGB of a terminal color, as xterm shows it.
+fn rgb(color: Color) -> RGBColor {
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    let index = match color {
+        Color::Rgb(r, g, b) => return RGBColor(r, g, b),
+        Color::Indexed(index) => index,
+        Color::Black | Color::Reset => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+    };
+    let (r, g, b) = match index {
+        0..=15 => BASIC[index as usize],
+        // A 6x6x6 cube of colors, then 24 shades of grey
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let n = index - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let grey = 8 + (index - 232) * 10;
+            (grey, grey, grey)
+        }
+    };
+    RGBColor(r, g, b)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rgb, segments, write, Chart, Format, Series};
+    use plotters::style::RGBColor;
+    use std::fs;
+    use std::path::Path;
+    use tui::style::Color;
+
+    #[test]
+    pub fn test_format() {
+        assert_eq!(Format::of(Path::new("chart.svg")).unwrap(), Format::Svg);
+        assert_eq!(Format::of(Path::new("out/Chart.PNG")).unwrap(), Format::Png);
+        assert!(Format::of(Path::new("chart.jpg")).is_err());
+        assert!(Format::of(Path::new("chart")).is_err());
+    }
+
+    #[test]
+    pub fn test_segments() {
+        let points = [(1.0, 0.0), (2.0, 10.0), (3.0, 12.0), (4.0, 0.0), (5.0, 9.0)];
+        assert_eq!(
+            segments(&points),
+            vec![vec![(2.0, 10.0), (3.0, 12.0)], vec![(5.0, 9.0)]]
+        );
+        assert!(segments(&[(1.0, 0.0)]).is_empty());
+    }
+
+    #[test]
+    pub fn test_rgb() {
+        assert_eq!(rgb(Color::Indexed(1)), RGBColor(205, 0, 0));
+        assert_eq!(rgb(Color::LightBlue), RGBColor(92, 92, 255));
+        assert_eq!(rgb(Color::Indexed(196)), RGBColor(255, 0, 0));
+        assert_eq!(rgb(Color::Indexed(244)), RGBColor(128, 128, 128));
+        assert_eq!(rgb(Color::Rgb(1, 2, 3)), RGBColor(1, 2, 3));
+    }
+
+    #[test]
+    pub fn test_write_svg() {
+        let path = std::env::temp_dir().join(format!("gping-test-{}.svg", std::process::id()));
+        let chart = Chart {
+            title: "example.com".to_string(),
+            series: vec![Series {
+                name: "example.com".to_string(),
+                color: Color::Indexed(1),
+                points: vec![(1.0, 12_000.0), (2.0, 0.0), (3.0, 14_000.0)],
+            }],
+            x_bounds: [1.0, 3.0],
+            y_bounds: [10_000.0, 15_000.0],
+        };
+        write(&chart, &path).unwrap();
+        let svg = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(svg.contains("<svg"));
+        assert!(svg.contains("example.com"));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::probe::PingResult;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Splits samples fed in line by line, as `value` or `label,value` with the
+/// value in milliseconds, into one series per label.
+pub struct Reader<F> {
+    /// What samples without a label are graphed as
+    default_label: String,
+    series: HashMap<String, mpsc::Sender<PingResult>>,
+    new_series: F,
+}
+
+impl<F> Reader<F>
+where
+    F: FnMut(String, mpsc::Receiver<PingResult>) -> Result<()>,
+{
+    /// The first sample with each label calls `new_series` with the label and
+    /// the stream its samples go to.
+    pub fn new(default_label: &str, new_series: F) -> Self {
+        Reader {
+            default_label: default_label.to_string(),
+            series: HashMap::new(),
+            new_series,
+        }
+    }
+
+    /// Read samples from `input` until it ends, skipping lines that aren't
+    /// samples. Returns false if the series are gone because gping is
+    /// quitting.
+    pub fn read(&mut self, input: impl BufRead) -> Result<bool> {
+        for line in input.lines() {
+            let line = line?;
+            let (label, value) = match parse_line(&line) {
+                Some((label, value)) => (label.unwrap_or(self.default_label.as_str()), value),
+                None => continue,
+            };
+            if !self.series.contains_key(label) {
+                let (tx, rx) = mpsc::channel();
+                (self.new_series)(label.to_string(), rx)?;
+                self.series.insert(label.to_string(), tx);
+            }
+            if self.series[label].send(PingResult::Pong(value)).is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Read samples from the named pipe at `path`, opening it again whenever
+/// the writer closes it so daemons can come and go. Anything else at `path`
+/// is read once.
+pub fn read_pipe<F>(path: &Path, reader: &mut Reader<F>) -> Result<()>
+where
+    F: FnMut(String, mpsc::Receiver<PingResult>) -> Result<()>,
+{
+    let reopen = is_fifo(path);
+    loop {
+        // Opening a FIFO waits for a writer
+        let file = File::open(path)?;
+        if !reader.read(BufReader::new(file))? || !reopen {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::fs;
+    use std::os::unix::fs::FileTypeExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_: &Path) -> bool {
+    false
+}
+
+fn parse_line(line: &str) -> Option<(Option<&str>, Duration)> {
+    let (label, value) = match line.rfind(',') {
+        Some(idx) => (Some(line[..idx].trim()), &line[idx + 1..]),
+        None => (None, line),
+    };
+    let value: f64 = value.trim().parse().ok()?;
+    if label == Some("") || !value.is_finite() || value < 0.0 {
+        return None;
+    }
+    Some((
+        label,
+        Duration::from_micros((value * 1000.0).round() as u64),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_line, Reader};
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_parse_line() {
+        assert_eq!(
+            parse_line("12.5"),
+            Some((None, Duration::from_micros(12500)))
+        );
+        assert_eq!(
+            parse_line("db primary, 3"),
+            Some((Some("db primary"), Duration::from_millis(3)))
+        );
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line("starting probe"), None);
+        assert_eq!(parse_line("cpu,-1"), None);
+        assert_eq!(parse_line(",5"), None);
+    }
+
+    #[test]
+    pub fn test_read() {
+        let mut series = vec![];
+        let mut reader = Reader::new("stdin", |label, rx| {
+            series.push((label, rx));
+            Ok(())
+        });
+        assert!(reader.read(&b"a,1\n2\nnoise\n"[..]).unwrap());
+        // Series carry on when the input is opened again
+        assert!(reader.read(&b"a,3\n"[..]).unwrap());
+        drop(reader);
+        let labels: Vec<_> = series.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, vec!["a", "stdin"]);
+        assert_eq!(series[0].1.iter().count(), 2);
+        assert_eq!(series[1].1.iter().count(), 1);
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::process::Command;
+
+/// The gateway of the IPv4 default route, read from `/proc/net/route` on
+/// Linux and asked of `route` elsewhere.
+pub fn default_gateway() -> Result<Ipv4Addr> {
+    let gateway = if cfg!(target_os = "linux") {
+        let table = fs::read_to_string("/proc/net/route")
+            .map_err(|e| anyhow!("Could not read the routing table: {}", e))?;
+        parse_proc_route(&table)
+    } else if cfg!(windows) {
+        parse_route_print(&run("route", &["print", "-4", "0.0.0.0"])?)
+    } else {
+        parse_route_get(&run("route", &["-n", "get", "default"])?)
+    };
+    gateway.ok_or_else(|| anyhow!("There is no IPv4 default route with a gateway"))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| anyhow!("Could not run {}: {}", program, e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Linux's routing table, with addresses as little endian hex, like
+/// `eth0  00000000  0101A8C0  0003  0  0  100  00000000  0  0  0`.
+fn parse_proc_route(table: &str) -> Option<Ipv4Addr> {
+    const RTF_UP: u16 = 0x1;
+    const RTF_GATEWAY: u16 = 0x2;
+    let mut best: Option<(u32, Ipv4Addr)> = None;
+    for line in table.lines().skip(1) {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        if fields.len() < 8 || fields[1] != "00000000" || fields[7] != "00000000" {
+            continue;
+        }
+        let flags = u16::from_str_radix(fields[3], 16).ok()?;
+        if flags & (RTF_UP | RTF_GATEWAY) != RTF_UP | RTF_GATEWAY {
+            continue;
+        }
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        let gateway = Ipv4Addr::from(gateway.swap_bytes());
+        let metric = fields[6].parse().unwrap_or(u32::MAX);
+        // With several default routes, the kernel uses the lowest metric
+        if best.is_none_or(|(best, _)| metric < best) {
+            best = Some((metric, gateway));
+        }
+    }
+    best.map(|(_, gateway)| gateway)
+}
+
+/// What the BSDs' and macOS' `route -n get default` print, which has a
+/// line like `    gateway: 192.168.1.1`.
+fn parse_route_get(output: &str) -> Option<Ipv4Addr> {
+    output.lines().find_map(|line| {
+        let line = line.trim();
+        line.strip_prefix("gateway:")?.trim().parse().ok()
+    })
+}
+
+/// What Windows' `route print -4 0.0.0.0` prints, which lists the default
+/// route as `0.0.0.0  0.0.0.0  192.168.1.1  192.168.1.20  25`.
+fn parse_route_print(output: &str) -> Option<Ipv4Addr> {
+    let mut best: Option<(u32, Ipv4Addr)> = None;
+    for line in output.lines() {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        if fields.len() != 5 || fields[0] != "0.0.0.0" || fields[1] != "0.0.0.0" {
+            continue;
+        }
+        // Routes without a gateway say "On-link" instead
+        let gateway = match fields[2].parse() {
+            Ok(gateway) => gateway,
+            Err(_) => continue,
+        };
+        let metric = fields[4].parse().unwrap_or(u32::MAX);
+        if best.is_none_or(|(best, _)| metric < best) {
+            best = Some((metric, gateway));
+        }
+    }
+    best.map(|(_, gateway)| gateway)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_proc_route, parse_route_get, parse_route_print};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    pub fn test_parse_proc_route() {
+        let table = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0100000A\t0003\t0\t0\t600\t00000000\t0\t0\t0
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+";
+        assert_eq!(parse_proc_route(table), Some(Ipv4Addr::new(192, 168, 1, 1)));
+        assert_eq!(parse_proc_route(table.lines().next().unwrap()), None);
+    }
+
+    #[test]
+    pub fn test_parse_route_get() {
+        let output = "   route to: default
+destination: default
+       mask: default
+    gateway: 192.168.1.1
+  interface: en0
+";
+        assert_eq!(parse_route_get(output), Some(Ipv4Addr::new(192, 168, 1, 1)));
+        assert_eq!(
+            parse_route_get("route: writing to routing socket: not in table"),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_parse_route_print() {
+        let output = "\
+IPv4 Route Table
+===========================================================================
+Active Routes:
+Network Destination        Netmask          Gateway       Interface  Metric
+          0.0.0.0          0.0.0.0      192.168.1.1     192.168.1.20     25
+          0.0.0.0          0.0.0.0         On-link         10.8.0.2      5
+===========================================================================
+";
+        assert_eq!(
+            parse_route_print(output),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use crate::summary::{self, Row};
+use chrono::{DateTime, Local};
+use serde_json::json;
+use std::time::Duration;
+
+/// Every sample of the session, kept for `--html-report` since the chart only
+/// holds what's on screen.
+#[derive(Default)]
+pub struct Timeline {
+    /// Milliseconds since the epoch and the latency in microseconds, if the
+    /// probe was answered, by host id
+    samples: Vec<Vec<(i64, Option<u64>)>>,
+}
+
+impl Timeline {
+    pub fn push(&mut self, host_id: usize, time: DateTime<Local>, latency: Option<Duration>) {
+        if self.samples.len() <= host_id {
+            self.samples.resize(host_id + 1, vec![]);
+        }
+        self.samples[host_id].push((
+            time.timestamp_millis(),
+            latency.map(|latency| latency.as_micros() as u64),
+        ));
+    }
+}
+
+/// A page that needs nothing else to open, with a chart of `timeline` that
+/// shows samples under the mouse and hides targets clicked in its legend,
+/// and `rows` as a table below.
+pub fn report(
+    rows: &[Row],
+    timeline: &Timeline,
+    started: DateTime<Local>,
+    elapsed: Duration,
+) -> String {
+    let series: Vec<_> = rows
+        .iter()
+        .enumerate()
+        .map(|(host_id, row)| {
+            json!({
+                "name": row.target,
+                "samples": timeline.samples.get(host_id).map_or(&[][..], |samples| &samples[..]),
+            })
+        })
+        .collect();
+    let mut table = String::new();
+    for row in rows {
+        let cells = [
+            escape(&row.target),
+            row.sent.to_string(),
+            row.received.to_string(),
+            format!("{:.1}%", row.loss()),
+            summary::millis(row.min),
+            summary::millis(row.avg),
+            summary::millis(row.max),
+            summary::millis(row.p50),
+            summary::millis(row.p95),
+            summary::millis(row.p99),
+            summary::millis(row.stddev),
+        ];
+        table.push_str(&format!("<tr><td>{}</td></tr>\n", cells.join("</td><td>")));
+    }
+    let title = format!("gping {}", started.format("%Y-%m-%d %H:%M:%S"));
+    // Each placeholder is filled once, before anything filled in later, so
+    // targets that look like placeholders stay as they are
+    PAGE.replace("{title}", &escape(&title))
+        .replace("{session}", &summary::format_elapsed(elapsed))
+        // Nothing in the data may end the script early
+        .replacen(
+            "{data}",
+            &json!(series).to_string().replace("</", "<\\/"),
+            1,
+        )
+        .replacen("{rows}", &table, 1)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body { font-family: sans-serif; margin: 2em; color: #222; }
+svg { width: 100%; height: 420px; border: 1px solid #ddd; }
+.legend span { cursor: pointer; margin-right: 1.5em; }
+.legend span.hidden { opacity: 0.3; }
+#tip { position: absolute; background: #fff; border: 1px solid #aaa; padding: 4px 8px;
+       font-size: 13px; pointer-events: none; display: none; white-space: pre; }
+table { border-collapse: collapse; margin-top: 1.5em; }
+th, td { padding: 4px 12px; text-align: right; border-bottom: 1px solid #eee; }
+th:first-child, td:first-child { text-align: left; }
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>Session of {session}</p>
+<div class="legend" id="legend"></div>
+<svg id="chart" viewBox="0 0 1000 420" preserveAspectRatio="none"></svg>
+<div id="tip"></div>
+<table>
+<tr><th>target</th><th>sent</th><th>recv</th><th>loss</th><th>min</th><th>avg</th><th>max</th>
+<th>p50</th><th>p95</th><th>p99</th><th>stddev</th></tr>
+{rows}</table>
+<script>
+const series = {data};
+const colors = ["#d62728", "#2ca02c", "#1f77b4", "#ff7f0e", "#9467bd", "#17becf",
+                "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22"];
+const hidden = new Set();
+const svg = document.getElementById("chart");
+const tip = document.getElementById("tip");
+const [W, H, PAD] = [1000, 420, 30];
+const all = series.flatMap(s => s.samples);
+// Long sessions have too many samples to spread into Math.min
+const t0 = all.length ? all.reduce((min, s) => Math.min(min, s[0]), Infinity) : 0;
+const t1 = all.reduce((max, s) => Math.max(max, s[0]), t0 + 1);
+const vmax = all.reduce((max, s) => Math.max(max, s[1] || 0), 1) * 1.1;
+const x = t => PAD + (t - t0) / (t1 - t0) * (W - 2 * PAD);
+const y = v => H - PAD - v / vmax * (H - 2 * PAD);
+const ms = us => (us / 1000).toFixed(1) + "ms";
+function draw() {
+  let out = "";
+  for (let i = 0; i <= 4; i++) {
+    const v = vmax * i / 4;
+    out += `<line x1="${PAD}" x2="${W - PAD}" y1="${y(v)}" y2="${y(v)}" stroke="#eee"/>`;
+    out += `<text x="2" y="${y(v) - 2}" font-size="11" fill="#888">${ms(v)}</text>`;
+  }
+  series.forEach((s, i) => {
+    if (hidden.has(i)) return;
+    const color = colors[i % colors.length];
+    // Lines break where probes got no reply, which are marked along the bottom
+    let path = "", gap = true;
+    for (const [t, v] of s.samples) {
+      if (v === null) {
+        out += `<circle cx="${x(t)}" cy="${H - PAD}" r="2" fill="${color}"/>`;
+        gap = true;
+      } else {
+        path += (gap ? "M" : "L") + x(t) + " " + y(v);
+        gap = false;
+      }
+    }
+    out += `<path d="${path}" fill="none" stroke="${color}" stroke-width="1.5"/>`;
+  });
+  svg.innerHTML = out;
+}
+const legend = document.getElementById("legend");
+series.forEach((s, i) => {
+  const item = document.createElement("span");
+  item.textContent = "■ " + s.name;
+  item.style.color = colors[i % colors.length];
+  item.onclick = () => {
+    hidden.has(i) ? hidden.delete(i) : hidden.add(i);
+    item.classList.toggle("hidden");
+    draw();
+  };
+  legend.appendChild(item);
+});
+svg.onmousemove = e => {
+  const box = svg.getBoundingClientRect();
+  const t = t0 + ((e.clientX - box.left) / box.width * W - PAD) / (W - 2 * PAD) * (t1 - t0);
+  const lines = [new Date(t).toLocaleTimeString()];
+  series.forEach((s, i) => {
+    if (hidden.has(i) || !s.samples.length) return;
+    const near = s.samples.reduce((a, b) => Math.abs(b[0] - t) < Math.abs(a[0] - t) ? b : a);
+    lines.push(s.name + ": " + (near[1] === null ? "no reply" : ms(near[1])));
+  });
+  tip.textContent = lines.join("\n");
+  tip.style.display = "block";
+  tip.style.left = e.pageX + 12 + "px";
+  tip.style.top = e.pageY + 12 + "px";
+};
+svg.onmouseleave = () => tip.style.display = "none";
+draw();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod test {
+    use super::{report, Timeline};
+    use crate::summary::Row;
+    use chrono::{Local, TimeZone};
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_report() {
+        let started = Local.with_ymd_and_hms(2021, 3, 1, 10, 0, 0).unwrap();
+        let mut timeline = Timeline::default();
+        timeline.push(0, started, Some(Duration::from_micros(12_500)));
+        timeline.push(0, started, None);
+        let rows = vec![Row {
+            target: "</script><b>".to_string(),
+            sent: 2,
+            received: 1,
+            min: Some(Duration::from_micros(12_500)),
+            avg: Some(Duration::from_micros(12_500)),
+            max: Some(Duration::from_micros(12_500)),
+            p50: Some(Duration::from_micros(12_500)),
+            p95: Some(Duration::from_micros(12_500)),
+            p99: Some(Duration::from_micros(12_500)),
+            stddev: None,
+        }];
+        let page = report(&rows, &timeline, started, Duration::from_secs(61));
+        assert!(page.contains("<title>gping 2021-03-01 10:00:00</title>"));
+        assert!(page.contains("Session of 1m 1s"));
+        assert!(page.contains("<td>&lt;/script&gt;&lt;b&gt;</td><td>2</td><td>1</td><td>50.0%"));
+        assert!(page.contains(&format!(
+            r#"[{{"name":"<\/script><b>","samples":[[{0},12500],[{0},null]]}}]"#,
+            started.timestamp_millis()
+        )));
+        assert_eq!(page.matches("</script>").count(), 1);
+    }
+}
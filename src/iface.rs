@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::process::Command;
+use std::time::Instant;
+
+/// Bytes a network interface has received and sent since it came up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Counters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// Read the byte counters of `iface` from `/sys/class/net` on Linux and
+/// from `netstat -ibn` on macOS and the BSDs.
+pub fn counters(iface: &str) -> Result<Counters> {
+    let counters = if cfg!(target_os = "linux") {
+        let read = |name| -> Result<u64> {
+            let path = format!("/sys/class/net/{}/statistics/{}", iface, name);
+            let value =
+                fs::read_to_string(&path).map_err(|e| anyhow!("Could not read {}: {}", path, e))?;
+            Ok(value.trim().parse()?)
+        };
+        Some(Counters {
+            rx_bytes: read("rx_bytes")?,
+            tx_bytes: read("tx_bytes")?,
+        })
+    } else if cfg!(windows) {
+        return Err(anyhow!("--iface-rate is not supported on Windows"));
+    } else {
+        let output = Command::new("netstat")
+            .args(["-ibn", "-I", iface])
+            .env("LC_ALL", "C")
+            .output()
+            .map_err(|e| anyhow!("Could not run netstat: {}", e))?;
+        parse_netstat(&String::from_utf8_lossy(&output.stdout), iface)
+    };
+    counters.ok_or_else(|| anyhow!("There is no network interface named {}", iface))
+}
+
+/// What `netstat -ibn` prints, like
+/// `en0  1500  <Link#6>  a4:83:e7:1f:2c:9d  182134  0  201348752  90211  0  12093284  0`.
+/// Rows for the interface's addresses repeat its counters, so the first one
+/// is used. Rows without an address have fewer columns, so fields are
+/// counted from the end, where macOS and the BSDs agree.
+fn parse_netstat(output: &str, iface: &str) -> Option<Counters> {
+    let mut lines = output.lines();
+    let header: Vec<_> = lines.next()?.split_whitespace().collect();
+    let from_end = |name| {
+        let idx = header.iter().position(|&column| column == name)?;
+        Some(header.len() - idx)
+    };
+    let (rx, tx) = (from_end("Ibytes")?, from_end("Obytes")?);
+    lines.find_map(|line| {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        if fields.first() != Some(&iface) || fields.len() < rx.max(tx) {
+            return None;
+        }
+        Some(Counters {
+            rx_bytes: fields[fields.len() - rx].parse().ok()?,
+            tx_bytes: fields[fields.len() - tx].parse().ok()?,
+        })
+    })
+}
+
+/// Turns successive readings of an interface's counters into rates.
+#[derive(Default)]
+pub struct Meter {
+    last: Option<(Instant, Counters)>,
+}
+
+impl Meter {
+    /// The received and sent bits per second since the previous reading,
+    /// or None for the first one. Counters that went backwards, because the
+    /// interface was reset, count as no traffic.
+    pub fn rates(&mut self, at: Instant, counters: Counters) -> Option<(f64, f64)> {
+        let last = self.last.replace((at, counters));
+        let (then, before) = last?;
+        let secs = at.duration_since(then).as_secs_f64();
+        if secs == 0.0 {
+            return None;
+        }
+        let rate = |after: u64, before: u64| after.saturating_sub(before) as f64 * 8.0 / secs;
+        Some((
+            rate(counters.rx_bytes, before.rx_bytes),
+            rate(counters.tx_bytes, before.tx_bytes),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_netstat, Counters, Meter};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    pub fn test_parse_netstat() {
+        let output = "\
+Name  Mtu   Network    Address            Ipkts Ierrs    Ibytes Opkts Oerrs   Obytes  Coll
+en0   1500  <Link#6>   a4:83:e7:1f:2c:9d 182134     0 201348752 90211     0 12093284     0
+en0   1500  192.168.1  192.168.1.20      180000     - 200000000 90000     - 12000000     -
+lo0   16384 <Link#1>                       5120     0    901234  5120     0   901234     0
+";
+        assert_eq!(
+            parse_netstat(output, "en0"),
+            Some(Counters {
+                rx_bytes: 201_348_752,
+                tx_bytes: 12_093_284
+            })
+        );
+        assert_eq!(
+            parse_netstat(output, "lo0"),
+            Some(Counters {
+                rx_bytes: 901_234,
+                tx_bytes: 901_234
+            })
+        );
+        assert_eq!(parse_netstat(output, "en1"), None);
+        assert_eq!(parse_netstat("", "en0"), None);
+    }
+
+    #[test]
+    pub fn test_rates() {
+        let mut meter = Meter::default();
+        let start = Instant::now();
+        let counters = |rx_bytes, tx_bytes| Counters { rx_bytes, tx_bytes };
+        assert_eq!(meter.rates(start, counters(1000, 500)), None);
+        assert_eq!(
+            meter.rates(start + Duration::from_secs(2), counters(3000, 750)),
+            Some((8000.0, 1000.0))
+        );
+        assert_eq!(
+            meter.rates(start + Duration::from_secs(3), counters(0, 750)),
+            Some((0.0, 0.0))
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// A jq style path to a value in a JSON document, like `.metrics.latency_ms`
+/// or `.results[0].time`. `.` is the whole document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    text: String,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid JSON path {:?}, expected one like .stats.p99", text);
+        if !text.starts_with('.') {
+            return Err(invalid());
+        }
+        let mut steps = vec![];
+        let mut rest = &text[1..];
+        while !rest.is_empty() {
+            if rest.starts_with('[') {
+                let end = rest.find(']').ok_or_else(invalid)?;
+                steps.push(Step::Index(rest[1..end].parse().map_err(|_| invalid())?));
+                rest = &rest[end + 1..];
+            } else {
+                let rest_of_key = rest.strip_prefix('.').unwrap_or(rest);
+                let end = rest_of_key.find(['.', '[']).unwrap_or(rest_of_key.len());
+                if end == 0 {
+                    return Err(invalid());
+                }
+                steps.push(Step::Key(rest_of_key[..end].to_string()));
+                rest = &rest_of_key[end..];
+            }
+        }
+        Ok(Selector {
+            text: text.to_string(),
+            steps,
+        })
+    }
+}
+
+/// `.`, the whole document.
+impl Default for Selector {
+    fn default() -> Self {
+        Selector {
+            text: ".".to_string(),
+            steps: vec![],
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Selector {
+    /// The number at this path in `document`. Numbers in strings, like
+    /// `"12.5"`, count too.
+    pub fn number(&self, document: &Value) -> Option<f64> {
+        let mut value = document;
+        for step in &self.steps {
+            value = match step {
+                Step::Key(key) => value.get(key)?,
+                Step::Index(index) => value.get(index)?,
+            };
+        }
+        match value {
+            Value::Number(number) => number.as_f64(),
+            Value::String(text) => text.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Selector;
+    use serde_json::json;
+
+    #[test]
+    pub fn test_number() {
+        let document = json!({
+            "metrics": {"latency_ms": 12.5, "p99": "40"},
+            "results": [{"time": 3}],
+            "status": "ok"
+        });
+        let number = |path: &str| path.parse::<Selector>().unwrap().number(&document);
+        assert_eq!(number(".metrics.latency_ms"), Some(12.5));
+        assert_eq!(number(".metrics.p99"), Some(40.0));
+        assert_eq!(number(".results[0].time"), Some(3.0));
+        assert_eq!(number(".results[1].time"), None);
+        assert_eq!(number(".status"), None);
+        assert_eq!(number("."), None);
+        assert_eq!(
+            ".".parse::<Selector>().unwrap().number(&json!(7)),
+            Some(7.0)
+        );
+    }
+
+    #[test]
+    pub fn test_parse() {
+        assert!("metrics.latency_ms".parse::<Selector>().is_err());
+        assert!(".results[x]".parse::<Selector>().is_err());
+        assert!(".results[0".parse::<Selector>().is_err());
+        assert!(".a..b".parse::<Selector>().is_err());
+        assert_eq!(".".parse::<Selector>().unwrap(), Selector::default());
+        assert_eq!(
+            ".results[0].time".parse::<Selector>().unwrap().to_string(),
+            ".results[0].time"
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::{anyhow, Result};
+
+/// What to show each series as, given their names and the `--label`s. A
+/// label like `target:label` renames the series named `target`, and others
+/// rename the remaining series in order, so `--label gateway --label "API
+/// health"` labels the first two.
+pub fn apply(names: &[String], labels: &[String]) -> Result<Vec<String>> {
+    let mut shown: Vec<Option<String>> = vec![None; names.len()];
+    let mut in_order = vec![];
+    for label in labels {
+        // Names can have colons of their own, like host:port or URLs
+        let targeted = label.rfind(':').and_then(|idx| {
+            let host_id = names.iter().position(|name| *name == label[..idx])?;
+            Some((host_id, &label[idx + 1..])).filter(|(_, label)| !label.is_empty())
+        });
+        match targeted {
+            Some((host_id, label)) => shown[host_id] = Some(label.to_string()),
+            None => in_order.push(label),
+        }
+    }
+    let mut unlabelled = shown.iter_mut().filter(|label| label.is_none());
+    for label in in_order {
+        let slot = unlabelled
+            .next()
+            .ok_or_else(|| anyhow!("There's nothing left to label {:?}", label))?;
+        *slot = Some(label.clone());
+    }
+    Ok(shown
+        .into_iter()
+        .zip(names)
+        .map(|(label, name)| label.unwrap_or_else(|| name.clone()))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::apply;
+
+    #[test]
+    pub fn test_apply() {
+        let names: Vec<String> = vec!["192.168.1.1", "example.com:443", "curl -s localhost/health"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let labels: Vec<String> = vec!["example.com:443:web", "gateway", "db: primary"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            apply(&names, &labels).unwrap(),
+            vec!["gateway", "web", "db: primary"]
+        );
+        assert_eq!(apply(&names, &[]).unwrap(), names);
+        assert!(apply(&names[..1], &labels[1..]).is_err());
+    }
+}
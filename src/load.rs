@@ -0,0 +1,195 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::fs;
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// How long CPU usage is measured over when there's no earlier reading.
+const CPU_WINDOW: Duration = Duration::from_millis(250);
+
+/// What `--load` graphs about this machine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// The 1-minute load average
+    Average,
+    /// The percentage of time all CPUs were busy
+    Cpu,
+}
+
+impl FromStr for Metric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "avg" => Ok(Metric::Average),
+            "cpu" => Ok(Metric::Cpu),
+            _ => Err(anyhow!("--load is avg or cpu, not {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Metric::Average => write!(f, "load average"),
+            Metric::Cpu => write!(f, "CPU %"),
+        }
+    }
+}
+
+/// Reads a metric again and again, remembering what's needed to turn CPU
+/// time counters into a percentage.
+pub struct Sampler {
+    metric: Metric,
+    /// Busy and total CPU time at the previous reading
+    last: Option<(u64, u64)>,
+}
+
+impl Sampler {
+    pub fn new(metric: Metric) -> Self {
+        Sampler { metric, last: None }
+    }
+
+    /// Fails on platforms the metric can't be read on.
+    pub fn sample(&mut self) -> Result<f64> {
+        match self.metric {
+            Metric::Average => load_average(),
+            Metric::Cpu if cfg!(target_os = "linux") => {
+                let last = match self.last {
+                    Some(last) => last,
+                    None => {
+                        let first = proc_stat()?;
+                        thread::sleep(CPU_WINDOW);
+                        first
+                    }
+                };
+                let now = proc_stat()?;
+                self.last = Some(now);
+                Ok(busy_percent(last, now))
+            }
+            Metric::Cpu if cfg!(windows) => {
+                let output = run("wmic", &["cpu", "get", "loadpercentage"])?;
+                parse_wmic(&output).ok_or_else(|| anyhow!("wmic did not report CPU usage"))
+            }
+            Metric::Cpu => Err(anyhow!("--load cpu is only supported on Linux and Windows")),
+        }
+    }
+}
+
+fn load_average() -> Result<f64> {
+    let average = if cfg!(target_os = "linux") {
+        let loadavg = fs::read_to_string("/proc/loadavg")
+            .map_err(|e| anyhow!("Could not read /proc/loadavg: {}", e))?;
+        loadavg
+            .split_whitespace()
+            .next()
+            .and_then(|avg| avg.parse().ok())
+    } else if cfg!(windows) {
+        return Err(anyhow!("Windows has no load average, try --load cpu"));
+    } else {
+        parse_sysctl_loadavg(&run("sysctl", &["-n", "vm.loadavg"])?)
+    };
+    average.ok_or_else(|| anyhow!("Could not read the load average"))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| anyhow!("Could not run {}: {}", program, e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Busy and total CPU time from Linux's `/proc/stat`, whose first line is
+/// like `cpu  4705 150 1120 16250 520 0 30 0 0 0` in clock ticks.
+fn proc_stat() -> Result<(u64, u64)> {
+    let stat = fs::read_to_string("/proc/stat")
+        .map_err(|e| anyhow!("Could not read /proc/stat: {}", e))?;
+    parse_proc_stat(&stat).ok_or_else(|| anyhow!("Could not parse /proc/stat"))
+}
+
+fn parse_proc_stat(stat: &str) -> Option<(u64, u64)> {
+    let line = stat.lines().find(|line| line.starts_with("cpu "))?;
+    let ticks: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .map(|ticks| ticks.parse().ok())
+        .collect::<Option<_>>()?;
+    if ticks.len() < 4 {
+        return None;
+    }
+    // Guest time is already counted in user time
+    let total: u64 = ticks.iter().take(8).sum();
+    let idle = ticks[3] + ticks.get(4).unwrap_or(&0);
+    Some((total - idle, total))
+}
+
+fn busy_percent((busy_before, total_before): (u64, u64), (busy, total): (u64, u64)) -> f64 {
+    let total = total.saturating_sub(total_before);
+    if total == 0 {
+        return 0.0;
+    }
+    busy.saturating_sub(busy_before) as f64 * 100.0 / total as f64
+}
+
+/// What macOS' and the BSDs' `sysctl -n vm.loadavg` print, like
+/// `{ 1.52 1.61 1.70 }`.
+fn parse_sysctl_loadavg(output: &str) -> Option<f64> {
+    output
+        .split_whitespace()
+        .find(|field| *field != "{")
+        .and_then(|avg| avg.parse().ok())
+}
+
+/// What `wmic cpu get loadpercentage` prints, a header and then a line per
+/// CPU socket, which are averaged.
+fn parse_wmic(output: &str) -> Option<f64> {
+    let loads: Vec<f64> = output
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.trim().parse().ok())
+        .collect();
+    if loads.is_empty() {
+        return None;
+    }
+    Some(loads.iter().sum::<f64>() / loads.len() as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{busy_percent, parse_proc_stat, parse_sysctl_loadavg, parse_wmic, Metric};
+
+    #[test]
+    pub fn test_parse_proc_stat() {
+        let stat = "\
+cpu  4705 150 1120 16250 520 0 30 0 0 0
+cpu0 2350 75 560 8125 260 0 15 0 0 0
+intr 123456 0 0
+";
+        assert_eq!(parse_proc_stat(stat), Some((6005, 22775)));
+        assert_eq!(parse_proc_stat("intr 1 2 3"), None);
+        assert_eq!(busy_percent((6005, 22775), (6105, 22975)), 50.0);
+        assert_eq!(busy_percent((6005, 22775), (6005, 22775)), 0.0);
+    }
+
+    #[test]
+    pub fn test_parse_platform_output() {
+        assert_eq!(parse_sysctl_loadavg("{ 1.52 1.61 1.70 }\n"), Some(1.52));
+        assert_eq!(parse_sysctl_loadavg(""), None);
+        assert_eq!(
+            parse_wmic("LoadPercentage  \r\r\n12  \r\r\n30  \r\r\n\r\r\n"),
+            Some(21.0)
+        );
+        assert_eq!(parse_wmic("LoadPercentage\n"), None);
+    }
+
+    #[test]
+    pub fn test_metric() {
+        assert_eq!("cpu".parse::<Metric>().unwrap(), Metric::Cpu);
+        assert_eq!("avg".parse::<Metric>().unwrap(), Metric::Average);
+        assert!("mem".parse::<Metric>().is_err());
+    }
+}
@@ -1,43 +1,255 @@
+mod alert;
+mod aliases;
+mod asciicast;
+mod bufferbloat;
+mod clock;
+mod compare;
+mod config;
+mod control;
+mod dashboard;
+mod duration;
+mod export;
+mod feed;
+mod gateway;
+mod html;
+mod iface;
+mod jsonpath;
+mod labels;
+mod load;
+mod poll;
+mod probe;
+mod prom;
+mod record;
+mod replay;
+mod resolve;
 mod ringbuffer;
+mod samplelog;
+mod schedule;
+mod sinks;
+mod source;
+mod summary;
+mod tail;
+mod throughput;
+mod watch;
 
-use anyhow::{Result, anyhow};
+use anyhow::{anyhow, Result};
+use chrono::Local;
 use crossterm::event::{KeyEvent, KeyModifiers};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use dns_lookup::lookup_host;
 use histogram::Histogram;
-use pinger::{ping, PingResult};
+use probe::{ping, PingResult};
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::io;
 use std::io::Write;
 use std::iter;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr};
 use std::ops::Add;
-use std::process::Command;
-use std::sync::atomic::Ordering;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 use structopt::StructOpt;
 use tui::backend::CrosstermBackend;
-use tui::layout::{Constraint, Direction, Layout};
-use tui::style::{Color, Style};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
 use tui::text::Span;
 use tui::widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph};
-use tui::{symbols, Terminal};
+use tui::{symbols, Frame, Terminal};
+
+/// Longest wait between attempts to resolve a host that didn't resolve.
+const MAX_RESOLVE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Number of entries kept in the event log panel.
+const MAX_EVENTS: usize = 100;
+
+/// How often a ping thread waiting on a quiet source checks whether to stop.
+const KILL_CHECK: Duration = Duration::from_millis(100);
+
+/// How long the 's' key silences alerts for when `--silence` isn't given.
+const DEFAULT_SILENCE: Duration = Duration::from_secs(60 * 60);
+
+/// How much of a watched command's output is kept when `--watch-output-limit`
+/// isn't given.
+const WATCH_OUTPUT_LIMIT: usize = 64 * 1024;
+
+/// How far the 'j' key skips through a replayed session.
+const REPLAY_JUMP: Duration = Duration::from_secs(60);
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(
+        about = "Chart two recordings from --record or --log lined up from their starts, \
+                 then print how each target changed from one to the other"
+    )]
+    Compare {
+        #[structopt(parse(from_os_str))]
+        before: PathBuf,
+        #[structopt(parse(from_os_str))]
+        after: PathBuf,
+    },
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "gping", about = "Ping, but with a graph.")]
 struct Args {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Command to run, graphing the executing time. Repeat to run several at once"
+    )]
+    watch: Vec<String>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Show a target as this instead, in the header, logs and alerts. Given as \
+                target:label, or just label to name targets in the order they're given"
+    )]
+    label: Vec<String>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Append every sample to this CSV file, \
+                as timestamp,target,seq,latency_us,status"
+    )]
+    log: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Move the --log aside and start another once it reaches a size, like 100M, \
+                or has been written to for a time, like 1d",
+        requires("log")
+    )]
+    log_rotate: Option<samplelog::Limit>,
+    #[structopt(
+        long,
+        default_value = "10",
+        help = "How many logs moved aside by --log-rotate to keep, deleting older ones"
+    )]
+    log_keep: usize,
+    #[structopt(
+        long,
+        help = "Run without drawing the chart, like a daemon, until interrupted or ended by \
+                --duration or --count. Needed for the [schedule] of --config"
+    )]
+    headless: bool,
+    #[structopt(
+        long,
+        possible_values = &["jsonl"],
+        help = "Stream every sample as JSON lines to standard output instead of drawing \
+                the chart, or to --output-file alongside it"
+    )]
+    output: Option<samplelog::Output>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "File to append --output to",
+        requires("output")
+    )]
+    output_file: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Write the statistics of the session as JSON to this file when gping exits, \
+                or to standard output given -"
+    )]
+    report_json: Option<String>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Record every sample to this SQLite database, adding a session to it"
+    )]
+    record: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Play back the latest session recorded to this SQLite database with --record, \
+                or a CSV file written with --log. Space pauses, + and - change the speed and \
+                j jumps a minute ahead",
+        conflicts_with_all(&["hosts", "watch"])
+    )]
+    replay: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Save the chart as an image when gping exits, as SVG or PNG by the file's \
+                extension. The x key saves one at any time"
+    )]
+    export_on_exit: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Write a page with a chart of the whole session and its statistics to this \
+                HTML file when gping exits"
+    )]
+    html_report: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Record the session as drawn to this asciicast file, to play with asciinema. \
+                Works with --replay to turn a recorded session into one"
+    )]
+    asciicast: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Graph numbers read from standard input, one per line as value or label,value, \
+                as milliseconds. Each label gets its own line"
+    )]
+    stdin: bool,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Graph numbers written to this named pipe, like --stdin, opening it again \
+                whenever the writer closes it. Unlabelled numbers are graphed as the path"
+    )]
+    pipe: Vec<PathBuf>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Follow a file like tail -f, graphing the milliseconds in --column of each \
+                row appended to it. Rows are split on commas, or whitespace if they have none"
+    )]
+    tail: Vec<PathBuf>,
     #[structopt(
         long,
-        help = "Command to run, graphing the executing time",
-        conflicts_with("hosts")
+        number_of_values = 1,
+        parse(try_from_str = prom::parse_target),
+        help = "Graph a Prometheus query every interval, given as http://server:9090|query. \
+                Its value is taken as seconds"
     )]
-    watch: Option<String>,
+    prom: Vec<(String, String)>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Fetch JSON from this URL every interval and graph the milliseconds at --path"
+    )]
+    json_poll: Vec<String>,
+    #[structopt(
+        long,
+        help = "Where the number is in --json-poll documents, like .metrics.latency_ms. \
+                The whole document unless given",
+        requires("json-poll")
+    )]
+    path: Option<jsonpath::Selector>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        possible_values = &["avg", "cpu"],
+        help = "Graph how loaded this machine is every interval: the 1-minute load average \
+                or CPU %, drawn as that many milliseconds"
+    )]
+    load: Vec<load::Metric>,
+    #[structopt(
+        long,
+        help = "Which column of --tail files to graph, counting from 1. The first unless given",
+        requires("tail")
+    )]
+    column: Option<usize>,
     #[structopt(
         short = "n",
         long,
@@ -45,8 +257,173 @@ struct Args {
         default_value = "2"
     )]
     watch_interval: f32,
-    #[structopt(help = "Hosts or IPs to ping", required_if("watch", ""))]
+    #[structopt(
+        long,
+        help = "Stop watching after the command fails this many times in a row",
+        requires("watch")
+    )]
+    watch_max_failures: Option<u64>,
+    #[structopt(
+        long,
+        help = "Stop as soon as a watched command fails, and exit with its exit code \
+                after printing stats",
+        requires("watch")
+    )]
+    watch_fail_exit: bool,
+    #[structopt(
+        long,
+        help = "Annotate the chart whenever the command's output changes",
+        requires("watch")
+    )]
+    watch_diff: bool,
+    #[structopt(
+        long,
+        help = "Command to run whenever the watched command's output changes",
+        requires("watch-diff")
+    )]
+    watch_diff_hook: Option<String>,
+    #[structopt(
+        long,
+        help = "Maximum number of bytes of output to capture from each command run, \
+                64KiB unless given",
+        requires("watch")
+    )]
+    watch_output_limit: Option<usize>,
+    #[structopt(
+        long,
+        parse(try_from_str = watch::parse_concurrency),
+        help = "How many watched commands may run at once: sequential, parallel, or a \
+                limit like 2. Parallel unless given",
+        requires("watch")
+    )]
+    watch_concurrency: Option<usize>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Directory to run watched commands in",
+        requires("watch")
+    )]
+    watch_dir: Option<PathBuf>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        parse(try_from_str = watch::parse_env),
+        help = "Set an environment variable for watched commands, as KEY=value",
+        requires("watch")
+    )]
+    watch_env: Vec<(String, String)>,
+    #[structopt(
+        long,
+        parse(try_from_str = duration::parse),
+        help = "Kill a watched command that's still running after this long, \
+                and count the run as a timeout",
+        requires("watch")
+    )]
+    watch_timeout: Option<Duration>,
+    #[structopt(
+        long,
+        help = "Graph the number the last line of the command's output starts with, \
+                as milliseconds, instead of how long the command takes",
+        requires("watch")
+    )]
+    watch_output: bool,
+    #[structopt(
+        long,
+        help = "Show the last line each watched command printed in its header",
+        requires("watch")
+    )]
+    watch_show_output: bool,
+    #[structopt(
+        long,
+        help = "Run watched commands through sh -c, or cmd /C on Windows, \
+                so pipes and quotes work",
+        requires("watch")
+    )]
+    shell: bool,
+    #[structopt(
+        long,
+        parse(try_from_str = watch::parse_extract),
+        help = "Graph the number this regex's one capture group finds in the command's \
+                output, as milliseconds, like 'time=([0-9.]+)'",
+        requires("watch"),
+        conflicts_with("watch-output")
+    )]
+    extract: Option<regex::Regex>,
+    #[structopt(
+        long,
+        help = "Parse the command's output as JSON and graph the number at this path, \
+                as milliseconds, like .stats.p99",
+        requires("watch"),
+        conflicts_with_all(&["watch-output", "extract"])
+    )]
+    extract_json: Option<jsonpath::Selector>,
+    #[structopt(
+        help = "Hosts or IPs to ping, or http:// and https:// URLs to time requests for. \
+                Add @interval to ping one at its own pace, like 10.0.0.1@200ms"
+    )]
     hosts: Vec<String>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        parse(try_from_str = resolve::split_port),
+        help = "Graph the TCP handshake time to host:port, e.g. example.com:443, \
+                for networks that filter ICMP"
+    )]
+    tcp: Vec<(String, u16)>,
+    #[structopt(
+        long,
+        requires = "tcp",
+        parse(try_from_str = probe::Proxy::parse),
+        help = "Also graph each --tcp target through this SOCKS5 or HTTP proxy, like \
+                socks5://127.0.0.1:1080, to compare with connecting directly"
+    )]
+    proxy: Option<probe::Proxy>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        parse(try_from_str = resolve::split_port),
+        help = "Graph the round trip of a datagram to host:port, answered by a UDP echo \
+                service or by the ICMP error from a closed port"
+    )]
+    udp: Vec<(String, u16)>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        parse(try_from_str = resolve::parse_query),
+        help = "Graph how long name@server takes to look up, e.g. example.com@1.1.1.1, \
+                to compare resolvers. The server may be tls:// or https:// too"
+    )]
+    dns: Vec<(String, resolve::Server)>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        parse(try_from_str = resolve::split_port),
+        help = "Graph just the TLS handshake time to host:port, e.g. example.com:443, \
+                leaving out the TCP connect before it"
+    )]
+    tls: Vec<(String, u16)>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        parse(try_from_str = resolve::split_port),
+        help = "Graph the QUIC handshake time to host:port, e.g. example.com:443, to compare \
+                HTTP/3 with --tls or a URL to the same host"
+    )]
+    quic: Vec<(String, u16)>,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Graph the ARP reply time of a device on the local network, for ones that \
+                drop ICMP. Runs arping, which needs root"
+    )]
+    arp: Vec<Ipv4Addr>,
+    #[structopt(
+        long,
+        help = "Also ping the gateway of the default route, to tell problems on the local \
+                network from ones further out",
+        conflicts_with("via")
+    )]
+    gateway: bool,
     #[structopt(
         short,
         long,
@@ -54,45 +431,531 @@ struct Args {
         help = "Determines the number pings to display."
     )]
     buffer: usize,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Ring the bell when a rule such as 'latency>100ms for 30s' is breached, in a \
+                rhythm of two groups of rings that tells the host: 1 and 2 for the second"
+    )]
+    alert: Vec<alert::Rule>,
+    #[structopt(long, help = "Send a desktop notification when a host goes down or up")]
+    notify: bool,
+    #[structopt(
+        long,
+        help = "POST a JSON payload to this URL on alerts, outages and recoveries"
+    )]
+    webhook: Option<String>,
+    #[structopt(
+        long,
+        help = "Shell command to run when a host stops replying, with $GPING_HOST set"
+    )]
+    on_down: Option<String>,
+    #[structopt(
+        long,
+        help = "Shell command to run when a host starts replying again, with $GPING_HOST set"
+    )]
+    on_up: Option<String>,
+    #[structopt(
+        long,
+        parse(try_from_str = duration::parse),
+        help = "Suppress alert notifications for this long, e.g. '30m'. Press 's' to toggle"
+    )]
+    silence: Option<Duration>,
+    #[structopt(
+        long,
+        parse(try_from_str = resolve::parse_server),
+        help = "DNS server to resolve hosts with instead of the system resolver. \
+                Use tls://<server> or https://<server> for encrypted DNS"
+    )]
+    resolver: Option<resolve::Server>,
+    #[structopt(
+        long = "resolve",
+        number_of_values = 1,
+        parse(try_from_str = resolve::parse_override),
+        help = "Pin a hostname to an address like curl's --resolve, e.g. example.com:192.0.2.1"
+    )]
+    resolve_overrides: Vec<(String, String)>,
+    #[structopt(
+        long,
+        parse(try_from_str = duration::parse),
+        help = "Re-resolve hostnames this often, e.g. '5m', following any address change"
+    )]
+    resolve_interval: Option<Duration>,
+    #[structopt(
+        long,
+        help = "Ping every address a hostname resolves to, each as its own series",
+        conflicts_with("resolve-interval")
+    )]
+    all_ips: bool,
+    #[structopt(
+        long,
+        help = "Ping the IPv4 and IPv6 address of dual-stack hosts once each and keep the faster"
+    )]
+    happy_eyeballs: bool,
+    #[structopt(
+        long,
+        help = "Graph both the IPv4 and IPv6 address of dual-stack hosts",
+        conflicts_with("resolve-interval")
+    )]
+    both_families: bool,
+    #[structopt(
+        short = "4",
+        long,
+        help = "Only resolve and ping IPv4 addresses",
+        conflicts_with_all(&["ipv6", "happy-eyeballs", "both-families"])
+    )]
+    ipv4: bool,
+    #[structopt(
+        short = "6",
+        long,
+        help = "Only resolve and ping IPv6 addresses",
+        conflicts_with_all(&["happy-eyeballs", "both-families"])
+    )]
+    ipv6: bool,
+    #[structopt(
+        long,
+        help = "Accept Host aliases from ~/.ssh/config, matching partial names"
+    )]
+    from_ssh_config: bool,
+    #[structopt(
+        long,
+        help = "Accept names from the hosts file, matching partial names"
+    )]
+    from_hosts: bool,
+    #[structopt(
+        long,
+        help = "Show how long the latest DNS resolution of each host took"
+    )]
+    dns_time: bool,
+    #[structopt(long, help = "Show the PTR name of hosts given as IP addresses")]
+    reverse_dns: bool,
+    #[structopt(
+        long,
+        help = "Draw with dots and 16 colors, for consoles lacking braille fonts. \
+                Detected automatically on the old Windows console"
+    )]
+    legacy_console: bool,
+    #[structopt(
+        long,
+        help = "Periodically download the start of this URL and graph the goodput"
+    )]
+    throughput: Option<String>,
+    #[structopt(
+        long,
+        default_value = "10s",
+        parse(try_from_str = duration::parse),
+        help = "How often to measure throughput"
+    )]
+    throughput_interval: Duration,
+    #[structopt(
+        long,
+        default_value = "102400",
+        help = "Bytes to download per measurement"
+    )]
+    throughput_size: u64,
+    #[structopt(
+        long,
+        requires("throughput"),
+        help = "Alternate idle phases with phases of back to back throughput downloads, \
+                and grade how much latency grows under load"
+    )]
+    bufferbloat: bool,
+    #[structopt(
+        long,
+        default_value = "15s",
+        parse(try_from_str = duration::parse),
+        help = "How long each idle and load phase of --bufferbloat lasts"
+    )]
+    bufferbloat_phase: Duration,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Graph how fast this network interface receives and sends, like eth0, \
+                under the latency chart"
+    )]
+    iface_rate: Vec<String>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Listen on this Unix socket for commands: annotate <text>, add-host <host>, \
+                pause and resume"
+    )]
+    control: Option<PathBuf>,
+    #[structopt(
+        short,
+        long,
+        default_value = "1",
+        parse(try_from_str = parse_interval),
+        help = "Seconds between pings of each host, e.g. 0.2. Windows always pings once a second"
+    )]
+    interval: Duration,
+    #[structopt(
+        long,
+        parse(try_from_str = duration::parse),
+        help = "Seconds to wait for each reply before counting it as a timeout, \
+                e.g. 3 for slow links. Defaults to the system ping's behaviour"
+    )]
+    timeout: Option<Duration>,
+    #[structopt(
+        short = "I",
+        long,
+        help = "Send pings out of this network interface, e.g. wlan0. Linux and macOS only"
+    )]
+    interface: Option<String>,
+    #[structopt(
+        long,
+        help = "Send pings from this local address",
+        conflicts_with("interface")
+    )]
+    source: Option<IpAddr>,
+    #[structopt(long, help = "Time to live, the number of hops pings may take")]
+    ttl: Option<u8>,
+    #[structopt(
+        long,
+        help = "Type of service byte to mark pings with, e.g. 184 for EF"
+    )]
+    tos: Option<u8>,
+    #[structopt(
+        long,
+        parse(try_from_str = probe::parse_dscp),
+        help = "DSCP class to mark pings with, by name like EF or AF41, or number",
+        conflicts_with("tos")
+    )]
+    dscp: Option<u8>,
+    #[structopt(
+        long,
+        help = "Also try a raw ICMP socket, which needs root, before the unprivileged \
+                ICMP socket and ping",
+        conflicts_with_all(&["interface", "tos", "dscp", "ping-args"])
+    )]
+    native: bool,
+    #[structopt(
+        long,
+        allow_hyphen_values = true,
+        help = "Extra arguments for the ping command, like \"-M do\". Skips the ICMP sockets"
+    )]
+    ping_args: Option<String>,
+    #[structopt(
+        long,
+        help = "Run ping on another machine over SSH, like ssh://user@jumphost, and graph \
+                the results here. Hosts are resolved there too",
+        conflicts_with_all(&[
+            "native",
+            "resolve-interval",
+            "all-ips",
+            "happy-eyeballs",
+            "both-families"
+        ])
+    )]
+    via: Option<String>,
+    #[structopt(
+        short = "c",
+        long,
+        help = "Stop after this many pings of each host and print a summary"
+    )]
+    count: Option<u64>,
+    #[structopt(
+        long,
+        parse(try_from_str = duration::parse),
+        help = "Stop after this long, like 5m or 1h, and print a summary"
+    )]
+    duration: Option<Duration>,
+    #[structopt(short = "s", long, help = "Bytes of data to send in each ping")]
+    size: Option<u16>,
+    #[structopt(
+        long,
+        help = "Don't let pings be fragmented, to find the largest --size a path carries"
+    )]
+    df: bool,
+    #[structopt(
+        long,
+        help = "Send this many pings in quick succession every interval and chart their \
+                median, with the fastest and slowest as dots",
+        conflicts_with("fast")
+    )]
+    burst: Option<u32>,
+    #[structopt(
+        long,
+        help = "Ping every --fast-interval for the first --fast-for, then slow down to normal"
+    )]
+    fast: bool,
+    #[structopt(
+        long,
+        default_value = "50ms",
+        parse(try_from_str = duration::parse),
+        help = "Interval between pings in --fast mode, at least 10ms"
+    )]
+    fast_interval: Duration,
+    #[structopt(
+        long,
+        default_value = "30s",
+        parse(try_from_str = duration::parse),
+        help = "How long --fast mode lasts, at most 10m"
+    )]
+    fast_for: Duration,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Read additional settings from a TOML file: alert rules and where to send \
+                them, a [dashboard] of panes, and a [schedule] of windows to probe in with \
+                --headless"
+    )]
+    config: Option<PathBuf>,
 }
 
 struct App {
     styles: Vec<Style>,
+    legacy_console: bool,
+    marker: symbols::Marker,
     data: Vec<ringbuffer::FixedRingBuffer<(f64, f64)>>,
     capacity: usize,
     idx: Vec<i64>,
+    /// Where on the x axis probes failed with an ICMP error, and why
+    failures: Vec<Vec<(f64, probe::Failure)>>,
+    /// The fastest and slowest reply of each `--burst`
+    band_min: Vec<ringbuffer::FixedRingBuffer<(f64, f64)>>,
+    band_max: Vec<ringbuffer::FixedRingBuffer<(f64, f64)>>,
+    /// How far along the x axis each sample moves a host, more than 1 for hosts
+    /// pinged less often than `--interval`
+    steps: Vec<f64>,
     window_min: Vec<f64>,
     window_max: Vec<f64>,
-    map_host_ip: HashMap<String, String>,
+    host_ips: Vec<Option<String>>,
+    reverse_names: Vec<Option<String>>,
+    resolve_times: Vec<Option<Duration>>,
+    transports: Vec<Option<probe::Mechanism>>,
+    /// The status of the latest response from URL targets
+    statuses: Vec<Option<u16>>,
+    /// The last line watched commands printed, with `--watch-show-output`
+    outputs: Vec<Option<String>>,
+    notes: Vec<Option<String>>,
+    annotations: Vec<(f64, String)>,
+    events: VecDeque<String>,
+    goodput: ringbuffer::FixedRingBuffer<(f64, f64)>,
+    /// Received and sent bits per second of each `--iface-rate` interface
+    iface_rates: Vec<[ringbuffer::FixedRingBuffer<(f64, f64)>; 2]>,
+    totals: Vec<Totals>,
+}
+
+/// Counts over the whole session, where `App::data` only keeps what's on screen.
+struct Totals {
+    sent: u64,
+    replies: Histogram,
+}
+
+impl Totals {
+    fn new() -> Self {
+        Totals {
+            sent: 0,
+            replies: Histogram::new(),
+        }
+    }
+
+    fn record(&mut self, sample: Option<Duration>) {
+        self.sent += 1;
+        if let Some(rtt) = sample {
+            self.replies.increment(rtt.as_micros() as u64).unwrap_or(());
+        }
+    }
+
+    fn row(&self, target: &str) -> summary::Row {
+        summary::Row::new(target, self.sent, &self.replies)
+    }
 }
 
 impl App {
-    fn new(thread_count: usize, capacity: usize) -> Self {
+    fn new(thread_count: usize, capacity: usize, legacy_console: bool) -> Self {
         App {
             styles: (0..thread_count)
-                .map(|i| Style::default().fg(Color::Indexed(i as u8 + 1)))
+                .map(|i| host_style(i, legacy_console))
                 .collect(),
+            legacy_console,
+            marker: if legacy_console {
+                symbols::Marker::Dot
+            } else {
+                symbols::Marker::Braille
+            },
             data: (0..thread_count)
                 .map(|_| ringbuffer::FixedRingBuffer::new(capacity))
                 .collect(),
             capacity,
             idx: vec![0; thread_count],
+            failures: vec![vec![]; thread_count],
+            band_min: (0..thread_count)
+                .map(|_| ringbuffer::FixedRingBuffer::new(capacity))
+                .collect(),
+            band_max: (0..thread_count)
+                .map(|_| ringbuffer::FixedRingBuffer::new(capacity))
+                .collect(),
+            steps: vec![1.0; thread_count],
             window_min: vec![0.0; thread_count],
             window_max: vec![capacity as f64; thread_count],
-            map_host_ip: HashMap::new(),
+            host_ips: vec![],
+            reverse_names: vec![None; thread_count],
+            resolve_times: vec![],
+            transports: vec![None; thread_count],
+            statuses: vec![None; thread_count],
+            outputs: vec![None; thread_count],
+            notes: vec![],
+            annotations: vec![],
+            events: VecDeque::new(),
+            goodput: ringbuffer::FixedRingBuffer::new(capacity),
+            iface_rates: vec![],
+            totals: (0..thread_count).map(|_| Totals::new()).collect(),
         }
     }
+    /// Make room for another host, starting it level with the others on the chart.
+    fn add_host(&mut self) {
+        let host_id = self.data.len();
+        self.styles.push(host_style(host_id, self.legacy_console));
+        self.data
+            .push(ringbuffer::FixedRingBuffer::new(self.capacity));
+        self.idx.push(self.max_x().ceil() as i64);
+        self.steps.push(1.0);
+        self.failures.push(vec![]);
+        self.band_min
+            .push(ringbuffer::FixedRingBuffer::new(self.capacity));
+        self.band_max
+            .push(ringbuffer::FixedRingBuffer::new(self.capacity));
+        let [window_min, window_max] = self.x_axis_bounds();
+        self.window_min.push(window_min);
+        self.window_max.push(window_max);
+        self.host_ips.push(None);
+        self.reverse_names.push(None);
+        self.resolve_times.push(None);
+        self.transports.push(None);
+        self.statuses.push(None);
+        self.outputs.push(None);
+        self.notes.push(None);
+        self.totals.push(Totals::new());
+    }
+    /// Ping a host every `step` intervals, keeping enough samples to fill the
+    /// same span of the chart as the others.
+    fn set_step(&mut self, host_id: usize, step: f64) {
+        self.steps[host_id] = step;
+        self.data[host_id] = ringbuffer::FixedRingBuffer::new(self.samples(host_id));
+        self.failures[host_id].clear();
+        self.band_min[host_id] = ringbuffer::FixedRingBuffer::new(self.samples(host_id));
+        self.band_max[host_id] = ringbuffer::FixedRingBuffer::new(self.samples(host_id));
+    }
+    /// Mark the latest update as having failed with an ICMP error.
+    fn record_failure(&mut self, host_id: usize, failure: probe::Failure) {
+        let x = self.x(host_id);
+        let min_x = self.window_min[host_id];
+        let failures = &mut self.failures[host_id];
+        failures.retain(|(failure_x, _)| *failure_x >= min_x);
+        failures.push((x, failure));
+    }
+    /// How many probes on screen failed with each ICMP error, leaving out
+    /// errors that haven't happened.
+    fn failure_counts(&self, host_id: usize) -> Vec<(probe::Failure, usize)> {
+        let min_x = self.window_min[host_id];
+        FAILURES
+            .iter()
+            .map(|&failure| {
+                let count = self.failures[host_id]
+                    .iter()
+                    .filter(|&&(x, f)| x >= min_x && f == failure)
+                    .count();
+                (failure, count)
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+    /// Points along the bottom of the chart where probes failed with `failure`.
+    fn failure_data(
+        &self,
+        host_id: usize,
+        failure: probe::Failure,
+        bounds: [f64; 2],
+    ) -> Vec<(f64, f64)> {
+        self.failures[host_id]
+            .iter()
+            .filter(|&&(_, f)| f == failure)
+            .map(|&(x, _)| (x, bounds[0]))
+            .collect()
+    }
+    /// Record the spread of the burst whose median is the next update.
+    fn record_band(&mut self, host_id: usize, min: Duration, max: Duration) {
+        let x = (self.idx[host_id] + 1) as f64 * self.steps[host_id];
+        self.band_min[host_id].push((x, min.as_micros() as f64));
+        self.band_max[host_id].push((x, max.as_micros() as f64));
+    }
+    fn samples(&self, host_id: usize) -> usize {
+        ((self.capacity as f64 / self.steps[host_id]).ceil() as usize).max(1)
+    }
+    fn x(&self, host_id: usize) -> f64 {
+        self.idx[host_id] as f64 * self.steps[host_id]
+    }
+    fn max_x(&self) -> f64 {
+        (0..self.idx.len())
+            .map(|host_id| self.x(host_id))
+            .fold(0f64, f64::max)
+    }
     fn update(&mut self, host_id: usize, item: Option<Duration>) {
         self.idx[host_id] += 1;
+        self.totals[host_id].record(item);
+        let (x, step, samples) = (self.x(host_id), self.steps[host_id], self.samples(host_id));
         let data = &mut self.data[host_id];
-        if data.len() >= self.capacity {
-            self.window_min[host_id] += 1_f64;
-            self.window_max[host_id] += 1_f64;
+        if data.len() >= samples {
+            self.window_min[host_id] += step;
+            self.window_max[host_id] += step;
         }
         match item {
-            Some(dur) => data.push((self.idx[host_id] as f64, dur.as_micros() as f64)),
-            None => data.push((self.idx[host_id] as f64, 0_f64)),
+            Some(dur) => data.push((x, dur.as_micros() as f64)),
+            None => data.push((x, 0_f64)),
+        }
+    }
+    fn annotate(&mut self, host_id: usize, label: String) {
+        self.annotate_at(self.x(host_id), label);
+    }
+    /// Mark the chart for every host at once, where the newest sample is.
+    fn annotate_all(&mut self, label: String) {
+        self.annotate_at(self.max_x(), label);
+    }
+    fn annotate_at(&mut self, x: f64, label: String) {
+        let min_x = self.x_axis_bounds()[0];
+        self.annotations
+            .retain(|(annotation_x, _)| *annotation_x >= min_x);
+        self.annotations.push((x, label));
+    }
+    fn log_event(&mut self, message: String) {
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events
+            .push_back(format!("{} {}", Local::now().format("%H:%M:%S"), message));
+    }
+    fn record_goodput(&mut self, bits_per_sec: f64) {
+        let x = self.max_x();
+        self.goodput.push((x, bits_per_sec));
+    }
+    fn record_iface_rate(&mut self, iface_id: usize, rx: f64, tx: f64) {
+        let x = self.max_x();
+        while self.iface_rates.len() <= iface_id {
+            self.iface_rates.push([
+                ringbuffer::FixedRingBuffer::new(self.capacity),
+                ringbuffer::FixedRingBuffer::new(self.capacity),
+            ]);
         }
+        self.iface_rates[iface_id][0].push((x, rx));
+        self.iface_rates[iface_id][1].push((x, tx));
+    }
+    fn goodput_bounds(&self) -> [f64; 2] {
+        let max = self
+            .iface_rates
+            .iter()
+            .flatten()
+            .chain(std::iter::once(&self.goodput))
+            .flat_map(|rates| rates.iter())
+            .fold(0f64, |a, &(_, b)| a.max(b));
+        [0f64, if max > 0f64 { max * 1.1 } else { 1f64 }]
+    }
+    fn annotation_data(&self, bounds: [f64; 2]) -> Vec<[(f64, f64); 2]> {
+        self.annotations
+            .iter()
+            .map(|(x, _)| [(*x, bounds[0]), (*x, bounds[1])])
+            .collect()
     }
     fn stats(&self) -> Vec<Histogram> {
         self.data
@@ -114,21 +977,45 @@ impl App {
             self.window_max.iter().fold(0f64, |a, &b| a.max(b)),
         ]
     }
-    fn y_axis_bounds(&self) -> [f64; 2] {
-        let iter = self
-            .data
+    fn y_axis_bounds(&self, host_ids: &[usize]) -> [f64; 2] {
+        let iter = host_ids
             .iter()
-            .map(|b| b.as_slice())
-            .flatten()
+            .flat_map(|&host_id| {
+                self.data[host_id]
+                    .iter()
+                    .chain(self.band_min[host_id].iter())
+                    .chain(self.band_max[host_id].iter())
+            })
             .map(|v| v.1);
         let min = iter.clone().fold(f64::INFINITY, |a, b| a.min(b));
         let max = iter.fold(0f64, |a, b| a.max(b));
+        if min > max {
+            // Nothing has been recorded yet
+            return [0f64, 1f64];
+        }
         // Add a 10% buffer to the top and bottom
         let max_10_percent = (max * 10_f64) / 100_f64;
         let min_10_percent = (min * 10_f64) / 100_f64;
         [min - min_10_percent, max + max_10_percent]
     }
-    fn y_axis_labels(&self, bounds: [f64; 2]) -> Vec<Span> {
+    /// Every host's line as it is on screen, to save as an image.
+    fn export_chart(&self, hosts: &[String]) -> export::Chart {
+        let host_ids: Vec<_> = (0..hosts.len()).collect();
+        export::Chart {
+            title: format!("gping {}", Local::now().format("%Y-%m-%d %H:%M:%S")),
+            series: host_ids
+                .iter()
+                .map(|&host_id| export::Series {
+                    name: hosts[host_id].clone(),
+                    color: self.styles[host_id].fg.unwrap_or(Color::Reset),
+                    points: self.data[host_id].as_slice().to_vec(),
+                })
+                .collect(),
+            x_bounds: self.x_axis_bounds(),
+            y_bounds: self.y_axis_bounds(&host_ids),
+        }
+    }
+    fn y_axis_labels(&self, bounds: [f64; 2]) -> Vec<Span<'_>> {
         // Split into 5 sections
         let min = bounds[0];
         let max = bounds[1];
@@ -141,15 +1028,849 @@ impl App {
             .map(|i| Span::raw(format!("{:?}", duration.add(increment * i))))
             .collect()
     }
-    fn get_hosts_ipaddr(&mut self, hosts: &Vec<String>) -> Result<()> {
-        for (_host_id, host) in hosts.iter().cloned().enumerate() {
-            let ipaddr: Vec<IpAddr> = match lookup_host(&host) {
-                Ok(ip) => ip,
-                Err(_) => return Err(anyhow!("Could not resolve hostname {}", host)),
+}
+
+/// Split one row off the top of `area` for each of `count` hosts, returning
+/// the rows and what's left below them.
+fn split_rows(area: Rect, count: usize) -> (Vec<Rect>, Rect) {
+    let mut chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            std::iter::repeat_n(Constraint::Length(1), count)
+                .chain(iter::once(Constraint::Percentage(10)))
+                .collect::<Vec<_>>(),
+        )
+        .split(area);
+    let rest = chunks.pop().expect("layout has a chart chunk");
+    (chunks, rest)
+}
+
+/// Everything needed to draw host rows and charts for some of the hosts.
+struct Screen<'a> {
+    app: &'a App,
+    hosts: &'a [String],
+    actions: &'a [&'static str],
+    alerts: &'a alert::Alerts,
+    bufferbloat: &'a bufferbloat::Tracker,
+    dns_time: bool,
+    source: Option<IpAddr>,
+}
+
+impl Screen<'_> {
+    fn style(&self, host_id: usize) -> Style {
+        let style = self.app.styles[host_id];
+        if self.alerts.is_firing(host_id) {
+            style.add_modifier(Modifier::REVERSED | Modifier::SLOW_BLINK)
+        } else {
+            style
+        }
+    }
+
+    fn header_text(&self, host_id: usize) -> String {
+        let app = self.app;
+        let host = &self.hosts[host_id];
+        let mut ping_text = format!("{} {}", self.actions[host_id], host);
+        let real_host = match (app.reverse_names.get(host_id), app.host_ips.get(host_id)) {
+            (Some(Some(name)), _) => name.as_str(),
+            (_, Some(Some(ip))) => ip.as_str(),
+            (_, Some(None)) => "resolving…",
+            _ => host.as_str(),
+        }
+        .to_owned();
+        let real_host = match self.source {
+            Some(source) => format!("{} from {}", real_host, source),
+            None => real_host,
+        };
+        let real_host = match app.transports.get(host_id) {
+            Some(Some(mechanism)) => format!("{} via {}", real_host, mechanism),
+            _ => real_host,
+        };
+        let s = match app.notes.get(host_id) {
+            Some(Some(note)) => format!(" ({}, {})", real_host, note),
+            _ => format!(" ({})", real_host),
+        };
+        ping_text.push_str(&s.to_string());
+        if self.dns_time {
+            if let Some(Some(elapsed)) = app.resolve_times.get(host_id) {
+                ping_text.push_str(&format!(" dns {:?}", elapsed));
+            }
+        }
+        if let Some(Some(status)) = app.statuses.get(host_id) {
+            ping_text.push_str(&format!(" HTTP {}", status));
+        }
+        if let Some(Some(output)) = app.outputs.get(host_id) {
+            ping_text.push_str(&format!(" > {}", output));
+        }
+        for (failure, count) in app.failure_counts(host_id) {
+            ping_text.push_str(&format!(" {} {}", count, failure));
+        }
+        if let Some(report) = self.bufferbloat.report(host_id) {
+            ping_text.push_str(&format!(
+                " bloat +{:?} ({})",
+                report.increase(),
+                report.grade()
+            ));
+        }
+        ping_text
+    }
+
+    fn draw_headers<B: tui::backend::Backend>(
+        &self,
+        f: &mut Frame<B>,
+        rows: &[Rect],
+        host_ids: &[usize],
+    ) {
+        let stats = self.app.stats();
+        for (&host_id, &row) in host_ids.iter().zip(rows) {
+            let stats = &stats[host_id];
+            let style = self.style(host_id);
+            // Narrow headers only have room for the host and its p95
+            let narrow = row.width < NARROW_WIDTH;
+            let header_constraints = if narrow {
+                vec![Constraint::Percentage(65), Constraint::Percentage(35)]
+            } else {
+                vec![Constraint::Percentage(25); 4]
             };
-            let _ipaddr = ipaddr.first();
-            self.map_host_ip.insert(host, _ipaddr.unwrap().to_string());
+            let header_layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(header_constraints)
+                .split(row);
+
+            f.render_widget(
+                Paragraph::new(self.header_text(host_id)).style(style),
+                header_layout[0],
+            );
+
+            if !narrow {
+                f.render_widget(
+                    Paragraph::new(format!(
+                        "min {:?}",
+                        Duration::from_micros(stats.minimum().unwrap_or(0))
+                    ))
+                    .style(style),
+                    header_layout[1],
+                );
+                f.render_widget(
+                    Paragraph::new(format!(
+                        "max {:?}",
+                        Duration::from_micros(stats.maximum().unwrap_or(0))
+                    ))
+                    .style(style),
+                    header_layout[2],
+                );
+            }
+            f.render_widget(
+                Paragraph::new(format!(
+                    "p95 {:?}",
+                    Duration::from_micros(stats.percentile(95.0).unwrap_or(0))
+                ))
+                .style(style),
+                header_layout[header_layout.len() - 1],
+            );
         }
+    }
+
+    fn draw_chart<B: tui::backend::Backend>(
+        &self,
+        f: &mut Frame<B>,
+        area: Rect,
+        host_ids: &[usize],
+        title: String,
+    ) {
+        let app = self.app;
+        let y_axis_bounds = app.y_axis_bounds(host_ids);
+        let annotation_data = app.annotation_data(y_axis_bounds);
+        let failure_data: Vec<_> = host_ids
+            .iter()
+            .flat_map(|&host_id| {
+                FAILURES.iter().map(move |&failure| {
+                    (failure, app.failure_data(host_id, failure, y_axis_bounds))
+                })
+            })
+            .collect();
+
+        let datasets: Vec<_> = host_ids
+            .iter()
+            .map(|&host_id| {
+                Dataset::default()
+                    .marker(app.marker)
+                    .style(app.styles[host_id])
+                    .graph_type(GraphType::Line)
+                    .data(app.data[host_id].as_slice())
+            })
+            .chain(host_ids.iter().flat_map(|&host_id| {
+                let style = app.styles[host_id].add_modifier(Modifier::DIM);
+                vec![&app.band_min[host_id], &app.band_max[host_id]]
+                    .into_iter()
+                    .map(move |band| {
+                        Dataset::default()
+                            .marker(symbols::Marker::Dot)
+                            .style(style)
+                            .graph_type(GraphType::Scatter)
+                            .data(band.as_slice())
+                    })
+            }))
+            // Probes that failed with an ICMP error sit along the bottom of the chart
+            .chain(failure_data.iter().map(|(failure, data)| {
+                Dataset::default()
+                    .marker(symbols::Marker::Block)
+                    .style(Style::default().fg(failure_color(*failure)))
+                    .graph_type(GraphType::Scatter)
+                    .data(data)
+            }))
+            .chain(annotation_data.iter().map(|data| {
+                Dataset::default()
+                    .marker(app.marker)
+                    .style(Style::default().fg(Color::DarkGray))
+                    .graph_type(GraphType::Line)
+                    .data(data)
+            }))
+            .collect();
+
+        let chart = Chart::new(datasets)
+            .block(Block::default().borders(Borders::NONE).title(title))
+            .x_axis(
+                Axis::default()
+                    .style(Style::default().fg(Color::Gray))
+                    .bounds(app.x_axis_bounds()),
+            )
+            .y_axis(
+                Axis::default()
+                    .style(Style::default().fg(Color::Gray))
+                    .bounds(y_axis_bounds)
+                    .labels(app.y_axis_labels(y_axis_bounds)),
+            );
+        f.render_widget(chart, area);
+    }
+
+    /// A line of statistics per host, for dashboard panes too small for a chart.
+    fn draw_stats<B: tui::backend::Backend>(
+        &self,
+        f: &mut Frame<B>,
+        area: Rect,
+        host_ids: &[usize],
+    ) {
+        let stats = self.app.stats();
+        let (rows, _) = split_rows(area, host_ids.len());
+        for (&host_id, &row) in host_ids.iter().zip(&rows) {
+            let data = &self.app.data[host_id];
+            let timeouts = data.iter().filter(|v| v.1 == 0f64).count();
+            let loss = if data.len() == 0 {
+                0f64
+            } else {
+                timeouts as f64 * 100f64 / data.len() as f64
+            };
+            let mut text = format!(
+                "{}  p50 {:?}  p95 {:?}  loss {:.1}%",
+                self.hosts[host_id],
+                Duration::from_micros(stats[host_id].percentile(50.0).unwrap_or(0)),
+                Duration::from_micros(stats[host_id].percentile(95.0).unwrap_or(0)),
+                loss
+            );
+            let failures = self.app.failure_counts(host_id);
+            let failed: usize = failures.iter().map(|&(_, count)| count).sum();
+            if timeouts > failed {
+                text.push_str(&format!("  timeout {}", timeouts - failed));
+            }
+            for (failure, count) in failures {
+                text.push_str(&format!("  {} {}", failure, count));
+            }
+            f.render_widget(Paragraph::new(text).style(self.style(host_id)), row);
+        }
+    }
+}
+
+/// Which of a hostname's addresses get pinged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Selection {
+    /// The first address returned by the resolver
+    First,
+    /// Every address, each as its own series
+    All,
+    /// Whichever of the first IPv4 and IPv6 address answers faster
+    Race,
+    /// The first IPv4 and the first IPv6 address, each as its own series
+    BothFamilies,
+}
+
+/// Split the interval off a host given as `host@interval`. URLs are left
+/// whole, as an `@` in them ends the user they log in as.
+fn split_interval(host: &str) -> Result<(&str, Option<Duration>)> {
+    if source::is_url(host) {
+        return Ok((host, None));
+    }
+    match host.rfind('@') {
+        Some(idx) => Ok((&host[..idx], Some(duration::parse(&host[idx + 1..])?))),
+        None => Ok((host, None)),
+    }
+}
+
+struct ResolvedHost {
+    host: String,
+    /// Missing if the host couldn't be resolved yet, its ping thread retries
+    ip: Option<String>,
+    resolve_time: Option<Duration>,
+    /// Shown next to the address in the header
+    note: Option<String>,
+}
+
+/// Colors every console can show, including the old Windows console which
+/// only understands the basic 16.
+const LEGACY_COLORS: [Color; 12] = [
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+];
+
+fn host_style(host_id: usize, legacy_console: bool) -> Style {
+    let color = if legacy_console {
+        LEGACY_COLORS[host_id % LEGACY_COLORS.len()]
+    } else {
+        Color::Indexed(host_id as u8 + 1)
+    };
+    Style::default().fg(color)
+}
+
+/// The received and sent lines of each `--iface-rate` interface take turns
+/// with these, after the white goodput line.
+fn iface_style(line: usize) -> Style {
+    const COLORS: [Color; 4] = [Color::Cyan, Color::Magenta, Color::Green, Color::Yellow];
+    Style::default().fg(COLORS[line % COLORS.len()])
+}
+
+/// Whether we're drawing to a console without ANSI support or braille fonts:
+/// stock cmd.exe and PowerShell windows, or the Linux virtual console.
+/// Windows Terminal, ConEmu, ANSICON and editor terminals announce themselves
+/// through the environment.
+fn is_legacy_console() -> bool {
+    if cfg!(windows) {
+        ![
+            "WT_SESSION",
+            "ConEmuANSI",
+            "ANSICON",
+            "TERM_PROGRAM",
+            "TERM",
+        ]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some())
+    } else {
+        std::env::var("TERM").is_ok_and(|term| term == "linux")
+    }
+}
+
+/// The shortest `--interval`, which unprivileged ping allows on most systems.
+const MIN_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Parse `--interval`, refusing ones shorter than `MIN_INTERVAL` before
+/// anything is resolved or connected to.
+fn parse_interval(input: &str) -> Result<Duration> {
+    let interval = duration::parse(input)?;
+    if interval < MIN_INTERVAL {
+        return Err(anyhow!(
+            "must be at least {:?}, use --fast for a short burst of faster pings",
+            MIN_INTERVAL
+        ));
+    }
+    Ok(interval)
+}
+/// Limits on `--fast`, so a forgotten gping doesn't flood its targets.
+const MIN_FAST_INTERVAL: Duration = Duration::from_millis(10);
+const MAX_FAST_FOR: Duration = Duration::from_secs(10 * 60);
+/// Every kind of failure, in the order they're listed.
+const FAILURES: [probe::Failure; 6] = [
+    probe::Failure::Unreachable,
+    probe::Failure::TtlExceeded,
+    probe::Failure::TooBig,
+    probe::Failure::ServerError,
+    probe::Failure::ParseError,
+    probe::Failure::CommandFailed,
+];
+
+fn failure_color(failure: probe::Failure) -> Color {
+    match failure {
+        probe::Failure::Unreachable => Color::Red,
+        probe::Failure::TtlExceeded => Color::Yellow,
+        probe::Failure::TooBig => Color::Magenta,
+        probe::Failure::ServerError => Color::LightRed,
+        probe::Failure::ParseError => Color::Cyan,
+        probe::Failure::CommandFailed => Color::LightMagenta,
+    }
+}
+
+/// Time between the pings of a `--burst`.
+const BURST_SPACING: Duration = Duration::from_millis(20);
+
+/// Below this many rows the chart drops its outer margin
+const SMALL_HEIGHT: u16 = 20;
+/// Below this many columns host headers only show the p95 latency
+const NARROW_WIDTH: u16 = 80;
+/// How long to wait for the first reply when racing address families.
+const RACE_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn first_reply(ip: IpAddr) -> Option<Duration> {
+    let (_, stream) = ping(ip.to_string(), &probe::Options::default()).ok()?;
+    match stream.recv_timeout(RACE_TIMEOUT) {
+        Ok(PingResult::Pong(duration)) => Some(duration),
+        _ => None,
+    }
+}
+
+/// Ping an IPv4 and an IPv6 address once each, returning the faster one and
+/// a description of the outcome.
+fn race_families(v4: IpAddr, v6: IpAddr) -> (IpAddr, String) {
+    let v6_thread = thread::spawn(move || first_reply(v6));
+    let v4_rtt = first_reply(v4);
+    let v6_rtt = v6_thread.join().unwrap_or(None);
+    match (v4_rtt, v6_rtt) {
+        (Some(v4_rtt), Some(v6_rtt)) if v6_rtt <= v4_rtt => {
+            (v6, format!("IPv6 faster by {:?}", v4_rtt - v6_rtt))
+        }
+        (Some(v4_rtt), Some(v6_rtt)) => (v4, format!("IPv4 faster by {:?}", v6_rtt - v4_rtt)),
+        (Some(_), None) => (v4, "IPv6 unreachable".to_string()),
+        (None, Some(_)) => (v6, "IPv4 unreachable".to_string()),
+        (None, None) => (v6, "no reply on IPv4 or IPv6".to_string()),
+    }
+}
+
+/// Resolve each host into the addresses to ping according to `selection`.
+fn resolve_hosts(
+    hosts: &[String],
+    resolver: &resolve::Resolver,
+    selection: Selection,
+) -> Vec<ResolvedHost> {
+    let mut resolved = vec![];
+    for host in hosts {
+        if resolve::scoped_ipv6(host).is_some() || source::is_url(host) {
+            // Keep the zone index, the ping has to go out of that interface.
+            // URLs are resolved by each request.
+            resolved.push(ResolvedHost {
+                host: host.clone(),
+                ip: Some(host.clone()),
+                resolve_time: None,
+                note: None,
+            });
+            continue;
+        }
+        let start = Instant::now();
+        let ips = match resolver.lookup(host) {
+            Ok(ips) => ips,
+            Err(_) => {
+                resolved.push(ResolvedHost {
+                    host: host.clone(),
+                    ip: None,
+                    resolve_time: None,
+                    note: None,
+                });
+                continue;
+            }
+        };
+        let resolve_time = Some(start.elapsed());
+        let v4 = ips.iter().find(|ip| ip.is_ipv4()).cloned();
+        let v6 = ips.iter().find(|ip| ip.is_ipv6()).cloned();
+        let chosen = match (selection, v4, v6) {
+            (Selection::All, _, _) => ips.iter().map(|&ip| (ip, None)).collect(),
+            (Selection::Race, Some(v4), Some(v6)) => {
+                let (ip, note) = race_families(v4, v6);
+                vec![(ip, Some(note))]
+            }
+            (Selection::BothFamilies, Some(v4), Some(v6)) => vec![
+                (v4, Some("IPv4".to_string())),
+                (v6, Some("IPv6".to_string())),
+            ],
+            _ => vec![(ips[0], None)],
+        };
+        for (ip, note) in chosen {
+            resolved.push(ResolvedHost {
+                host: host.clone(),
+                ip: Some(ip.to_string()),
+                resolve_time,
+                note,
+            });
+        }
+    }
+    resolved
+}
+
+/// Sleep for `duration`, waking early if gping is shutting down.
+fn sleep_unless_killed(duration: Duration, killed: &AtomicBool) {
+    let deadline = Instant::now() + duration;
+    while !killed.load(Ordering::Acquire) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// How the ping threads probe their hosts.
+#[derive(Debug, Clone)]
+struct Probing {
+    options: probe::Options,
+    /// With `--fast`, the interval to use instead for a while after starting
+    fast: Option<Fast>,
+    /// With `--burst`, how many pings to send each interval
+    burst: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fast {
+    interval: Duration,
+    duration: Duration,
+}
+
+impl Probing {
+    /// The same probing for a host given its own interval.
+    fn with_interval(&self, interval: Duration) -> Probing {
+        let mut probing = self.clone();
+        probing.options.interval = interval;
+        probing
+    }
+    fn options_at(&self, elapsed: Duration) -> probe::Options {
+        let mut options = self.options.clone();
+        if let Some(fast) = self.fast {
+            if elapsed < fast.duration {
+                options.interval = fast.interval;
+            }
+        }
+        options
+    }
+}
+
+/// What every ping thread shares: the resolver for hosts without an address
+/// yet, where to send samples and when to stop.
+struct Pinger {
+    resolver: resolve::Resolver,
+    ping_tx: mpsc::Sender<Event>,
+    killed: Arc<AtomicBool>,
+    /// Set outside the windows of a `[schedule]`, when nothing is probed
+    idle: Arc<AtomicBool>,
+}
+
+/// Sample `host` with `source` from a new thread, first resolving it with an
+/// increasing backoff if `target` doesn't have an address yet.
+fn spawn_pinger(
+    pinger: &Pinger,
+    host_id: usize,
+    host: String,
+    target: Arc<Mutex<Option<String>>>,
+    probing: Probing,
+    source: Arc<dyn source::DataSource>,
+) -> thread::JoinHandle<Result<()>> {
+    let resolver = pinger.resolver.clone();
+    let ping_tx = pinger.ping_tx.clone();
+    let killed_ping = Arc::clone(&pinger.killed);
+    let idle = Arc::clone(&pinger.idle);
+    // Sources like feeds can't be stopped and started again, what they send
+    // outside a window is dropped instead
+    let pauses = !source.own_pace();
+    let mut probing = probing;
+    if source.own_pace() {
+        probing.fast = None;
+        probing.burst = None;
+    }
+    // Pump ping messages into the queue
+    thread::spawn(move || -> Result<()> {
+        let mut backoff = Duration::from_secs(1);
+        let mut ip = loop {
+            if killed_ping.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            if let Some(ip) = target.lock().unwrap().clone() {
+                break ip;
+            }
+            let start = Instant::now();
+            if let Ok(ips) = resolver.lookup(&host) {
+                ping_tx.send(Event::ResolveTime(host_id, start.elapsed()))?;
+                let ip = ips[0].to_string();
+                *target.lock().unwrap() = Some(ip.clone());
+                ping_tx.send(Event::Resolved(host_id, ip.clone()))?;
+                break ip;
+            }
+            sleep_unless_killed(backoff, &killed_ping);
+            backoff = std::cmp::min(backoff * 2, MAX_RESOLVE_BACKOFF);
+        };
+        let start = |ip: &str, options: &probe::Options| -> Result<mpsc::Receiver<PingResult>> {
+            let notice_tx = ping_tx.clone();
+            source.start(
+                ip,
+                options,
+                Box::new(move |notice| {
+                    let event = match notice {
+                        source::Notice::Transport(mechanism) => {
+                            Event::Transport(host_id, mechanism)
+                        }
+                        source::Notice::Status(status) => Event::Status(host_id, status),
+                        source::Notice::Annotation(label) => Event::Annotation(host_id, label),
+                        source::Notice::Output(line) => Event::Output(host_id, line),
+                        source::Notice::ExitCode(code) => Event::ExitCode(host_id, code),
+                    };
+                    notice_tx.send(event).unwrap_or(());
+                }),
+            )
+        };
+        if let Some(burst) = probing.burst {
+            let mut options = probing.options.clone();
+            options.interval = BURST_SPACING;
+            while !killed_ping.load(Ordering::Acquire) {
+                if pauses && idle.load(Ordering::Acquire) {
+                    sleep_unless_killed(KILL_CHECK, &killed_ping);
+                    continue;
+                }
+                let cycle = Instant::now();
+                if let Some(current) = target.lock().unwrap().clone() {
+                    ip = current;
+                }
+                // Dropping the stream stops the pings once the burst is in
+                let stream = start(&ip, &options)?;
+                let interval = probing.options.interval;
+                let mut rtts = vec![];
+                let mut failure = None;
+                for _ in 0..burst {
+                    if killed_ping.load(Ordering::Acquire) {
+                        return Ok(());
+                    }
+                    match stream.recv_timeout(interval) {
+                        Ok(PingResult::Pong(rtt)) => rtts.push(rtt),
+                        Ok(PingResult::Failed(error)) => failure = Some(error),
+                        Ok(PingResult::Timeout) => {}
+                        // A stalled burst counts what it has, the rest are lost
+                        Err(mpsc::RecvTimeoutError::Timeout) => break,
+                        // The whole burst failed, like a watched command that died
+                        Err(mpsc::RecvTimeoutError::Disconnected) => {
+                            rtts.clear();
+                            break;
+                        }
+                    }
+                }
+                rtts.sort();
+                // A burst is only lost if every ping in it is
+                let update = match (rtts.first(), rtts.last()) {
+                    (Some(&min), Some(&max)) => {
+                        ping_tx.send(Event::Band(host_id, min, max))?;
+                        Update::Result(rtts[rtts.len() / 2])
+                    }
+                    _ => failure.map_or(Update::Timeout, Update::Failed),
+                };
+                ping_tx.send(Event::Update(host_id, update))?;
+                sleep_unless_killed(interval - cycle.elapsed().min(interval), &killed_ping);
+            }
+            return Ok(());
+        }
+        let started = Instant::now();
+        let mut options = probing.options_at(started.elapsed());
+        let mut idling = pauses && idle.load(Ordering::Acquire);
+        let mut stream = if idling {
+            mpsc::channel().1
+        } else {
+            start(&ip, &options)?
+        };
+        while !killed_ping.load(Ordering::Acquire) {
+            // Dropping the stream stops the pings until the next window
+            if pauses && idle.load(Ordering::Acquire) {
+                if !idling {
+                    stream = mpsc::channel().1;
+                    idling = true;
+                }
+                sleep_unless_killed(KILL_CHECK, &killed_ping);
+                continue;
+            }
+            if idling {
+                stream = start(&ip, &options)?;
+                idling = false;
+            }
+            let current_options = probing.options_at(started.elapsed());
+            if current_options != options {
+                options = current_options;
+                stream = start(&ip, &options)?;
+                ping_tx.send(Event::Annotation(host_id, "fast mode ended".to_string()))?;
+            }
+            // Follow the host to its new address after a re-resolution
+            if let Some(current) = target.lock().unwrap().clone() {
+                if current != ip {
+                    ip = current;
+                    stream = start(&ip, &options)?;
+                }
+            }
+            match stream.recv_timeout(KILL_CHECK) {
+                Ok(result) => ping_tx.send(Event::Update(host_id, result.into()))?,
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                // The source has run dry, like stdin once it's closed
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Print how each host did in a window of the `[schedule]` that opened at
+/// `opened`, and write it to the schedule's `reports` directory if it has one.
+fn report_window(
+    opened: chrono::DateTime<Local>,
+    totals: &[Totals],
+    hosts: &[String],
+    reports: Option<&Path>,
+) {
+    let rows: Vec<_> = totals
+        .iter()
+        .zip(hosts)
+        .map(|(totals, host)| totals.row(host))
+        .collect();
+    let elapsed = (Local::now() - opened).to_std().unwrap_or_default();
+    // Standard output may be carrying the samples
+    eprintln!(
+        "window opened {}\n{}",
+        opened.format("%Y-%m-%d %H:%M:%S"),
+        summary::table(&rows, elapsed)
+    );
+    if let Some(dir) = reports {
+        let mut report = summary::report(&rows, elapsed);
+        report["opened"] = opened.to_rfc3339().into();
+        let path = dir.join(format!("{}.json", opened.format("%Y%m%dT%H%M%S")));
+        // A full disk shouldn't stop the windows still to come
+        if let Err(e) = std::fs::write(&path, format!("{:#}\n", report)) {
+            eprintln!("Could not write the report to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Chart the recordings at `before` and `after` together until a key is
+/// pressed, then print how each target changed. With `export_to`, the chart is
+/// saved as an image too.
+fn compare_recordings(
+    before: &Path,
+    after: &Path,
+    legacy_console: bool,
+    export_to: Option<&Path>,
+) -> Result<()> {
+    let (before_recording, after_recording) = (replay::load(before)?, replay::load(after)?);
+    let table = compare::table(
+        &compare::rows(&before_recording),
+        &compare::rows(&after_recording),
+    );
+    let label = |path: &Path| {
+        path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().to_string(),
+        )
+    };
+    let (before_label, after_label) = (label(before), label(after));
+    let merged = compare::merge(
+        before_recording,
+        after_recording,
+        [&before_label, &after_label],
+    );
+    let series: Vec<_> = merged
+        .targets
+        .iter()
+        .zip(compare::points(&merged))
+        .enumerate()
+        .map(|(host_id, (name, points))| export::Series {
+            name: name.clone(),
+            color: host_style(host_id, legacy_console)
+                .fg
+                .unwrap_or(Color::Reset),
+            points,
+        })
+        .collect();
+    let max_x = series
+        .iter()
+        .flat_map(|series| series.points.iter().map(|point| point.0))
+        .fold(1f64, f64::max);
+    let max_y = series
+        .iter()
+        .flat_map(|series| series.points.iter().map(|point| point.1))
+        .fold(0f64, f64::max);
+    let chart = export::Chart {
+        title: format!("{} vs {}", before_label, after_label),
+        series,
+        x_bounds: [0f64, max_x],
+        // Add a 10% buffer to the top, like the live chart
+        y_bounds: [0f64, (max_y * 1.1).max(1f64)],
+    };
+    if let Some(path) = export_to {
+        export::write(&chart, path)?;
+    }
+
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+    terminal.clear()?;
+    let marker = if legacy_console {
+        symbols::Marker::Dot
+    } else {
+        symbols::Marker::Braille
+    };
+    // Drawn again whenever the terminal is resized, until a key is pressed
+    let drawn: Result<()> = loop {
+        let drawn = terminal.draw(|f| {
+            let datasets = chart
+                .series
+                .iter()
+                .map(|series| {
+                    Dataset::default()
+                        .name(series.name.as_str())
+                        .marker(marker)
+                        .style(Style::default().fg(series.color))
+                        .graph_type(GraphType::Line)
+                        .data(&series.points)
+                })
+                .collect();
+            let [min_y, max_y] = chart.y_bounds;
+            let step = Duration::from_micros(((max_y - min_y) / 4f64) as u64);
+            let widget = Chart::new(datasets)
+                .block(Block::default().borders(Borders::NONE).title(Span::styled(
+                    format!("{} (press any key)", chart.title),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )))
+                .x_axis(
+                    Axis::default()
+                        .style(Style::default().fg(Color::Gray))
+                        .bounds(chart.x_bounds),
+                )
+                .y_axis(
+                    Axis::default()
+                        .style(Style::default().fg(Color::Gray))
+                        .bounds(chart.y_bounds)
+                        .labels(
+                            (0..5)
+                                .map(|i| Span::raw(format!("{:?}", step * i)))
+                                .collect(),
+                        ),
+                );
+            f.render_widget(widget, f.size());
+        });
+        if let Err(e) = drawn {
+            break Err(e.into());
+        }
+        match event::read() {
+            Ok(CEvent::Key(_)) => break Ok(()),
+            Ok(_) => {}
+            Err(e) => break Err(e.into()),
+        }
+    };
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    drawn?;
+
+    println!("{}", table);
+    Ok(())
+}
+
+/// Graph each new series of a feed as another host.
+fn add_fed_series(
+    tx: mpsc::Sender<Event>,
+) -> impl FnMut(String, mpsc::Receiver<PingResult>) -> Result<()> {
+    move |label, samples| {
+        let source = Arc::new(source::Feed::new(label.clone(), samples));
+        tx.send(Event::AddSource(label, source))?;
         Ok(())
     }
 }
@@ -158,6 +1879,18 @@ impl App {
 enum Update {
     Result(Duration),
     Timeout,
+    Failed(probe::Failure),
+}
+
+impl Update {
+    /// How the probe went, as logged: `ok`, `timeout` or the failure.
+    fn status(&self) -> String {
+        match self {
+            Update::Result(_) => "ok".to_string(),
+            Update::Timeout => "timeout".to_string(),
+            Update::Failed(failure) => failure.to_string(),
+        }
+    }
 }
 
 impl From<PingResult> for Update {
@@ -165,6 +1898,7 @@ impl From<PingResult> for Update {
         match result {
             PingResult::Pong(duration) => Update::Result(duration),
             PingResult::Timeout => Update::Timeout,
+            PingResult::Failed(failure) => Update::Failed(failure),
         }
     }
 }
@@ -172,22 +1906,588 @@ impl From<PingResult> for Update {
 #[derive(Debug)]
 enum Event {
     Update(usize, Update),
+    Annotation(usize, String),
+    Resolved(usize, String),
+    ResolveTime(usize, Duration),
+    /// The fastest and slowest replies of a `--burst`, sent before its median
+    Band(usize, Duration, Duration),
+    /// How a host's pings are being sent, whenever its pinger starts
+    Transport(usize, probe::Mechanism),
+    /// The status of a response from a URL target
+    Status(usize, u16),
+    /// The last line a watched command printed
+    Output(usize, String),
+    /// A watched command exited with a code other than 0
+    ExitCode(usize, i32),
+    /// Start graphing another host
+    AddSource(String, Arc<dyn source::DataSource>),
+    Throughput(Result<throughput::Transfer, String>),
+    /// Received and sent bits per second of an `--iface-rate` interface
+    IfaceRate(usize, f64, f64),
+    Phase(bufferbloat::Phase),
+    Control(control::Command),
     Input(KeyEvent),
+    /// `--duration` has passed, or a headless session was interrupted
+    Finished,
+    /// A window of the `[schedule]` opened or closed
+    Window(bool),
 }
 
 fn main() -> Result<()> {
     let args = Args::from_args();
-    let num_threads = std::cmp::max(1, args.hosts.len());
-    let mut app = App::new(num_threads, args.buffer);
-    app.get_hosts_ipaddr(&args.hosts)?;
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-
-    let mut terminal = Terminal::new(backend)?;
+    if let Some(Command::Compare { before, after }) = &args.command {
+        let legacy_console = args.legacy_console || is_legacy_console();
+        return compare_recordings(
+            before,
+            after,
+            legacy_console,
+            args.export_on_exit.as_deref(),
+        );
+    }
+    let config = match args.config {
+        Some(ref path) => config::Config::load(path)?,
+        None => config::Config::default(),
+    };
+    let mut resolver = resolve::Resolver::new(match args.resolver.clone() {
+        Some(server) => resolve::Nameserver::Server(server),
+        None => resolve::Nameserver::System,
+    });
+    let family = if args.ipv4 {
+        Some(probe::Family::V4)
+    } else if args.ipv6 {
+        Some(probe::Family::V6)
+    } else {
+        None
+    };
+    if let Some(family) = family {
+        resolver.restrict_to(family);
+    }
+    resolver.pin(args.resolve_overrides.clone());
+    if args.from_ssh_config {
+        resolver.add_aliases(aliases::from_ssh_config());
+    }
+    if args.from_hosts {
+        resolver.add_aliases(aliases::from_hosts_file());
+    }
+    // By position, as the same host can be given twice at different intervals
+    let mut host_intervals = vec![];
+    let mut requested_hosts = args
+        .hosts
+        .iter()
+        .map(|arg| {
+            let (host, interval) = split_interval(arg)?;
+            if interval.is_some_and(|interval| interval < MIN_INTERVAL) {
+                return Err(anyhow!(
+                    "{}'s interval must be at least {:?}",
+                    host,
+                    MIN_INTERVAL
+                ));
+            }
+            host_intervals.push(interval);
+            resolver.complete(host)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Hosts shown in dashboard panes are pinged along with those given as arguments
+    let mut dashboard_config = config.dashboard.clone();
+    if let Some(ref mut dashboard) = dashboard_config {
+        for pane in &mut dashboard.pane {
+            pane.hosts = pane
+                .hosts
+                .iter()
+                .map(|host| resolver.complete(host))
+                .collect::<Result<Vec<_>>>()?;
+            for host in &pane.hosts {
+                if !requested_hosts.contains(host) {
+                    requested_hosts.push(host.clone());
+                    host_intervals.push(None);
+                }
+            }
+        }
+    }
+    let selection = if args.all_ips {
+        Selection::All
+    } else if args.both_families {
+        Selection::BothFamilies
+    } else if args.happy_eyeballs {
+        Selection::Race
+    } else {
+        Selection::First
+    };
+    for iface in &args.iface_rate {
+        iface::counters(iface)?;
+    }
+    for &metric in &args.load {
+        load::Sampler::new(metric).sample()?;
+    }
+    if let Some(url) = args.json_poll.iter().find(|url| !source::is_url(url)) {
+        return Err(anyhow!(
+            "--json-poll needs an http:// or https:// URL, not {}",
+            url
+        ));
+    }
+    if args.column == Some(0) {
+        return Err(anyhow!("--column counts from 1"));
+    }
+    if let Some(path) = args.tail.iter().find(|path| !path.is_file()) {
+        return Err(anyhow!("There's no file to follow at {}", path.display()));
+    }
+    if let Some(ref path) = args.export_on_exit {
+        export::Format::of(path)?;
+    }
+    if args.asciicast.is_some() && args.output.is_some() && args.output_file.is_none() {
+        return Err(anyhow!(
+            "--asciicast records the chart, which --output leaves out without --output-file"
+        ));
+    }
+    if let Some(dir) = args.watch_dir.as_ref().filter(|dir| !dir.is_dir()) {
+        return Err(anyhow!("--watch-dir {} is not a directory", dir.display()));
+    }
+    if let Some(path) = args.pipe.iter().find(|path| !path.exists()) {
+        return Err(anyhow!(
+            "There's no pipe at {}, make one with mkfifo",
+            path.display()
+        ));
+    }
+    if let Some((host, _)) = args
+        .tls
+        .iter()
+        .chain(&args.quic)
+        .find(|(host, _)| host.parse::<IpAddr>().is_ok())
+    {
+        return Err(anyhow!(
+            "--tls and --quic need a hostname to check the certificate of, not {}",
+            host
+        ));
+    }
+    let remote = args
+        .via
+        .as_deref()
+        .map(probe::Remote::connect)
+        .transpose()?;
+    let flavor = remote
+        .as_ref()
+        .map_or_else(probe::Flavor::current, |remote| remote.flavor);
+    // Each address a host resolves to is pinged at the host's interval
+    let mut target_intervals = vec![];
+    let mut resolved = vec![];
+    for (host, &interval) in requested_hosts.iter().zip(&host_intervals) {
+        let targets = if remote.is_some() {
+            // The remote machine resolves hosts itself
+            vec![ResolvedHost {
+                host: host.clone(),
+                ip: Some(host.clone()),
+                resolve_time: None,
+                note: None,
+            }]
+        } else {
+            resolve_hosts(std::slice::from_ref(host), &resolver, selection)
+        };
+        target_intervals.extend(std::iter::repeat_n(interval, targets.len()));
+        resolved.extend(targets);
+    }
+    let mut sources: Vec<_> = resolved.iter().map(|r| source::of(&r.host)).collect();
+    type MakeSource = fn(String, u16) -> Arc<dyn source::DataSource>;
+    let port_targets: [(_, MakeSource); 4] = [
+        (&args.tcp, |host, port| Arc::new(source::Tcp { host, port })),
+        (&args.udp, |host, port| Arc::new(source::Udp { host, port })),
+        (&args.tls, |host, port| Arc::new(source::Tls { host, port })),
+        (&args.quic, |host, port| {
+            Arc::new(source::Quic { host, port })
+        }),
+    ];
+    for (targets, make) in port_targets.iter() {
+        for (host, port) in targets.iter() {
+            for resolved_host in resolve_hosts(&[resolver.complete(host)?], &resolver, selection) {
+                sources.push(make(resolved_host.host.clone(), *port));
+                resolved.push(resolved_host);
+            }
+        }
+    }
+    if let Some(ref proxy) = args.proxy {
+        for (host, port) in &args.tcp {
+            let host = resolver.complete(host)?;
+            resolved.push(ResolvedHost {
+                host: host.clone(),
+                ip: Some(proxy.to_string()),
+                resolve_time: None,
+                note: None,
+            });
+            sources.push(Arc::new(source::Proxied {
+                host,
+                port: *port,
+                proxy: proxy.clone(),
+            }));
+        }
+    }
+    for (name, server) in &args.dns {
+        resolved.push(ResolvedHost {
+            host: name.clone(),
+            ip: Some(server.addr.ip().to_string()),
+            resolve_time: None,
+            note: None,
+        });
+        sources.push(Arc::new(source::Dns {
+            name: name.clone(),
+            server: server.clone(),
+        }));
+    }
+    for ip in &args.arp {
+        resolved.push(ResolvedHost {
+            host: ip.to_string(),
+            ip: Some(ip.to_string()),
+            resolve_time: None,
+            note: None,
+        });
+        sources.push(Arc::new(source::Arp {
+            host: ip.to_string(),
+        }));
+    }
+    if args.gateway {
+        let gateway = gateway::default_gateway()?.to_string();
+        resolved.push(ResolvedHost {
+            host: gateway.clone(),
+            ip: Some(gateway.clone()),
+            resolve_time: None,
+            note: Some("default gateway".to_string()),
+        });
+        sources.push(source::of(&gateway));
+    }
+    for (server, query) in &args.prom {
+        resolved.push(ResolvedHost {
+            host: query.clone(),
+            ip: Some(server.clone()),
+            resolve_time: None,
+            note: None,
+        });
+        sources.push(Arc::new(source::Prom {
+            server: server.clone(),
+            query: query.clone(),
+        }));
+    }
+    for url in &args.json_poll {
+        resolved.push(ResolvedHost {
+            host: url.clone(),
+            ip: Some(url.clone()),
+            resolve_time: None,
+            note: None,
+        });
+        sources.push(Arc::new(source::JsonPoll {
+            url: url.clone(),
+            selector: args.path.clone().unwrap_or_default(),
+        }));
+    }
+    for &metric in &args.load {
+        resolved.push(ResolvedHost {
+            host: metric.to_string(),
+            ip: Some("localhost".to_string()),
+            resolve_time: None,
+            note: None,
+        });
+        sources.push(Arc::new(source::Load { metric }));
+    }
+    for path in &args.tail {
+        let file = path.display().to_string();
+        resolved.push(ResolvedHost {
+            host: file.clone(),
+            ip: Some(file),
+            resolve_time: None,
+            note: None,
+        });
+        sources.push(Arc::new(source::Tail {
+            path: path.clone(),
+            column: args.column.unwrap_or(1),
+        }));
+    }
+    let mut replay_streams = vec![];
+    let recording = match args.replay {
+        Some(ref path) => replay::load(path)?,
+        None => replay::Recording::default(),
+    };
+    for target in &recording.targets {
+        let (tx, rx) = mpsc::channel();
+        replay_streams.push(tx);
+        resolved.push(ResolvedHost {
+            host: target.clone(),
+            ip: args.replay.as_ref().map(|path| path.display().to_string()),
+            resolve_time: None,
+            note: Some("replay".to_string()),
+        });
+        sources.push(Arc::new(source::Feed::new(target.clone(), rx)));
+    }
+    // Where the watched commands are, to count their failures
+    let mut watch_ids = vec![];
+    let slots = args.watch_concurrency.map(watch::Slots::new);
+    for watch_cmd in &args.watch {
+        watch_ids.push(sources.len());
+        resolved.push(ResolvedHost {
+            host: watch_cmd.clone(),
+            ip: Some(watch_cmd.clone()),
+            resolve_time: None,
+            note: None,
+        });
+        sources.push(Arc::new(source::Watch {
+            command: watch_cmd.clone(),
+            shell: args.shell,
+            context: watch::Context {
+                dir: args.watch_dir.clone(),
+                env: args.watch_env.clone(),
+            },
+            value: if let Some(ref regex) = args.extract {
+                watch::Value::Extract(regex.clone())
+            } else if let Some(ref selector) = args.extract_json {
+                watch::Value::Json(selector.clone())
+            } else if args.watch_output {
+                watch::Value::LastLine
+            } else {
+                watch::Value::Elapsed
+            },
+            interval: Duration::from_millis((args.watch_interval * 1000.0) as u64),
+            output_limit: args.watch_output_limit.unwrap_or(WATCH_OUTPUT_LIMIT),
+            show_output: args.watch_show_output,
+            timeout: args.watch_timeout,
+            diff: args.watch_diff,
+            diff_hook: args.watch_diff_hook.clone(),
+            slots: slots.clone(),
+        }));
+    }
+    let ping_hosts: Vec<_> = resolved.iter().map(|r| r.host.clone()).collect();
+    // Targets can come from many options, so clap can't require one. With
+    // --control they can be added once running.
+    if ping_hosts.is_empty() && args.control.is_none() {
+        return Err(anyhow!(
+            "Nothing to graph, give a host or URL, or another target like --watch"
+        ));
+    }
+    let num_threads = std::cmp::max(1, ping_hosts.len());
+    let (mut panes, dashboard_columns) = match dashboard_config {
+        Some(ref dashboard) => (dashboard::panes(dashboard, &ping_hosts), dashboard.columns),
+        _ => (vec![], 0),
+    };
+    let legacy_console = args.legacy_console || is_legacy_console();
+    let mut app = App::new(num_threads, args.buffer, legacy_console);
+    let intervals: Vec<_> = (0..ping_hosts.len())
+        .map(|host_id| {
+            target_intervals
+                .get(host_id)
+                .copied()
+                .flatten()
+                .unwrap_or(args.interval)
+        })
+        .collect();
+    for (host_id, interval) in intervals.iter().enumerate() {
+        app.set_step(
+            host_id,
+            interval.as_secs_f64() / args.interval.as_secs_f64(),
+        );
+    }
+    for resolved_host in resolved {
+        app.host_ips.push(resolved_host.ip);
+        app.resolve_times.push(resolved_host.resolve_time);
+        app.notes.push(resolved_host.note);
+    }
+    if args.reverse_dns {
+        for (host_id, host) in ping_hosts.iter().enumerate() {
+            if let Ok(ip) = host.parse() {
+                app.reverse_names[host_id] = resolver.reverse(ip).ok();
+            }
+        }
+    }
+    let names: Vec<_> = sources.iter().map(|source| source.name()).collect();
+    let mut hosts = labels::apply(&names, &args.label)?;
+    let mut actions: Vec<_> = sources.iter().map(|source| source.action()).collect();
+    let probing = Probing {
+        options: probe::Options {
+            interval: args.interval,
+            timeout: args.timeout,
+            family,
+            interface: args.interface.clone(),
+            source: args.source,
+            ttl: args.ttl,
+            // DSCP is the top six bits of the TOS byte
+            tos: args.tos.or_else(|| args.dscp.map(|dscp| dscp << 2)),
+            native: args.native,
+            extra_args: args
+                .ping_args
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            size: args.size,
+            dont_fragment: args.df,
+            via: remote.clone(),
+        },
+        fast: if args.fast {
+            Some(Fast {
+                interval: args.fast_interval,
+                duration: args.fast_for,
+            })
+        } else {
+            None
+        },
+        burst: args.burst,
+    };
+    if let Some(burst) = args.burst {
+        if burst < 2 || BURST_SPACING * burst >= args.interval {
+            return Err(anyhow!(
+                "--burst must be at least 2, and its pings {:?} apart must fit in --interval",
+                BURST_SPACING
+            ));
+        }
+    }
+    if args.interface.is_some() && !flavor.can_bind_interface() {
+        return Err(anyhow!(
+            "This platform's ping can't send from a chosen interface"
+        ));
+    }
+    if args.source.is_some() && flavor == probe::Flavor::Solaris {
+        return Err(anyhow!(
+            "This platform's ping can't choose a source address"
+        ));
+    }
+    if (args.tos.is_some() || args.dscp.is_some()) && !flavor.can_mark_tos() {
+        return Err(anyhow!("This platform's ping can't mark packets"));
+    }
+    if args.size.is_some() && !flavor.can_set_size() {
+        return Err(anyhow!(
+            "This platform's ping can't change the size of pings"
+        ));
+    }
+    if args.df && !flavor.can_set_dont_fragment() {
+        return Err(anyhow!(
+            "This platform's ping can't stop pings being fragmented"
+        ));
+    }
+    if args.fast_interval < MIN_FAST_INTERVAL {
+        return Err(anyhow!(
+            "--fast-interval must be at least {:?}",
+            MIN_FAST_INTERVAL
+        ));
+    }
+    if args.fast_for > MAX_FAST_FOR {
+        return Err(anyhow!("--fast-for can be at most {:?}", MAX_FAST_FOR));
+    }
+    let custom_interval = args.fast || args.interval != probe::Options::default().interval;
+    // Only a chosen source address, extra ping arguments or a remote machine
+    // send Windows back to ping.exe
+    let windows_ping = (args.source.is_some() || args.ping_args.is_some() || remote.is_some())
+        && flavor == probe::Flavor::Windows;
+    if custom_interval && windows_ping {
+        app.log_event("Windows' ping always pings once a second".to_string());
+    }
+    if let Some(ref url) = args.via {
+        app.log_event(format!("Running ping on {} over SSH", url));
+    }
+    // Other kinds of target don't need any way of sending ICMP, and a
+    // remote machine's ping was found when connecting to it
+    if sources.iter().any(|source| source.uses_icmp()) && remote.is_none() {
+        let capabilities = probe::detect(probe::Flavor::current());
+        let mechanisms = probing.options.mechanisms();
+        let families = [probe::Family::V4, probe::Family::V6];
+        for &family in &families {
+            app.log_event(probe::describe(&capabilities, family, mechanisms));
+        }
+        if families
+            .iter()
+            .all(|&family| probe::choose(&capabilities, family, mechanisms).is_none())
+        {
+            return Err(anyhow!(
+                "No way to send pings: {}",
+                probe::describe(&capabilities, probe::Family::V4, mechanisms)
+            ));
+        }
+    }
 
-    terminal.clear()?;
+    // Streaming samples to standard output leaves no room for the chart
+    let samples_to_stdout = args.output.is_some() && args.output_file.is_none();
+    let headless = args.headless || samples_to_stdout;
+    let mut rules = args.alert.clone();
+    for alert_config in &config.alert {
+        rules.push(alert::Rule::from_config(alert_config)?);
+    }
+    if !rules.is_empty() && !headless && hosts.len() > sinks::DISTINCT_CUES {
+        return Err(anyhow!(
+            "The bell can only tell {} hosts apart, alert on fewer",
+            sinks::DISTINCT_CUES
+        ));
+    }
+    let mut bell = sinks::Bell::default();
+    let mut alerts = alert::Alerts::new(rules, hosts.clone());
+    let mut reachability = alert::Reachability::new(num_threads);
+    let sinks = sinks::Sinks {
+        desktop: args.notify,
+        webhook: args.webhook.clone(),
+        on_down: args.on_down.clone(),
+        on_up: args.on_up.clone(),
+        smtp: config.smtp,
+        pagerduty: config.pagerduty,
+        opsgenie: config.opsgenie,
+    };
+    let mut sample_log = match args.log {
+        Some(ref path) => {
+            let rotation = args.log_rotate.map(|limit| samplelog::Rotation {
+                limit,
+                keep: args.log_keep,
+            });
+            Some(samplelog::CsvLog::open(path, rotation)?)
+        }
+        None => None,
+    };
+    let session_start = Instant::now();
+    let session_started = Local::now();
+    // Only kept when it's needed, as it grows with every sample
+    let mut timeline = args.html_report.as_ref().map(|_| html::Timeline::default());
+    let mut recorder = match args.record {
+        Some(ref path) => Some(record::Recorder::create(
+            path,
+            session_started,
+            args.interval,
+        )?),
+        None => None,
+    };
+    let schedule = match config.schedule {
+        Some(_) if !headless => {
+            return Err(anyhow!(
+                "The [schedule] in the config needs --headless, or --output jsonl without \
+                 --output-file"
+            ))
+        }
+        Some(ref schedule) => Some(schedule::Schedule::from_config(schedule)?),
+        None => None,
+    };
+    let reports = config
+        .schedule
+        .as_ref()
+        .and_then(|schedule| schedule.reports.clone());
+    let mut sample_output = match (args.output, &args.output_file) {
+        (Some(samplelog::Output::JsonLines), Some(path)) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| anyhow!("Could not open {}: {}", path.display(), e))?;
+            Some(samplelog::JsonLines::new(Box::new(file)))
+        }
+        (Some(samplelog::Output::JsonLines), None) => {
+            Some(samplelog::JsonLines::new(Box::new(io::stdout())))
+        }
+        (None, _) => None,
+    };
+    let mut terminal = if headless {
+        None
+    } else {
+        let cast = match args.asciicast {
+            Some(ref path) => Some(asciicast::Cast::create(path, crossterm::terminal::size()?)?),
+            None => None,
+        };
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+        let backend = CrosstermBackend::new(asciicast::Tee::new(stdout, cast));
+        let mut terminal = Terminal::new(backend)?;
+        terminal.clear()?;
+        Some(terminal)
+    };
 
     let (key_tx, rx) = mpsc::channel();
 
@@ -195,164 +2495,334 @@ fn main() -> Result<()> {
 
     let killed = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
 
-    if let Some(ref watch_cmd) = args.watch {
-        let cmd_tx = key_tx.clone();
-        let killed_cmd = std::sync::Arc::clone(&killed);
-        let mut words = watch_cmd.split_ascii_whitespace();
-        let cmd = words
-            .next()
-            .expect("Must specify a command to watch")
-            .to_string();
-        let cmd_args = words
-            .into_iter()
-            .map(|w| w.to_string())
-            .collect::<Vec<String>>();
-
-        let interval = Duration::from_millis((args.watch_interval * 1000.0) as u64);
-
-        // Pump cmd watches into the queue
-        let cmd_thread = thread::spawn(move || -> Result<()> {
-            while !killed_cmd.load(Ordering::Acquire) {
-                let start = Instant::now();
-                let output = Command::new(&cmd).args(&cmd_args).output()?;
-                let duration = start.elapsed();
-                let update = if output.status.success() {
-                    Update::Result(duration)
-                } else {
-                    Update::Timeout
-                };
-                cmd_tx.send(Event::Update(0, update))?;
-                thread::sleep(interval);
+    let mut targets = vec![];
+
+    let mut silenced_until = args.silence.map(|silence| Instant::now() + silence);
+    let mut show_events = false;
+    let mut consecutive_failures: HashMap<usize, u64> =
+        watch_ids.iter().map(|&watch_id| (watch_id, 0)).collect();
+    // The watched command that failed --watch-max-failures times in a row
+    let mut exit_failure = None;
+    // The watched command that failed with --watch-fail-exit, and its code
+    let mut exit_code = None;
+    let mut sleep = clock::SleepDetector::new();
+    let mut bufferbloat = bufferbloat::Tracker::new(num_threads);
+    let mut paused = false;
+
+    let pinger = Pinger {
+        resolver: resolver.clone(),
+        ping_tx: key_tx.clone(),
+        killed: Arc::clone(&killed),
+        // Until the schedule says a window is open
+        idle: Arc::new(AtomicBool::new(schedule.is_some())),
+    };
+    for (host_id, ip) in app.host_ips.iter().enumerate() {
+        let target = Arc::new(Mutex::new(ip.clone()));
+        targets.push(Arc::clone(&target));
+        let thread = spawn_pinger(
+            &pinger,
+            host_id,
+            ping_hosts[host_id].clone(),
+            target,
+            probing.with_interval(intervals[host_id]),
+            Arc::clone(&sources[host_id]),
+        );
+        threads.push((hosts[host_id].clone(), thread));
+    }
+
+    // Feeds aren't joined, reading one blocks until the next line however
+    // long that takes. The ping threads stop their series when told to quit.
+    if args.stdin {
+        let mut reader = feed::Reader::new("stdin", add_fed_series(key_tx.clone()));
+        thread::spawn(move || reader.read(io::stdin().lock()));
+    }
+    for path in &args.pipe {
+        let path = path.clone();
+        let mut reader =
+            feed::Reader::new(&path.display().to_string(), add_fed_series(key_tx.clone()));
+        thread::spawn(move || feed::read_pipe(&path, &mut reader));
+    }
+    let replay_controls = if args.replay.is_some() {
+        let controls = Arc::new(replay::Controls::default());
+        let playing = Arc::clone(&controls);
+        let samples = recording.samples;
+        thread::spawn(move || replay::play(samples, replay_streams, &playing));
+        Some(controls)
+    } else {
+        None
+    };
+
+    if let Some(interval) = args.resolve_interval {
+        let resolve_tx = key_tx.clone();
+        let killed_resolve = std::sync::Arc::clone(&killed);
+        let hosts = ping_hosts.clone();
+        let resolves: Vec<_> = sources.iter().map(|source| source.resolves()).collect();
+        let targets = targets.clone();
+        let resolver = resolver.clone();
+        // Periodically re-resolve hosts, pointing the ping threads at any new address
+        let resolve_thread = thread::spawn(move || -> Result<()> {
+            while !killed_resolve.load(Ordering::Acquire) {
+                sleep_unless_killed(interval, &killed_resolve);
+                for (host_id, host) in hosts.iter().enumerate() {
+                    if resolve::scoped_ipv6(host).is_some() || !resolves[host_id] {
+                        continue;
+                    }
+                    // Keep using the last known address if resolution fails
+                    let start = Instant::now();
+                    match resolver.lookup(host) {
+                        Ok(ips) => {
+                            resolve_tx.send(Event::ResolveTime(host_id, start.elapsed()))?;
+                            let ip = ips[0].to_string();
+                            let mut target = targets[host_id].lock().unwrap();
+                            if target.as_ref() != Some(&ip) {
+                                *target = Some(ip.clone());
+                                resolve_tx.send(Event::Resolved(host_id, ip))?;
+                            }
+                        }
+                        Err(e) => {
+                            resolve_tx.send(Event::Annotation(host_id, e.to_string()))?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        });
+        threads.push(("re-resolving".to_string(), resolve_thread));
+    }
+
+    for (iface_id, iface) in args.iface_rate.iter().enumerate() {
+        let iface = iface.clone();
+        let iface_tx = key_tx.clone();
+        let killed_iface = std::sync::Arc::clone(&killed);
+        let interval = args.interval;
+        let iface_thread = thread::spawn(move || -> Result<()> {
+            let mut meter = iface::Meter::default();
+            while !killed_iface.load(Ordering::Acquire) {
+                let counters = iface::counters(&iface)?;
+                if let Some((rx, tx)) = meter.rates(Instant::now(), counters) {
+                    iface_tx.send(Event::IfaceRate(iface_id, rx, tx))?;
+                }
+                sleep_unless_killed(interval, &killed_iface);
             }
             Ok(())
         });
-        threads.push(cmd_thread);
+        threads.push(("--iface-rate".to_string(), iface_thread));
+    }
+
+    let show_goodput = args.throughput.is_some() || !args.iface_rate.is_empty();
+    let rates_title = if args.iface_rate.is_empty() {
+        "goodput"
     } else {
-        for (host_id, host) in args.hosts.iter().cloned().enumerate() {
-            let ping_tx = key_tx.clone();
+        "throughput"
+    };
+    let iface_labels: Vec<_> = args
+        .iface_rate
+        .iter()
+        .map(|iface| [format!("{} rx", iface), format!("{} tx", iface)])
+        .collect();
+    if let Some(url) = args.throughput.clone() {
+        let throughput_tx = key_tx.clone();
+        let killed_throughput = std::sync::Arc::clone(&killed);
+        let (interval, size) = (args.throughput_interval, args.throughput_size);
+        let phase = if args.bufferbloat {
+            Some(args.bufferbloat_phase)
+        } else {
+            None
+        };
+        let throughput_thread = thread::spawn(move || -> Result<()> {
+            while !killed_throughput.load(Ordering::Acquire) {
+                let phase = match phase {
+                    Some(phase) => phase,
+                    None => {
+                        let transfer = throughput::measure(&url, size).map_err(|e| e.to_string());
+                        throughput_tx.send(Event::Throughput(transfer))?;
+                        sleep_unless_killed(interval, &killed_throughput);
+                        continue;
+                    }
+                };
+                throughput_tx.send(Event::Phase(bufferbloat::Phase::Idle))?;
+                sleep_unless_killed(phase, &killed_throughput);
+                throughput_tx.send(Event::Phase(bufferbloat::Phase::Load))?;
+                let load_start = Instant::now();
+                while load_start.elapsed() < phase && !killed_throughput.load(Ordering::Acquire) {
+                    let transfer = throughput::measure(&url, size).map_err(|e| e.to_string());
+                    throughput_tx.send(Event::Throughput(transfer))?;
+                }
+            }
+            Ok(())
+        });
+        threads.push(("--throughput".to_string(), throughput_thread));
+    }
+
+    if let Some(path) = args.control.clone() {
+        let control_tx = key_tx.clone();
+        let killed_control = std::sync::Arc::clone(&killed);
+        let control_thread = thread::spawn(move || -> Result<()> {
+            control::listen(&path, killed_control, |command| {
+                Ok(control_tx.send(Event::Control(command))?)
+            })
+        });
+        threads.push(("--control".to_string(), control_thread));
+    }
+
+    if let Some(duration) = args.duration {
+        let finish_tx = key_tx.clone();
+        let killed_finish = std::sync::Arc::clone(&killed);
+        let finish_thread = thread::spawn(move || -> Result<()> {
+            sleep_unless_killed(duration, &killed_finish);
+            if !killed_finish.load(Ordering::Acquire) {
+                finish_tx.send(Event::Finished)?;
+            }
+            Ok(())
+        });
+        threads.push(("--duration".to_string(), finish_thread));
+    }
 
-            let killed_ping = std::sync::Arc::clone(&killed);
-            // Pump ping messages into the queue
-            let ping_thread = thread::spawn(move || -> Result<()> {
-                let stream = ping(host)?;
-                while !killed_ping.load(Ordering::Acquire) {
-                    ping_tx.send(Event::Update(host_id, stream.recv()?.into()))?;
+    // Without the keyboard, Ctrl-C and SIGTERM stop the session like q does
+    if headless {
+        let interrupt_tx = key_tx.clone();
+        ctrlc::set_handler(move || interrupt_tx.send(Event::Finished).unwrap_or(()))?;
+    }
+
+    // Samples in the window that's open, and when it opened
+    let mut window: Option<(chrono::DateTime<Local>, Vec<Totals>)> = None;
+    let scheduled = schedule.is_some();
+    if let Some(schedule) = schedule {
+        let window_tx = key_tx.clone();
+        let killed_window = std::sync::Arc::clone(&killed);
+        let window_thread = thread::spawn(move || -> Result<()> {
+            let mut open = false;
+            while !killed_window.load(Ordering::Acquire) {
+                let now = Local::now().naive_local();
+                if schedule.open_until(now).is_some() != open {
+                    open = !open;
+                    window_tx.send(Event::Window(open))?;
                 }
-                Ok(())
-            });
-            threads.push(ping_thread);
-        }
+                sleep_unless_killed(Duration::from_secs(1), &killed_window);
+            }
+            Ok(())
+        });
+        threads.push(("[schedule]".to_string(), window_thread));
     }
 
     // Pump keyboard messages into the queue
-    let killed_thread = std::sync::Arc::clone(&killed);
-    let key_thread = thread::spawn(move || -> Result<()> {
-        while !killed_thread.load(Ordering::Acquire) {
-            if event::poll(Duration::from_millis(100))? {
-                if let CEvent::Key(key) = event::read()? {
-                    key_tx.send(Event::Input(key))?;
+    if !headless {
+        let input_tx = key_tx.clone();
+        let killed_thread = std::sync::Arc::clone(&killed);
+        let key_thread = thread::spawn(move || -> Result<()> {
+            while !killed_thread.load(Ordering::Acquire) {
+                if event::poll(Duration::from_millis(100))? {
+                    if let CEvent::Key(key) = event::read()? {
+                        input_tx.send(Event::Input(key))?;
+                    }
                 }
             }
-        }
-        Ok(())
-    });
-    threads.push(key_thread);
+            Ok(())
+        });
+        threads.push(("keyboard".to_string(), key_thread));
+    }
 
     loop {
-        match rx.recv()? {
-            Event::Update(host_id, update) => {
-                match update {
-                    Update::Result(duration) => app.update(host_id, Some(duration)),
-                    Update::Timeout => app.update(host_id, None),
+        if let Some(ref mut terminal) = terminal {
+            terminal.draw(|f| {
+                let screen = Screen {
+                    app: &app,
+                    hosts: &hosts,
+                    actions: &actions,
+                    alerts: &alerts,
+                    bufferbloat: &bufferbloat,
+                    dns_time: args.dns_time,
+                    source: args.source,
+                };
+                // Small terminals, like Termux on a phone, can't spare the margin
+                let margin = if f.size().height < SMALL_HEIGHT { 0 } else { 2 };
+                let area = Layout::default()
+                    .margin(margin)
+                    .constraints([Constraint::Percentage(100)].as_ref())
+                    .split(f.size())[0];
+                let all_hosts: Vec<_> = (0..hosts.len()).collect();
+                let mut chart_area = if panes.is_empty() {
+                    let (rows, rest) = split_rows(area, hosts.len());
+                    screen.draw_headers(f, &rows, &all_hosts);
+                    rest
+                } else {
+                    area
+                };
+
+                let title = match silenced_until {
+                    Some(until) => format!(
+                        "alerts silenced for {}m",
+                        until.saturating_duration_since(Instant::now()).as_secs() / 60 + 1
+                    ),
+                    None => String::new(),
+                };
+                let title = if paused {
+                    format!("paused {}", title)
+                } else {
+                    title
                 };
-                terminal.draw(|f| {
-                    let chunks = Layout::default()
+
+                if show_events {
+                    let chart_layout = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints(
+                            [Constraint::Percentage(70), Constraint::Percentage(30)].as_ref(),
+                        )
+                        .split(chart_area);
+                    let visible = chart_layout[1].height.saturating_sub(2) as usize;
+                    let log = app
+                        .events
+                        .iter()
+                        .skip(app.events.len().saturating_sub(visible))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    chart_area = chart_layout[0];
+                    f.render_widget(
+                        Paragraph::new(log)
+                            .block(Block::default().borders(Borders::ALL).title("Events")),
+                        chart_layout[1],
+                    );
+                }
+                if show_goodput {
+                    let goodput_layout = Layout::default()
                         .direction(Direction::Vertical)
-                        .margin(2)
                         .constraints(
-                            iter::repeat(Constraint::Length(1))
-                                .take(num_threads)
-                                .chain(iter::once(Constraint::Percentage(10)))
-                                .collect::<Vec<_>>()
-                                .as_ref(),
+                            [Constraint::Percentage(75), Constraint::Percentage(25)].as_ref(),
                         )
-                        .split(f.size());
-                    let (hosts, action) = if let Some(ref watch_cmd) = args.watch {
-                        (vec![watch_cmd.to_string()], "Running")
-                    } else {
-                        (args.hosts.clone(), "Pinging")
-                    };
-
-                    for (((host_id, host), stats), &style) in
-                        hosts.iter().enumerate().zip(app.stats()).zip(&app.styles)
-                    {
-                        let header_layout = Layout::default()
-                            .direction(Direction::Horizontal)
-                            .constraints(
-                                [
-                                    Constraint::Percentage(25),
-                                    Constraint::Percentage(25),
-                                    Constraint::Percentage(25),
-                                    Constraint::Percentage(25),
-                                ]
-                                .as_ref(),
-                            )
-                            .split(chunks[host_id]);
-
-                        let mut ping_text = format!("{} {}", action, host);
-                        let real_host = match app.map_host_ip.get::<String>(&host) {
-                            Some(ip) => ip,
-                            _ => host,
-                        }
-                        .to_owned();
-                        let s = format!(" ({})", real_host);
-                        ping_text.push_str(&s.to_string());
-
-                        f.render_widget(Paragraph::new(ping_text).style(style), header_layout[0]);
-
-                        f.render_widget(
-                            Paragraph::new(format!(
-                                "min {:?}",
-                                Duration::from_micros(stats.minimum().unwrap_or(0))
-                            ))
-                            .style(style),
-                            header_layout[1],
-                        );
-                        f.render_widget(
-                            Paragraph::new(format!(
-                                "max {:?}",
-                                Duration::from_micros(stats.maximum().unwrap_or(0))
-                            ))
-                            .style(style),
-                            header_layout[2],
-                        );
-                        f.render_widget(
-                            Paragraph::new(format!(
-                                "p95 {:?}",
-                                Duration::from_micros(stats.percentile(95.0).unwrap_or(0))
-                            ))
-                            .style(style),
-                            header_layout[3],
-                        );
+                        .split(chart_area);
+                    chart_area = goodput_layout[0];
+                    let goodput_bounds = app.goodput_bounds();
+                    let mut datasets = vec![];
+                    if args.throughput.is_some() {
+                        let goodput = Dataset::default()
+                            .marker(app.marker)
+                            .style(Style::default().fg(Color::White))
+                            .graph_type(GraphType::Line)
+                            .data(app.goodput.as_slice());
+                        // Only tell lines apart when there's more than one
+                        datasets.push(if iface_labels.is_empty() {
+                            goodput
+                        } else {
+                            goodput.name("goodput")
+                        });
                     }
-
-                    let datasets: Vec<_> = app
-                        .data
+                    let lines = iface_labels
                         .iter()
-                        .zip(&app.styles)
-                        .map(|(data, &style)| {
+                        .flatten()
+                        .zip(app.iface_rates.iter().flatten());
+                    for (line, (label, rates)) in lines.enumerate() {
+                        datasets.push(
                             Dataset::default()
-                                .marker(symbols::Marker::Braille)
-                                .style(style)
+                                .name(label.as_str())
+                                .marker(app.marker)
+                                .style(iface_style(line))
                                 .graph_type(GraphType::Line)
-                                .data(data.as_slice())
-                        })
-                        .collect();
-
-                    let y_axis_bounds = app.y_axis_bounds();
-
-                    let chart = Chart::new(datasets)
-                        .block(Block::default().borders(Borders::NONE))
+                                .data(rates.as_slice()),
+                        );
+                    }
+                    let goodput_chart = Chart::new(datasets)
+                        .block(Block::default().borders(Borders::TOP).title(rates_title))
                         .x_axis(
                             Axis::default()
                                 .style(Style::default().fg(Color::Gray))
@@ -361,11 +2831,320 @@ fn main() -> Result<()> {
                         .y_axis(
                             Axis::default()
                                 .style(Style::default().fg(Color::Gray))
-                                .bounds(y_axis_bounds)
-                                .labels(app.y_axis_labels(y_axis_bounds)),
+                                .bounds(goodput_bounds)
+                                .labels(
+                                    goodput_bounds
+                                        .iter()
+                                        .map(|&rate| Span::raw(throughput::format_rate(rate)))
+                                        .collect(),
+                                ),
                         );
-                    f.render_widget(chart, chunks[num_threads]);
-                })?;
+                    f.render_widget(goodput_chart, goodput_layout[1]);
+                }
+
+                if panes.is_empty() {
+                    screen.draw_chart(f, chart_area, &all_hosts, title);
+                    return;
+                }
+                let cells = dashboard::grid(chart_area, panes.len(), dashboard_columns);
+                for (pane, cell) in panes.iter().zip(cells) {
+                    let block = Block::default()
+                        .borders(Borders::ALL)
+                        .title(format!("{} {}", pane.title, title).trim_end().to_string());
+                    let inner = block.inner(cell);
+                    f.render_widget(block, cell);
+                    match pane.view {
+                        config::PaneView::Chart => {
+                            let (rows, rest) = split_rows(inner, pane.host_ids.len());
+                            screen.draw_headers(f, &rows, &pane.host_ids);
+                            screen.draw_chart(f, rest, &pane.host_ids, String::new());
+                        }
+                        config::PaneView::Stats => screen.draw_stats(f, inner, &pane.host_ids),
+                    }
+                }
+            })?;
+            if bell.due(Instant::now()) {
+                let backend = terminal.backend_mut();
+                backend.write_all(b"\x07")?;
+                backend.flush()?;
+            }
+        }
+        // Wake up for the next ring of the bell even without any events
+        let event = match bell.next() {
+            Some(at) => match rx.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(mpsc::RecvError.into()),
+            },
+            None => rx.recv()?,
+        };
+        if let Some(slept) = sleep.check() {
+            // Mark where the machine was suspended rather than letting it
+            // read as a host that stopped replying
+            consecutive_failures
+                .values_mut()
+                .for_each(|failures| *failures = 0);
+            for host_id in 0..hosts.len() {
+                app.annotate(host_id, "asleep".to_string());
+            }
+            app.log_event(format!("Resumed after sleeping for {}s", slept.as_secs()));
+        }
+        match event {
+            // Replies that arrive while paused or between windows are dropped
+            Event::Update(..) if paused || (scheduled && window.is_none()) => {}
+            // Hosts that reached --count wait for the others
+            Event::Update(host_id, _)
+                if args
+                    .count
+                    .is_some_and(|count| app.totals[host_id].sent >= count) => {}
+            Event::Update(host_id, update) => {
+                let sample = match update {
+                    Update::Result(duration) => Some(duration),
+                    Update::Timeout | Update::Failed(_) => None,
+                };
+                if let Some(rtt) = sample {
+                    bufferbloat.record(host_id, rtt);
+                }
+                if let Some(failures) = consecutive_failures.get_mut(&host_id) {
+                    if sample.is_some() {
+                        *failures = 0;
+                    } else {
+                        *failures += 1;
+                    }
+                }
+                app.update(host_id, sample);
+                if let Some((_, ref mut totals)) = window {
+                    totals[host_id].record(sample);
+                }
+                let status = update.status();
+                let ip = app.host_ips[host_id].clone();
+                let logged_sample = samplelog::Sample {
+                    time: Local::now(),
+                    target: &hosts[host_id],
+                    ip: ip.as_deref(),
+                    seq: app.totals[host_id].sent,
+                    latency: sample,
+                    status: &status,
+                };
+                if let Some(ref mut log) = sample_log {
+                    if let Err(e) = log.write(&logged_sample) {
+                        app.log_event(format!("Could not write to the log: {}", e));
+                    }
+                }
+                if let Some(ref mut recorder) = recorder {
+                    if let Err(e) = recorder.record(host_id, &logged_sample) {
+                        app.log_event(format!("Could not record the sample: {}", e));
+                    }
+                }
+                if let Some(ref mut timeline) = timeline {
+                    timeline.push(host_id, logged_sample.time, sample);
+                }
+                if let Some(ref mut output) = sample_output {
+                    // Nobody's reading any more, like jq having quit
+                    if output.write(&logged_sample).is_err() && samples_to_stdout {
+                        killed.store(true, Ordering::Release);
+                        break;
+                    }
+                }
+                if let Update::Failed(failure) = update {
+                    app.record_failure(host_id, failure);
+                }
+                let mut events = vec![];
+                let host_stats = app.stats().swap_remove(host_id);
+                let stats = sinks::Stats {
+                    min: host_stats.minimum().unwrap_or(0),
+                    max: host_stats.maximum().unwrap_or(0),
+                    p95: host_stats.percentile(95.0).unwrap_or(0),
+                };
+                for (rule, transition) in alerts.sample(host_id, sample, Instant::now()) {
+                    let (kind, message) = match transition {
+                        alert::Transition::Triggered => (sinks::Kind::Triggered, "Breached"),
+                        alert::Transition::Resolved => (sinks::Kind::Resolved, "Cleared"),
+                    };
+                    events.push(sinks::AlertEvent {
+                        dedup_key: format!("gping/{}/{}", hosts[host_id], rule),
+                        host: hosts[host_id].clone(),
+                        kind,
+                        severity: rule.severity(),
+                        message: format!("{} {}", message, rule),
+                        stats,
+                    });
+                }
+                if let Some(status) = reachability.sample(host_id, sample) {
+                    let (kind, severity, message) = match status {
+                        alert::Status::Up => (
+                            sinks::Kind::Up,
+                            alert::Severity::Info,
+                            "Replies have resumed",
+                        ),
+                        alert::Status::Down => (
+                            sinks::Kind::Down,
+                            alert::Severity::Critical,
+                            "Stopped replying",
+                        ),
+                    };
+                    events.push(sinks::AlertEvent {
+                        dedup_key: format!("gping/{}/down", hosts[host_id]),
+                        host: hosts[host_id].clone(),
+                        kind,
+                        severity,
+                        message: message.to_string(),
+                        stats,
+                    });
+                }
+                for event in &events {
+                    app.log_event(format!(
+                        "{} {}: {}",
+                        event.host,
+                        event.kind.as_str(),
+                        event.message
+                    ));
+                }
+                if silenced_until.is_some_and(|until| Instant::now() >= until) {
+                    silenced_until = None;
+                }
+                if silenced_until.is_none() {
+                    // The bell would end up in the samples written to stdout
+                    if !headless
+                        && events
+                            .iter()
+                            .any(|event| event.kind == sinks::Kind::Triggered)
+                    {
+                        // Each host has its own rhythm, to tell which one it is by ear
+                        bell.ring(host_id, Instant::now());
+                    }
+                    for event in events {
+                        sinks.send(event);
+                    }
+                }
+                if let Some(max_failures) = args.watch_max_failures {
+                    if consecutive_failures.get(&host_id) >= Some(&max_failures) {
+                        killed.store(true, Ordering::Release);
+                        exit_failure = Some(host_id);
+                        break;
+                    }
+                }
+                if let Some(count) = args.count {
+                    if app.totals.iter().all(|totals| totals.sent >= count) {
+                        killed.store(true, Ordering::Release);
+                        break;
+                    }
+                }
+            }
+            Event::Annotation(host_id, label) => {
+                app.log_event(format!("{}: {}", hosts[host_id], label));
+                app.annotate(host_id, label);
+            }
+            Event::ResolveTime(host_id, elapsed) => app.resolve_times[host_id] = Some(elapsed),
+            Event::Band(..) if paused || (scheduled && window.is_none()) => {}
+            Event::Band(host_id, min, max) => app.record_band(host_id, min, max),
+            Event::Transport(host_id, mechanism) => app.transports[host_id] = Some(mechanism),
+            Event::Status(host_id, status) => app.statuses[host_id] = Some(status),
+            Event::Output(host_id, line) => app.outputs[host_id] = Some(line),
+            Event::ExitCode(host_id, code) => {
+                app.log_event(format!("{} exited with {}", hosts[host_id], code));
+                if args.watch_fail_exit {
+                    killed.store(true, Ordering::Release);
+                    exit_code = Some((host_id, code));
+                    break;
+                }
+            }
+            Event::Throughput(Ok(transfer)) => app.record_goodput(transfer.bits_per_sec()),
+            Event::Throughput(Err(e)) => {
+                app.record_goodput(0f64);
+                app.log_event(format!("Throughput measurement failed: {}", e));
+            }
+            Event::IfaceRate(iface_id, rx, tx) => app.record_iface_rate(iface_id, rx, tx),
+            Event::Phase(phase) => {
+                if phase == bufferbloat::Phase::Idle {
+                    for (host_id, host) in hosts.iter().enumerate() {
+                        if let Some(report) = bufferbloat.report(host_id) {
+                            app.log_event(format!(
+                                "{}: idle {:?}, loaded {:?}, grade {}",
+                                host,
+                                report.idle,
+                                report.load,
+                                report.grade()
+                            ));
+                        }
+                    }
+                }
+                bufferbloat.set_phase(phase);
+                // Every host's latency is graded against the phases
+                app.annotate_all(
+                    match phase {
+                        bufferbloat::Phase::Idle => "idle",
+                        bufferbloat::Phase::Load => "load",
+                    }
+                    .to_string(),
+                );
+            }
+            Event::AddSource(host, source) => {
+                let host_id = hosts.len();
+                app.add_host();
+                if let Some((_, ref mut totals)) = window {
+                    totals.push(Totals::new());
+                }
+                if let Some(ref dashboard) = dashboard_config {
+                    dashboard::add_host(&mut panes, dashboard, host_id, &host);
+                }
+                alerts.add_host(host.clone());
+                reachability.add_host();
+                bufferbloat.add_host();
+                if !source.resolves() {
+                    app.host_ips[host_id] = Some(host.clone());
+                }
+                let target = Arc::new(Mutex::new(app.host_ips[host_id].clone()));
+                targets.push(Arc::clone(&target));
+                let thread = spawn_pinger(
+                    &pinger,
+                    host_id,
+                    host.clone(),
+                    target,
+                    probing.clone(),
+                    Arc::clone(&source),
+                );
+                threads.push((host.clone(), thread));
+                app.log_event(format!("Added {}", host));
+                hosts.push(host);
+                actions.push(source.action());
+            }
+            Event::Control(command) => match command {
+                control::Command::Annotate(label) => {
+                    app.log_event(label.clone());
+                    app.annotate(0, label);
+                }
+                control::Command::AddHost(host) => {
+                    key_tx.send(Event::AddSource(host.clone(), source::of(&host)))?;
+                }
+                control::Command::Pause => {
+                    paused = true;
+                    app.log_event("Paused".to_string());
+                }
+                control::Command::Resume => {
+                    paused = false;
+                    app.log_event("Resumed".to_string());
+                }
+            },
+            Event::Resolved(host_id, ip) => {
+                let label = format!("now resolves to {}", ip);
+                app.log_event(format!("{}: {}", hosts[host_id], label));
+                app.annotate(host_id, label);
+                app.host_ips[host_id] = Some(ip);
+            }
+            Event::Finished => {
+                killed.store(true, Ordering::Release);
+                break;
+            }
+            Event::Window(true) => {
+                pinger.idle.store(false, Ordering::Release);
+                window = Some((Local::now(), hosts.iter().map(|_| Totals::new()).collect()));
+            }
+            Event::Window(false) => {
+                pinger.idle.store(true, Ordering::Release);
+                if let Some((opened, totals)) = window.take() {
+                    report_window(opened, &totals, &hosts, reports.as_deref());
+                }
             }
             Event::Input(input) => match input.code {
                 KeyCode::Char('q') | KeyCode::Esc => {
@@ -376,22 +3155,134 @@ fn main() -> Result<()> {
                     killed.store(true, Ordering::Release);
                     break;
                 }
+                KeyCode::Char('e') => show_events = !show_events,
+                KeyCode::Char('x') => {
+                    let path = PathBuf::from(format!(
+                        "gping-{}.png",
+                        Local::now().format("%Y%m%d-%H%M%S")
+                    ));
+                    match export::write(&app.export_chart(&hosts), &path) {
+                        Ok(()) => app.log_event(format!("Saved the chart to {}", path.display())),
+                        Err(e) => app.log_event(e.to_string()),
+                    }
+                }
+                KeyCode::Char('s') => {
+                    silenced_until = match silenced_until {
+                        Some(_) => None,
+                        None => Some(Instant::now() + args.silence.unwrap_or(DEFAULT_SILENCE)),
+                    };
+                }
+                KeyCode::Char(key) => {
+                    if let Some(ref controls) = replay_controls {
+                        match key {
+                            ' ' if controls.toggle_pause() => {
+                                app.log_event("Replay paused".to_string())
+                            }
+                            ' ' => app.log_event("Replay resumed".to_string()),
+                            '+' => app.log_event(format!("Replaying at {}x", controls.faster())),
+                            '-' => app.log_event(format!("Replaying at {}x", controls.slower())),
+                            'j' => {
+                                controls.jump(REPLAY_JUMP);
+                                app.log_event(format!("Jumped {:?} ahead", REPLAY_JUMP));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
                 _ => {}
             },
         }
     }
 
-    for thread in threads {
-        thread.join().unwrap()?;
+    if let Some(ref mut terminal) = terminal {
+        disable_raw_mode()?;
+        execute!(
+            terminal.backend_mut(),
+            LeaveAlternateScreen,
+            DisableMouseCapture
+        )?;
+        terminal.show_cursor()?;
     }
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    // One target failing shouldn't lose the summary of the others
+    for (name, thread) in threads {
+        match thread.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("{}: {}", name, e),
+            Err(_) => eprintln!("{}: the thread panicked", name),
+        }
+    }
+
+    if let Some((watch_id, code)) = exit_code {
+        println!("{} exited with {}, stopping", hosts[watch_id], code);
+    }
+    if let Some(ref path) = args.export_on_exit {
+        export::write(&app.export_chart(&hosts), path)?;
+    }
+    // Quitting closes the window that's open early
+    if let Some((opened, totals)) = window.take() {
+        report_window(opened, &totals, &hosts, reports.as_deref());
+    }
+    let rows: Vec<_> = app
+        .totals
+        .iter()
+        .zip(&hosts)
+        .map(|(totals, host)| totals.row(host))
+        .collect();
+    let elapsed = session_start.elapsed();
+    let table = summary::table(&rows, elapsed);
+    // Standard output is left to the samples or the report for jq and the like
+    if samples_to_stdout || args.report_json.as_deref() == Some("-") {
+        eprintln!("{}", table);
+    } else {
+        println!("{}", table);
+    }
+    match args.report_json.as_deref() {
+        Some("-") => println!("{:#}", summary::report(&rows, elapsed)),
+        Some(path) => std::fs::write(path, format!("{:#}\n", summary::report(&rows, elapsed)))
+            .map_err(|e| anyhow!("Could not write the report to {}: {}", path, e))?,
+        None => {}
+    }
+    if let (Some(path), Some(timeline)) = (&args.html_report, &timeline) {
+        let page = html::report(&rows, timeline, session_started, elapsed);
+        std::fs::write(path, page)
+            .map_err(|e| anyhow!("Could not write the report to {}: {}", path.display(), e))?;
+    }
+
+    if args.bufferbloat {
+        for (host_id, host) in hosts.iter().enumerate() {
+            match bufferbloat.report(host_id) {
+                Some(report) => println!(
+                    "{}: idle {:?}, loaded {:?}, +{:?}, grade {}",
+                    host,
+                    report.idle,
+                    report.load,
+                    report.increase(),
+                    report.grade()
+                ),
+                None => println!("{}: not enough replies to grade", host),
+            }
+        }
+    }
+
+    if let Some(watch_id) = exit_failure {
+        let stats = app.stats().swap_remove(watch_id);
+        println!(
+            "{} failed {} times in a row, stopping",
+            hosts[watch_id], consecutive_failures[&watch_id]
+        );
+        println!(
+            "{} runs, min {:?}, max {:?}, p95 {:?}",
+            app.idx[watch_id],
+            Duration::from_micros(stats.minimum().unwrap_or(0)),
+            Duration::from_micros(stats.maximum().unwrap_or(0)),
+            Duration::from_micros(stats.percentile(95.0).unwrap_or(0))
+        );
+        std::process::exit(1);
+    }
+    if let Some((_, code)) = exit_code {
+        std::process::exit(code);
+    }
 
     Ok(())
 }
@@ -0,0 +1,38 @@
+use crate::clock::SleepDetector;
+use crate::probe::PingResult;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Take a sample with `sample` every `interval` from a new thread, until the
+/// receiver is dropped. Samples taken across a sleep of the machine are
+/// dropped, like the probes' are.
+pub fn every(
+    interval: Duration,
+    mut sample: impl FnMut() -> PingResult + Send + 'static,
+) -> mpsc::Receiver<PingResult> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let result = sample();
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    rx
+}
+
+/// A number of milliseconds as a sample. Negative or missing numbers, and
+/// NaN, count as no answer.
+pub fn millis(value: Option<f64>) -> PingResult {
+    match value {
+        Some(value) if value.is_finite() && value >= 0.0 => {
+            PingResult::Pong(Duration::from_micros((value * 1000.0).round() as u64))
+        }
+        _ => PingResult::Timeout,
+    }
+}
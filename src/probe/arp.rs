@@ -0,0 +1,94 @@
+use super::detect::find_program;
+use super::{Options, PingResult};
+use crate::clock::SleepDetector;
+use anyhow::{anyhow, Result};
+use std::net::Ipv4Addr;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time an ARP request for `addr` every interval by running `arping` once
+/// per probe. Like a raw socket, arping needs root or CAP_NET_RAW. Only the
+/// interface of `options` applies, and arping gives up on a reply after about
+/// a second whatever `--timeout` says.
+pub fn ping(addr: Ipv4Addr, options: &Options) -> Result<mpsc::Receiver<PingResult>> {
+    find_program("arping").map_err(|e| anyhow!("Can't ARP ping {}: {}", addr, e))?;
+    let interface = options.interface.clone();
+    let interval = options.interval;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let mut cmd = Command::new("arping");
+            cmd.env("LC_ALL", "C").args(["-c", "1"]);
+            if let Some(ref interface) = interface {
+                cmd.args(["-I", interface]);
+            }
+            let output = cmd
+                .arg(addr.to_string())
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output();
+            let rtt = output.ok().and_then(|output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .find_map(parse_reply)
+            });
+            let result = match rtt {
+                Some(rtt) => PingResult::Pong(rtt),
+                None => PingResult::Timeout,
+            };
+            // A reply that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    Ok(rx)
+}
+
+/// The round trip in a reply line from iputils' arping, which ends like
+/// `[00:11:22:33:44:55]  0.712ms`, or from Thomas Habets' arping, which ends
+/// like `index=0 time=712.345 usec`.
+fn parse_reply(line: &str) -> Option<Duration> {
+    let line = line.trim_end();
+    let (number, micros) = if let Some(number) = line.strip_suffix("usec") {
+        (number, 1.0)
+    } else if let Some(number) = line.strip_suffix("msec") {
+        (number, 1000.0)
+    } else if let Some(number) = line.strip_suffix("ms") {
+        (number, 1000.0)
+    } else {
+        return None;
+    };
+    let number = number.trim_end();
+    let start = number.rfind([' ', '='])? + 1;
+    let value: f64 = number[start..].parse().ok()?;
+    Some(Duration::from_micros((value * micros).round() as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_reply;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_parse_reply() {
+        assert_eq!(
+            parse_reply("Unicast reply from 192.168.1.1 [00:11:22:33:44:55]  0.712ms"),
+            Some(Duration::from_micros(712))
+        );
+        assert_eq!(
+            parse_reply("60 bytes from 00:11:22:33:44:55 (192.168.1.1): index=0 time=712 usec"),
+            Some(Duration::from_micros(712))
+        );
+        assert_eq!(
+            parse_reply("ARPING 192.168.1.1 from 192.168.1.2 eth0"),
+            None
+        );
+        assert_eq!(parse_reply("Received 0 response(s)"), None);
+    }
+}
@@ -0,0 +1,219 @@
+use super::Flavor;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::env;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    pub fn matches(self, ip: &IpAddr) -> bool {
+        match self {
+            Family::V4 => ip.is_ipv4(),
+            Family::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Family::V4 => write!(f, "IPv4"),
+            Family::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// A way of sending ICMP echo requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mechanism {
+    /// A raw ICMP socket, which needs root or CAP_NET_RAW
+    Raw,
+    /// An ICMP datagram socket, allowed unprivileged on macOS and on Linux
+    /// within net.ipv4.ping_group_range
+    Datagram,
+    /// Windows' IcmpSendEcho API, which needs no privileges
+    IcmpApi,
+    /// Running the platform's ping binary and parsing its output
+    Subprocess,
+}
+
+impl fmt::Display for Mechanism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mechanism::Raw => write!(f, "raw socket"),
+            Mechanism::Datagram => write!(f, "ICMP datagram socket"),
+            Mechanism::IcmpApi => write!(f, "Windows ICMP API"),
+            Mechanism::Subprocess => write!(f, "ping binary"),
+        }
+    }
+}
+
+/// Whether a mechanism works for an address family, and why not if it doesn't.
+#[derive(Debug, Clone)]
+pub struct Capability {
+    pub family: Family,
+    pub mechanism: Mechanism,
+    pub error: Option<String>,
+}
+
+/// Mechanisms this platform has.
+#[cfg(windows)]
+const MECHANISMS: &[Mechanism] = &[
+    Mechanism::IcmpApi,
+    Mechanism::Raw,
+    Mechanism::Datagram,
+    Mechanism::Subprocess,
+];
+#[cfg(not(windows))]
+const MECHANISMS: &[Mechanism] = &[Mechanism::Raw, Mechanism::Datagram, Mechanism::Subprocess];
+
+/// Try every mechanism for both address families.
+pub fn detect(flavor: Flavor) -> Vec<Capability> {
+    let mut capabilities = vec![];
+    for &family in &[Family::V4, Family::V6] {
+        for &mechanism in MECHANISMS {
+            let error = match mechanism {
+                Mechanism::Raw => socket_works(family, Type::raw()),
+                Mechanism::Datagram => socket_works(family, Type::dgram()),
+                Mechanism::IcmpApi => icmp_api_works(family),
+                Mechanism::Subprocess => find_program(flavor.program(family == Family::V6)),
+            }
+            .err();
+            capabilities.push(Capability {
+                family,
+                mechanism,
+                error,
+            });
+        }
+    }
+    capabilities
+}
+
+/// The first working mechanism for a family out of `preference`.
+pub fn choose(
+    capabilities: &[Capability],
+    family: Family,
+    preference: &[Mechanism],
+) -> Option<Mechanism> {
+    preference.iter().copied().find(|&mechanism| {
+        capabilities
+            .iter()
+            .any(|c| c.family == family && c.mechanism == mechanism && c.error.is_none())
+    })
+}
+
+/// Summarise how a family will be pinged, and what was wrong with anything
+/// that was ruled out, e.g. `IPv4 via ping binary (raw socket: Operation not
+/// permitted)`.
+pub fn describe(capabilities: &[Capability], family: Family, preference: &[Mechanism]) -> String {
+    let problems = capabilities
+        .iter()
+        .filter(|c| c.family == family && preference.contains(&c.mechanism))
+        .filter_map(|c| Some(format!("{}: {}", c.mechanism, c.error.as_ref()?)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let chosen = match choose(capabilities, family, preference) {
+        Some(mechanism) => format!("{} via {}", family, mechanism),
+        None => format!("{} can't be pinged", family),
+    };
+    if problems.is_empty() {
+        chosen
+    } else {
+        format!("{} ({})", chosen, problems)
+    }
+}
+
+pub(super) fn open_socket(family: Family, kind: Type) -> io::Result<Socket> {
+    let (domain, protocol) = match family {
+        Family::V4 => (Domain::ipv4(), Protocol::icmpv4()),
+        Family::V6 => (Domain::ipv6(), Protocol::icmpv6()),
+    };
+    Socket::new(domain, kind, Some(protocol))
+}
+
+fn socket_works(family: Family, kind: Type) -> Result<(), String> {
+    open_socket(family, kind)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(windows)]
+fn icmp_api_works(family: Family) -> Result<(), String> {
+    super::windows::open(family == Family::V6)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(windows))]
+fn icmp_api_works(_family: Family) -> Result<(), String> {
+    Err("only available on Windows".to_string())
+}
+
+pub(super) fn find_program(program: &str) -> Result<(), String> {
+    if Path::new(program).is_absolute() {
+        return if Path::new(program).exists() {
+            Ok(())
+        } else {
+            Err(format!("{} does not exist", program))
+        };
+    }
+    let path = env::var_os("PATH").unwrap_or_default();
+    if env::split_paths(&path).any(|dir| dir.join(program).is_file()) {
+        Ok(())
+    } else {
+        Err(format!("{} not found on PATH", program))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{choose, describe, Capability, Family, Mechanism};
+
+    #[test]
+    pub fn test_choose() {
+        let capabilities = vec![
+            Capability {
+                family: Family::V4,
+                mechanism: Mechanism::Raw,
+                error: Some("Operation not permitted".to_string()),
+            },
+            Capability {
+                family: Family::V4,
+                mechanism: Mechanism::Subprocess,
+                error: None,
+            },
+            Capability {
+                family: Family::V6,
+                mechanism: Mechanism::Subprocess,
+                error: Some("ping6 not found on PATH".to_string()),
+            },
+        ];
+        let native = [Mechanism::Raw, Mechanism::Datagram, Mechanism::Subprocess];
+        let plain = [Mechanism::Subprocess];
+        assert_eq!(
+            choose(&capabilities, Family::V4, &native),
+            Some(Mechanism::Subprocess)
+        );
+        assert_eq!(choose(&capabilities, Family::V6, &native), None);
+        assert_eq!(
+            describe(&capabilities, Family::V4, &native),
+            "IPv4 via ping binary (raw socket: Operation not permitted)"
+        );
+        // Mechanisms that weren't asked for aren't mentioned
+        assert_eq!(
+            describe(&capabilities, Family::V4, &plain),
+            "IPv4 via ping binary"
+        );
+        assert_eq!(
+            describe(&capabilities, Family::V6, &plain),
+            "IPv6 can't be pinged (ping binary: ping6 not found on PATH)"
+        );
+    }
+}
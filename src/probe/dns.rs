@@ -0,0 +1,41 @@
+use super::{Options, PingResult};
+use crate::clock::SleepDetector;
+use crate::resolve::Server;
+use anyhow::Result;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+use trust_dns_resolver::error::ResolveErrorKind;
+
+/// Look `name` up on `server` every interval and time each answer. Only the
+/// timeout of `options` applies.
+pub fn ping(
+    name: String,
+    server: &Server,
+    options: &Options,
+) -> Result<mpsc::Receiver<PingResult>> {
+    let interval = options.interval;
+    // Like the other engines, give up on a query when the next one is due
+    let resolver = server.timing_resolver(options.timeout.unwrap_or(interval))?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let result = match resolver.lookup_ip(name.as_str()) {
+                Ok(_) => PingResult::Pong(started.elapsed()),
+                // The server still answered, just without any addresses
+                Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                    PingResult::Pong(started.elapsed())
+                }
+                Err(_) => PingResult::Timeout,
+            };
+            // An answer that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    Ok(rx)
+}
@@ -0,0 +1,64 @@
+use super::{Failure, Options, PingResult};
+use crate::clock::SleepDetector;
+use std::io::{self, Read};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bytes of each response body read before the rest is dropped with the
+/// connection, so a large page doesn't turn into a throughput test.
+const MAX_BODY: u64 = 1024 * 1024;
+
+/// Fetch `url` every interval and time each request from sending it to the
+/// last byte of the body. `on_status` is told the status code of every
+/// response, 5xx responses count as failures. Only the timeout of `options`
+/// applies.
+pub fn ping(
+    url: String,
+    options: &Options,
+    on_status: impl Fn(u16) + Send + 'static,
+) -> mpsc::Receiver<PingResult> {
+    let interval = options.interval;
+    // Like the other engines, give up on a request when the next one is due
+    let timeout = options.timeout.unwrap_or(interval);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let result = match fetch(&url, timeout) {
+                Some((status, rtt)) => {
+                    on_status(status);
+                    if status >= 500 {
+                        PingResult::Failed(Failure::ServerError)
+                    } else {
+                        PingResult::Pong(rtt)
+                    }
+                }
+                None => PingResult::Timeout,
+            };
+            // A request that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    rx
+}
+
+/// Make one request and return its status and how long it took. Each request
+/// gets a new connection, so the time includes connecting and any TLS
+/// handshake like a `curl` would.
+fn fetch(url: &str, timeout: Duration) -> Option<(u16, Duration)> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    let started = Instant::now();
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(ureq::Error::Transport(_)) => return None,
+    };
+    let status = response.status();
+    io::copy(&mut response.into_reader().take(MAX_BODY), &mut io::sink()).ok()?;
+    Some((status, started.elapsed()))
+}
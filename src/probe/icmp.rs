@@ -0,0 +1,475 @@
+use super::detect::{open_socket, Family, Mechanism};
+use super::{Failure, Options, PingResult};
+use crate::clock::SleepDetector;
+use anyhow::{anyhow, Result};
+use socket2::{SockAddr, Socket, Type};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+const UNREACHABLE_V4: u8 = 3;
+const TIME_EXCEEDED_V4: u8 = 11;
+/// The destination unreachable code for "fragmentation needed and DF set"
+const FRAG_NEEDED_V4: u8 = 4;
+const UNREACHABLE_V6: u8 = 1;
+const TOO_BIG_V6: u8 = 2;
+const TIME_EXCEEDED_V6: u8 = 3;
+
+/// Sent in every request so replies to other programs' pings are ignored.
+pub(super) const PAYLOAD: &[u8] = b"gping echo probe";
+
+/// Raw sockets see every reply to the machine, so each pinger needs its own
+/// identifier.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Ping `addr` over the first kind of ICMP socket in `options.mechanisms()`
+/// that can be opened.
+pub fn ping(addr: IpAddr, options: &Options) -> Result<(Mechanism, mpsc::Receiver<PingResult>)> {
+    let family = if addr.is_ipv6() {
+        Family::V6
+    } else {
+        Family::V4
+    };
+    let (socket, mechanism) = options
+        .mechanisms()
+        .iter()
+        .find_map(|&mechanism| {
+            let kind = match mechanism {
+                Mechanism::Raw => Type::raw(),
+                Mechanism::Datagram => Type::dgram(),
+                Mechanism::IcmpApi | Mechanism::Subprocess => return None,
+            };
+            let socket = open_socket(family, kind).ok()?;
+            Some((socket, mechanism))
+        })
+        .ok_or_else(|| anyhow!("Could not open an ICMP socket for {}", addr))?;
+    if let Some(source) = options.source {
+        socket.bind(&SockAddr::from(SocketAddr::new(source, 0)))?;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if mechanism == Mechanism::Datagram {
+            errqueue::enable(&socket, family)?;
+        }
+    }
+    if let Some(ttl) = options.ttl {
+        match family {
+            Family::V4 => socket.set_ttl(ttl.into())?,
+            Family::V6 => socket.set_unicast_hops_v6(ttl.into())?,
+        }
+    }
+    let id = (process::id() as usize).wrapping_add(NEXT_ID.fetch_add(1, Ordering::Relaxed)) as u16;
+    let interval = options.interval;
+    // Like `ping -O`, give up on a probe when the next one is due
+    let timeout = options.timeout.unwrap_or(interval);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let target = SockAddr::from(SocketAddr::new(addr, 0));
+        let mut in_flight: HashMap<u16, Instant> = HashMap::new();
+        let mut seq = 0u16;
+        let mut next_send = Instant::now();
+        let mut sleep = SleepDetector::new();
+        let mut buf = [0u8; 1500];
+        loop {
+            if sleep.check().is_some() {
+                // Their round trips would include the sleep
+                in_flight.clear();
+            }
+            let now = Instant::now();
+            let mut results = vec![];
+            in_flight.retain(|_, sent| {
+                let waiting = now.saturating_duration_since(*sent) < timeout;
+                if !waiting {
+                    results.push(PingResult::Timeout);
+                }
+                waiting
+            });
+            if now >= next_send {
+                let request = echo_request(family, id, seq);
+                // Errors like "network unreachable" count as lost probes
+                match socket.send_to(&request, &target) {
+                    Ok(_) => {
+                        in_flight.insert(seq, now);
+                    }
+                    Err(_) => results.push(PingResult::Timeout),
+                }
+                seq = seq.wrapping_add(1);
+                next_send = (next_send + interval).max(now);
+            }
+            for result in results {
+                if tx.send(result).is_err() {
+                    return;
+                }
+            }
+            let wait = in_flight
+                .values()
+                .map(|sent| (*sent + timeout).saturating_duration_since(now))
+                .fold(next_send.saturating_duration_since(now), Duration::min)
+                .max(Duration::from_millis(1));
+            if let Some((sent, failure)) =
+                receive(&socket, &mut buf, wait, addr, family, mechanism, id)
+                    .and_then(|(seq, failure)| Some((in_flight.remove(&seq)?, failure)))
+            {
+                let result = match failure {
+                    Some(failure) => PingResult::Failed(failure),
+                    None => PingResult::Pong(sent.elapsed()),
+                };
+                if tx.send(result).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok((mechanism, rx))
+}
+
+/// Wait up to `wait` for a reply or ICMP error for one of our probes, and
+/// return its sequence number.
+fn receive(
+    socket: &Socket,
+    buf: &mut [u8],
+    wait: Duration,
+    addr: IpAddr,
+    family: Family,
+    mechanism: Mechanism,
+    id: u16,
+) -> Option<(u16, Option<Failure>)> {
+    socket.set_read_timeout(Some(wait)).ok()?;
+    let (len, from) = match socket.recv_from(buf) {
+        Ok(received) => received,
+        Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+            return None
+        }
+        Err(_) => {
+            // Datagram sockets on Linux fail a read when an ICMP error
+            // arrives, and leave the error itself in their error queue
+            #[cfg(target_os = "linux")]
+            {
+                if mechanism == Mechanism::Datagram {
+                    if let Some(error) = errqueue::receive(socket, family, buf) {
+                        return Some(error);
+                    }
+                }
+            }
+            // Don't spin on a socket that keeps failing
+            thread::sleep(wait);
+            return None;
+        }
+    };
+    let (reply_id, seq, failure) = parse_reply(family, &buf[..len])?;
+    // Errors come from routers on the way, replies only from the host
+    if failure.is_none() && from.as_std().map(|from| from.ip()) != Some(addr) {
+        return None;
+    }
+    // The kernel chooses the identifier for datagram sockets, and only
+    // passes them their own replies
+    if mechanism == Mechanism::Raw && reply_id != id {
+        return None;
+    }
+    Some((seq, failure))
+}
+
+fn echo_request(family: Family, id: u16, seq: u16) -> Vec<u8> {
+    let kind = match family {
+        Family::V4 => ECHO_REQUEST_V4,
+        Family::V6 => ECHO_REQUEST_V6,
+    };
+    let mut packet = vec![kind, 0, 0, 0];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+    // The kernel fills in the ICMPv6 checksum, which covers the IP addresses
+    if family == Family::V4 {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    packet
+}
+
+/// The identifier and sequence number of an echo reply carrying our payload,
+/// or of the request an ICMP error was sent back for.
+fn parse_reply(family: Family, buf: &[u8]) -> Option<(u16, u16, Option<Failure>)> {
+    let packet = strip_ip_header(family, buf)?;
+    let (kind, code) = (*packet.first()?, *packet.get(1)?);
+    if (family, kind) == (Family::V4, ECHO_REPLY_V4)
+        || (family, kind) == (Family::V6, ECHO_REPLY_V6)
+    {
+        if packet.get(8..8 + PAYLOAD.len())? != PAYLOAD {
+            return None;
+        }
+        return Some((
+            u16::from_be_bytes([packet[4], packet[5]]),
+            u16::from_be_bytes([packet[6], packet[7]]),
+            None,
+        ));
+    }
+    let failure = failure(family, kind, code)?;
+    // Errors quote the IP header and first 8 bytes of the request they're about
+    let request = match family {
+        Family::V4 => strip_ip_header(family, packet.get(8..)?)?,
+        Family::V6 => packet.get(8 + IPV6_HEADER_LEN..)?,
+    };
+    let (id, seq) = parse_request(family, request)?;
+    Some((id, seq, Some(failure)))
+}
+
+/// What an ICMP error of this type and code means for a probe.
+fn failure(family: Family, kind: u8, code: u8) -> Option<Failure> {
+    match (family, kind, code) {
+        (Family::V4, UNREACHABLE_V4, FRAG_NEEDED_V4) => Some(Failure::TooBig),
+        (Family::V4, UNREACHABLE_V4, _) | (Family::V6, UNREACHABLE_V6, _) => {
+            Some(Failure::Unreachable)
+        }
+        (Family::V4, TIME_EXCEEDED_V4, _) | (Family::V6, TIME_EXCEEDED_V6, _) => {
+            Some(Failure::TtlExceeded)
+        }
+        (Family::V6, TOO_BIG_V6, _) => Some(Failure::TooBig),
+        _ => None,
+    }
+}
+
+/// The identifier and sequence number of an echo request of ours, as quoted
+/// by an ICMP error.
+fn parse_request(family: Family, request: &[u8]) -> Option<(u16, u16)> {
+    let kind = match family {
+        Family::V4 => ECHO_REQUEST_V4,
+        Family::V6 => ECHO_REQUEST_V6,
+    };
+    if request.len() < 8 || request[0] != kind {
+        return None;
+    }
+    Some((
+        u16::from_be_bytes([request[4], request[5]]),
+        u16::from_be_bytes([request[6], request[7]]),
+    ))
+}
+
+/// Datagram sockets on Linux are only told of ICMP errors, like unreachable
+/// hosts or TTLs running out, through their error queue once asked to.
+#[cfg(target_os = "linux")]
+mod errqueue {
+    use super::{failure, parse_request, Failure, Family};
+    use socket2::Socket;
+    use std::io;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    /// Where in a `sock_extended_err` its origin, type and code are.
+    const ORIGIN: usize = 4;
+    const TYPE: usize = 5;
+    const CODE: usize = 6;
+
+    /// Ask for ICMP errors to be queued on `socket`.
+    pub fn enable(socket: &Socket, family: Family) -> io::Result<()> {
+        let (level, name) = match family {
+            Family::V4 => (libc::SOL_IP, libc::IP_RECVERR),
+            Family::V6 => (libc::SOL_IPV6, libc::IPV6_RECVERR),
+        };
+        let on: libc::c_int = 1;
+        let set = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &on as *const _ as *const libc::c_void,
+                mem::size_of_val(&on) as libc::socklen_t,
+            )
+        };
+        if set == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Take an ICMP error off the queue, returning the sequence number of
+    /// the probe it's about.
+    pub fn receive(
+        socket: &Socket,
+        family: Family,
+        buf: &mut [u8],
+    ) -> Option<(u16, Option<Failure>)> {
+        let mut control = [0u8; 256];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let mut message: libc::msghdr = unsafe { mem::zeroed() };
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        message.msg_controllen = control.len() as _;
+        let len = unsafe {
+            libc::recvmsg(
+                socket.as_raw_fd(),
+                &mut message,
+                libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+            )
+        };
+        if len < 0 {
+            return None;
+        }
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&message) };
+        while !cmsg.is_null() {
+            let (level, kind) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+            if (level, kind) == (libc::SOL_IP, libc::IP_RECVERR)
+                || (level, kind) == (libc::SOL_IPV6, libc::IPV6_RECVERR)
+            {
+                let data = unsafe {
+                    let start = libc::CMSG_DATA(cmsg);
+                    let end = (cmsg as *const u8).add((*cmsg).cmsg_len as usize);
+                    std::slice::from_raw_parts(start, end.offset_from(start) as usize)
+                };
+                return parse(family, data, &buf[..len as usize]);
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&message, cmsg) };
+        }
+        None
+    }
+
+    /// Parse a queued error, given the `sock_extended_err` it came with and
+    /// the start of the probe it's about.
+    pub fn parse(family: Family, error: &[u8], request: &[u8]) -> Option<(u16, Option<Failure>)> {
+        let origin = match family {
+            Family::V4 => libc::SO_EE_ORIGIN_ICMP,
+            Family::V6 => libc::SO_EE_ORIGIN_ICMP6,
+        };
+        if *error.get(ORIGIN)? != origin {
+            return None;
+        }
+        let failure = failure(family, *error.get(TYPE)?, *error.get(CODE)?)?;
+        let (_, seq) = parse_request(family, request)?;
+        Some((seq, Some(failure)))
+    }
+}
+
+const IPV6_HEADER_LEN: usize = 40;
+
+/// Skip the IPv4 header that raw sockets, and datagram sockets on macOS,
+/// include. IPv6 sockets never do.
+fn strip_ip_header(family: Family, buf: &[u8]) -> Option<&[u8]> {
+    match family {
+        Family::V4 if buf.first()? >> 4 == 4 => buf.get(usize::from(buf[0] & 0xf) * 4..),
+        _ => Some(buf),
+    }
+}
+
+/// The internet checksum from RFC 1071.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u32::from(pair[0]) << 8 | u32::from(*pair.get(1).unwrap_or(&0)))
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{checksum, echo_request, parse_reply, Failure, Family, ECHO_REPLY_V4};
+
+    #[test]
+    pub fn test_checksum() {
+        let request = echo_request(Family::V4, 0x1234, 7);
+        // A packet including its own checksum sums to zero
+        assert_eq!(checksum(&request), 0);
+        assert_eq!(checksum(&[0x00, 0x01, 0xf2]), !0xf201);
+    }
+
+    #[test]
+    pub fn test_parse_reply() {
+        let mut reply = echo_request(Family::V4, 0x1234, 7);
+        reply[0] = ECHO_REPLY_V4;
+        assert_eq!(parse_reply(Family::V4, &reply), Some((0x1234, 7, None)));
+
+        // As a raw socket receives it, after a 20 byte IP header
+        let mut with_header = vec![0x45];
+        with_header.extend_from_slice(&[0; 19]);
+        with_header.extend_from_slice(&reply);
+        assert_eq!(
+            parse_reply(Family::V4, &with_header),
+            Some((0x1234, 7, None))
+        );
+
+        // Our own request, looped back to a raw socket
+        let request = echo_request(Family::V4, 0x1234, 7);
+        assert_eq!(parse_reply(Family::V4, &request), None);
+
+        let mut reply = echo_request(Family::V6, 1, 2);
+        reply[0] = 129;
+        assert_eq!(parse_reply(Family::V6, &reply), Some((1, 2, None)));
+        reply.truncate(12);
+        assert_eq!(parse_reply(Family::V6, &reply), None);
+    }
+
+    #[test]
+    pub fn test_parse_error() {
+        // Time exceeded from a router, quoting our request's IP header and
+        // first 8 bytes
+        let mut error = vec![0x45];
+        error.extend_from_slice(&[0; 19]);
+        error.extend_from_slice(&[11, 0, 0, 0, 0, 0, 0, 0, 0x45]);
+        error.extend_from_slice(&[0; 19]);
+        error.extend_from_slice(&echo_request(Family::V4, 0x1234, 7)[..8]);
+        assert_eq!(
+            parse_reply(Family::V4, &error),
+            Some((0x1234, 7, Some(Failure::TtlExceeded)))
+        );
+        // Fragmentation needed
+        error[20..22].copy_from_slice(&[3, 4]);
+        assert_eq!(
+            parse_reply(Family::V4, &error),
+            Some((0x1234, 7, Some(Failure::TooBig)))
+        );
+
+        let mut error = vec![1, 3, 0, 0, 0, 0, 0, 0];
+        error.extend_from_slice(&[0x60; 40]);
+        error.extend_from_slice(&echo_request(Family::V6, 1, 2)[..8]);
+        assert_eq!(
+            parse_reply(Family::V6, &error),
+            Some((1, 2, Some(Failure::Unreachable)))
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    pub fn test_parse_queued_error() {
+        use super::errqueue;
+
+        // The sock_extended_err queued for a time exceeded error: errno
+        // EHOSTUNREACH, origin ICMP, type 11, code 0, then the router's
+        // address
+        let error = [
+            113, 0, 0, 0, 2, 11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 192, 168, 1, 1, 0, 0, 0,
+            0, 0, 0, 0, 0,
+        ];
+        // The probe it's about, with the identifier the kernel gave it
+        let mut request = echo_request(Family::V4, 0, 7);
+        request[4..6].copy_from_slice(&[0x0b, 0xad]);
+        assert_eq!(
+            errqueue::parse(Family::V4, &error, &request),
+            Some((7, Some(Failure::TtlExceeded)))
+        );
+        // Errors that came from this machine rather than an ICMP message
+        let mut local = error;
+        local[4] = 1;
+        assert_eq!(errqueue::parse(Family::V4, &local, &request), None);
+
+        let mut error = error;
+        error[4..7].copy_from_slice(&[3, 1, 3]);
+        assert_eq!(
+            errqueue::parse(Family::V6, &error, &echo_request(Family::V6, 0, 9)),
+            Some((9, Some(Failure::Unreachable)))
+        );
+    }
+}
@@ -0,0 +1,656 @@
+mod arp;
+mod detect;
+mod dns;
+mod http;
+mod icmp;
+mod parser;
+mod proxy;
+mod quic;
+mod ssh;
+mod tcp;
+mod tls;
+mod udp;
+#[cfg(windows)]
+mod windows;
+
+use crate::clock::SleepDetector;
+use crate::resolve;
+use anyhow::{anyhow, Result};
+pub use detect::{choose, describe, detect, Family, Mechanism};
+use parser::Parser;
+pub use proxy::Proxy;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+pub use ssh::Remote;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Once};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PingResult {
+    Pong(Duration),
+    Timeout,
+    /// An ICMP error or an HTTP server error came back instead of a reply
+    Failed(Failure),
+}
+
+/// Why a probe failed, from the ICMP error a router or the host sent back, the
+/// status of an HTTP response, or output a watched command printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    /// No route to the network or host, or it refused the probe
+    Unreachable,
+    /// The probe ran out of hops, see `--ttl`
+    TtlExceeded,
+    /// The probe was too big for a link on the way and `--df` stopped it
+    /// being fragmented
+    TooBig,
+    /// A URL target answered with a 5xx status
+    ServerError,
+    /// A watched command's output had no number where one was looked for
+    ParseError,
+    /// A watched command exited with a code other than 0
+    CommandFailed,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Unreachable => write!(f, "unreachable"),
+            Failure::TtlExceeded => write!(f, "ttl exceeded"),
+            Failure::TooBig => write!(f, "too big"),
+            Failure::ServerError => write!(f, "5xx"),
+            Failure::ParseError => write!(f, "parse error"),
+            Failure::CommandFailed => write!(f, "failed"),
+        }
+    }
+}
+
+/// The other way round from `Display`, for reading back logged samples.
+impl FromStr for Failure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "unreachable" => Ok(Failure::Unreachable),
+            "ttl exceeded" => Ok(Failure::TtlExceeded),
+            "too big" => Ok(Failure::TooBig),
+            "5xx" => Ok(Failure::ServerError),
+            "parse error" => Ok(Failure::ParseError),
+            "failed" => Ok(Failure::CommandFailed),
+            _ => Err(anyhow!("Unknown failure {:?}", s)),
+        }
+    }
+}
+
+/// How pings are sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Time between probes. Windows' ping always waits a second.
+    pub interval: Duration,
+    /// How long to wait for a reply before counting the probe as lost,
+    /// rather than the ping binary's own idea of when to give up
+    pub timeout: Option<Duration>,
+    /// Force the address family when pinging a hostname
+    pub family: Option<Family>,
+    /// Network interface to send from
+    pub interface: Option<String>,
+    /// Local address to send from
+    pub source: Option<IpAddr>,
+    pub ttl: Option<u8>,
+    /// Type of service byte, or traffic class for IPv6
+    pub tos: Option<u8>,
+    /// Try a raw ICMP socket as well as an unprivileged datagram one before
+    /// running the ping binary
+    pub native: bool,
+    /// Passed to the ping binary as they are, after the flags gping adds
+    pub extra_args: Vec<String>,
+    /// Bytes of data in each probe
+    pub size: Option<u16>,
+    /// Set the don't fragment bit, so probes too big for the path fail
+    pub dont_fragment: bool,
+    /// Run the ping binary on this machine instead
+    pub via: Option<Remote>,
+}
+
+impl Options {
+    /// Mechanisms to try, best first. Only the ping binary can send from an
+    /// interface, mark the TOS byte, take extra arguments, change the size
+    /// and fragmentation of probes or run on another machine.
+    pub fn mechanisms(&self) -> &'static [Mechanism] {
+        if self.interface.is_some()
+            || self.tos.is_some()
+            || !self.extra_args.is_empty()
+            || self.size.is_some()
+            || self.dont_fragment
+            || self.via.is_some()
+        {
+            &[Mechanism::Subprocess]
+        } else if self.native {
+            &[
+                Mechanism::IcmpApi,
+                Mechanism::Raw,
+                Mechanism::Datagram,
+                Mechanism::Subprocess,
+            ]
+        } else {
+            &[
+                Mechanism::IcmpApi,
+                Mechanism::Datagram,
+                Mechanism::Subprocess,
+            ]
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            interval: Duration::from_secs(1),
+            timeout: None,
+            family: None,
+            interface: None,
+            source: None,
+            ttl: None,
+            tos: None,
+            native: false,
+            extra_args: vec![],
+            size: None,
+            dont_fragment: false,
+            via: None,
+        }
+    }
+}
+
+/// The family of `ping` implementation on this platform, which decides the
+/// flags we pass and the output format we parse.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flavor {
+    /// iputils ping, as found on most Linux distributions
+    Linux,
+    /// BusyBox's ping, as found on Alpine and other small images
+    Busybox,
+    /// macOS, FreeBSD, OpenBSD, NetBSD and DragonFly
+    Bsd,
+    /// illumos and Solaris, e.g. SmartOS zones
+    Solaris,
+    Windows,
+}
+
+impl Flavor {
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            Flavor::Windows
+        } else if cfg!(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "openbsd",
+            target_os = "netbsd"
+        )) {
+            Flavor::Bsd
+        } else if cfg!(any(target_os = "illumos", target_os = "solaris")) {
+            Flavor::Solaris
+        } else if is_wsl() && !linux_ping_works() {
+            // WSL1, and WSL2 without the capability ping needs, can't send
+            // ICMP from inside the distribution, Windows' ping.exe can
+            Flavor::Windows
+        } else if is_busybox() {
+            Flavor::Busybox
+        } else {
+            Flavor::Linux
+        }
+    }
+
+    /// Whether `Options::interface` can be honoured.
+    pub fn can_bind_interface(self) -> bool {
+        match self {
+            Flavor::Linux | Flavor::Busybox => true,
+            Flavor::Bsd => cfg!(any(target_os = "macos", target_os = "ios")),
+            Flavor::Solaris | Flavor::Windows => false,
+        }
+    }
+
+    /// Whether `Options::tos` can be honoured. Windows ignores the TOS its
+    /// ping asks for.
+    pub fn can_mark_tos(self) -> bool {
+        match self {
+            Flavor::Linux => !is_android(),
+            Flavor::Bsd => !cfg!(target_os = "netbsd"),
+            Flavor::Busybox | Flavor::Solaris | Flavor::Windows => false,
+        }
+    }
+
+    /// Whether `Options::dont_fragment` can be honoured.
+    pub fn can_set_dont_fragment(self) -> bool {
+        match self {
+            // Android's ping has no -M
+            Flavor::Linux => !is_android(),
+            Flavor::Bsd | Flavor::Windows => true,
+            Flavor::Busybox | Flavor::Solaris => false,
+        }
+    }
+
+    /// Whether `Options::size` can be honoured. Solaris takes the size after
+    /// the address.
+    pub fn can_set_size(self) -> bool {
+        self != Flavor::Solaris
+    }
+
+    fn ttl_flag(self, is_v6: bool) -> &'static str {
+        match self {
+            Flavor::Linux | Flavor::Busybox | Flavor::Solaris => "-t",
+            // ping6 calls it the hop limit
+            Flavor::Bsd if is_v6 => "-h",
+            Flavor::Bsd if cfg!(target_os = "openbsd") => "-t",
+            Flavor::Bsd if cfg!(target_os = "netbsd") => "-T",
+            Flavor::Bsd => "-m",
+            Flavor::Windows => "-i",
+        }
+    }
+
+    /// The ping binary to run for an IPv4 or IPv6 address.
+    pub fn program(self, is_v6: bool) -> &'static str {
+        match self {
+            Flavor::Linux if is_android() => {
+                // Android's own ping works without root, unlike most ping
+                // packages installed inside Termux. Older releases only ping
+                // IPv6 through ping6.
+                let system = if is_v6 { ANDROID_PING6 } else { ANDROID_PING };
+                if Path::new(system).exists() {
+                    system
+                } else {
+                    "ping"
+                }
+            }
+            // The BSDs ship a separate binary for IPv6
+            Flavor::Bsd if is_v6 => "ping6",
+            // The .exe is needed to find it from inside WSL
+            Flavor::Windows => "ping.exe",
+            _ => "ping",
+        }
+    }
+
+    fn command(self, addr: &str, options: &Options) -> Command {
+        let is_v6 = addr
+            .parse::<IpAddr>()
+            .map_or(addr.contains(':'), |ip| ip.is_ipv6());
+        let interval = options.interval.as_secs_f64().to_string();
+        let interval = interval.as_str();
+        let mut cmd = match options.via {
+            Some(ref remote) => remote.command(self.program(is_v6)),
+            None => Command::new(self.program(is_v6)),
+        };
+        // The parser expects English, though it copes with most translations
+        cmd.env("LC_ALL", "C");
+        match self {
+            // Android's ping predates -O, lost probes are found from gaps in
+            // the sequence instead
+            Flavor::Linux if is_android() => cmd.args(["-i", interval]),
+            // -O reports probes that got no answer before the next is sent
+            Flavor::Linux => cmd.args(["-O", "-i", interval]),
+            // BusyBox doesn't report lost probes either
+            Flavor::Bsd | Flavor::Busybox => cmd.args(["-i", interval]),
+            // Without -s ping only reports whether the host is alive
+            Flavor::Solaris => cmd.args(["-s", "-I", interval]),
+            Flavor::Windows => cmd.arg("-t"),
+        };
+        match (self, options.family) {
+            // Android's ping has no family flags
+            (Flavor::Linux, _) if is_android() => {}
+            (Flavor::Linux, Some(Family::V4))
+            | (Flavor::Busybox, Some(Family::V4))
+            | (Flavor::Windows, Some(Family::V4)) => {
+                cmd.arg("-4");
+            }
+            (Flavor::Linux, Some(Family::V6))
+            | (Flavor::Busybox, Some(Family::V6))
+            | (Flavor::Windows, Some(Family::V6)) => {
+                cmd.arg("-6");
+            }
+            (Flavor::Solaris, Some(Family::V4)) => {
+                cmd.args(["-A", "inet"]);
+            }
+            (Flavor::Solaris, Some(Family::V6)) => {
+                cmd.args(["-A", "inet6"]);
+            }
+            // The BSDs pick ping or ping6 by the address instead
+            _ => {}
+        }
+        if let (Flavor::Windows, Some(timeout)) = (self, options.timeout) {
+            // Windows' ping waits for replies itself, and reports no sequence
+            // numbers we could use to wait for late ones
+            cmd.args(["-w", &timeout.as_millis().to_string()]);
+        }
+        if let Some(ref interface) = options.interface {
+            // macOS calls it the bound interface, iputils binds with -I
+            let flag = if cfg!(any(target_os = "macos", target_os = "ios")) {
+                "-b"
+            } else {
+                "-I"
+            };
+            cmd.args([flag, interface]);
+        }
+        if let Some(source) = options.source {
+            // iputils and BusyBox take an address or an interface for -I
+            let flag = match self {
+                Flavor::Linux | Flavor::Busybox => "-I",
+                _ => "-S",
+            };
+            cmd.args([flag, &source.to_string()]);
+        }
+        if let Some(ttl) = options.ttl {
+            cmd.args([self.ttl_flag(is_v6), &ttl.to_string()]);
+        }
+        if let Some(tos) = options.tos {
+            let flag = match self {
+                Flavor::Linux => "-Q",
+                Flavor::Bsd if cfg!(target_os = "openbsd") => "-T",
+                _ => "-z",
+            };
+            cmd.args([flag, &tos.to_string()]);
+        }
+        if let Some(size) = options.size {
+            let flag = if self == Flavor::Windows { "-l" } else { "-s" };
+            cmd.args([flag, &size.to_string()]);
+        }
+        if options.dont_fragment {
+            match self {
+                Flavor::Linux => cmd.args(["-M", "do"]),
+                Flavor::Windows => cmd.arg("-f"),
+                _ => cmd.arg("-D"),
+            };
+        }
+        cmd.args(&options.extra_args);
+        // Only GNU getopt accepts options after the address
+        cmd.arg(addr);
+        cmd
+    }
+}
+
+const ANDROID_PING: &str = "/system/bin/ping";
+const ANDROID_PING6: &str = "/system/bin/ping6";
+
+/// Android, either natively or a Linux build running under Termux.
+fn is_android() -> bool {
+    cfg!(target_os = "android") || env::var_os("TERMUX_VERSION").is_some()
+}
+
+/// Running under the Windows Subsystem for Linux.
+fn is_wsl() -> bool {
+    env::var_os("WSL_DISTRO_NAME").is_some()
+        || fs::read_to_string("/proc/sys/kernel/osrelease")
+            .is_ok_and(|release| release.to_lowercase().contains("microsoft"))
+}
+
+/// Whether `ping` is BusyBox's, which says so in its usage.
+fn is_busybox() -> bool {
+    static CHECK: Once = Once::new();
+    static BUSYBOX: AtomicBool = AtomicBool::new(false);
+    CHECK.call_once(|| {
+        let busybox = Command::new("ping")
+            .arg("--help")
+            .stdin(Stdio::null())
+            .output()
+            .is_ok_and(|output| {
+                let usage = [output.stdout, output.stderr].concat();
+                String::from_utf8_lossy(&usage).contains("BusyBox")
+            });
+        BUSYBOX.store(busybox, Ordering::Release);
+    });
+    BUSYBOX.load(Ordering::Acquire)
+}
+
+/// Whether the Linux ping can send probes at all, found once by pinging the
+/// loopback address.
+fn linux_ping_works() -> bool {
+    static CHECK: Once = Once::new();
+    static WORKS: AtomicBool = AtomicBool::new(false);
+    CHECK.call_once(|| {
+        let works = Command::new("ping")
+            .args(["-c", "1", "-W", "1", "127.0.0.1"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        WORKS.store(works, Ordering::Release);
+    });
+    WORKS.load(Ordering::Acquire)
+}
+
+/// Parse a DSCP class by name (`EF`, `AF41`, `CS6`, `BE`) or number.
+pub fn parse_dscp(input: &str) -> Result<u8> {
+    let invalid = || anyhow!("Invalid DSCP class {:?}", input);
+    let name = input.to_ascii_uppercase();
+    let dscp = if name == "EF" {
+        46
+    } else if name == "BE" {
+        0
+    } else if let Some(class) = name.strip_prefix("CS") {
+        let class: u8 = class.parse().map_err(|_| invalid())?;
+        if class > 7 {
+            return Err(invalid());
+        }
+        class << 3
+    } else if name.starts_with("AF") && name.len() == 4 {
+        let digits = &name.as_bytes()[2..];
+        let (class, drop) = (digits[0].wrapping_sub(b'0'), digits[1].wrapping_sub(b'0'));
+        if !(1..=4).contains(&class) || !(1..=3).contains(&drop) {
+            return Err(invalid());
+        }
+        (class << 3) | (drop << 1)
+    } else {
+        input.parse().map_err(|_| invalid())?
+    };
+    if dscp > 63 {
+        return Err(invalid());
+    }
+    Ok(dscp)
+}
+
+/// Start pinging `addr` with the best mechanism that works, reporting which.
+pub fn ping(addr: String, options: &Options) -> Result<(Mechanism, mpsc::Receiver<PingResult>)> {
+    if let Ok(ip) = addr.parse() {
+        #[cfg(windows)]
+        {
+            if options.mechanisms().contains(&Mechanism::IcmpApi) {
+                if let Ok(started) = windows::ping(ip, options) {
+                    return Ok(started);
+                }
+            }
+        }
+        // Fall back to the ping binary when ICMP sockets aren't allowed
+        if let Ok(started) = icmp::ping(ip, options) {
+            return Ok(started);
+        }
+    }
+    let flavor = options
+        .via
+        .as_ref()
+        .map_or_else(Flavor::current, |remote| remote.flavor);
+    let mut child = flavor
+        .command(&addr, options)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow!("Could not run ping for {}: {}", addr, e))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let options = options.clone();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut parser = match options.timeout {
+            Some(timeout) => Parser::with_timeout(flavor, timeout, options.interval),
+            None => Parser::new(flavor),
+        };
+        let mut sleep = SleepDetector::new();
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if sleep.check().is_some() {
+                // Probes that were in flight while the machine slept come back
+                // with the sleep counted in their round trip, drop them.
+                parser.resync();
+                parser.parse_line(&line);
+                continue;
+            }
+            for result in parser.parse_line(&line) {
+                if tx.send(result).is_err() {
+                    child.kill().unwrap_or(());
+                    break;
+                }
+            }
+        }
+        child.wait().map(|_| ()).unwrap_or(());
+    });
+    Ok((Mechanism::Subprocess, rx))
+}
+
+/// Time TCP handshakes with `port` on `addr` instead of pinging it.
+pub fn ping_tcp(addr: &str, port: u16, options: &Options) -> Result<mpsc::Receiver<PingResult>> {
+    Ok(tcp::ping(socket_addr(addr, port)?, options))
+}
+
+/// Time TCP connects to `port` on `host` through `proxy`, which resolves
+/// `host`, instead of pinging it.
+pub fn ping_proxied(
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+    options: &Options,
+) -> mpsc::Receiver<PingResult> {
+    proxy::ping(proxy.clone(), host.to_string(), port, options)
+}
+
+/// Time TLS handshakes with `port` on `addr`, checking the certificate is
+/// for `name`, instead of pinging it.
+pub fn ping_tls(
+    name: &str,
+    addr: &str,
+    port: u16,
+    options: &Options,
+) -> Result<mpsc::Receiver<PingResult>> {
+    tls::ping(name, socket_addr(addr, port)?, options)
+}
+
+/// Time QUIC handshakes with `port` on `addr`, checking the certificate is
+/// for `name`, instead of pinging it.
+pub fn ping_quic(
+    name: &str,
+    addr: &str,
+    port: u16,
+    options: &Options,
+) -> Result<mpsc::Receiver<PingResult>> {
+    quic::ping(name, socket_addr(addr, port)?, options)
+}
+
+/// Time ARP requests for `addr` on the local network instead of pinging it.
+pub fn ping_arp(addr: &str, options: &Options) -> Result<mpsc::Receiver<PingResult>> {
+    let ip = addr
+        .parse()
+        .map_err(|_| anyhow!("Can't ARP ping {}, it isn't an IPv4 address", addr))?;
+    arp::ping(ip, options)
+}
+
+/// Time datagrams sent to `port` on `addr` instead of pinging it.
+pub fn ping_udp(addr: &str, port: u16, options: &Options) -> Result<mpsc::Receiver<PingResult>> {
+    Ok(udp::ping(socket_addr(addr, port)?, options))
+}
+
+/// Time lookups of `name` on a DNS server instead of pinging a host.
+pub fn ping_dns(
+    name: &str,
+    server: &resolve::Server,
+    options: &Options,
+) -> Result<mpsc::Receiver<PingResult>> {
+    dns::ping(name.to_string(), server, options)
+}
+
+/// Time requests for `url` instead of pinging a host, telling `on_status`
+/// the status of each response.
+pub fn ping_http(
+    url: &str,
+    options: &Options,
+    on_status: impl Fn(u16) + Send + 'static,
+) -> Result<mpsc::Receiver<PingResult>> {
+    Ok(http::ping(url.to_string(), options, on_status))
+}
+
+fn socket_addr(addr: &str, port: u16) -> Result<SocketAddr> {
+    let ip = addr
+        .parse()
+        .map_err(|_| anyhow!("Can't connect to {}, it isn't an IP address", addr))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// A socket for probing `addr`, sending from the source address and with the
+/// TTL in `options`.
+fn socket_for(
+    addr: SocketAddr,
+    kind: Type,
+    protocol: Protocol,
+    options: &Options,
+) -> io::Result<Socket> {
+    let domain = if addr.is_ipv6() {
+        Domain::ipv6()
+    } else {
+        Domain::ipv4()
+    };
+    let socket = Socket::new(domain, kind, Some(protocol))?;
+    if let Some(source) = options.source {
+        socket.bind(&SockAddr::from(SocketAddr::new(source, 0)))?;
+    }
+    if let Some(ttl) = options.ttl {
+        if addr.is_ipv6() {
+            socket.set_unicast_hops_v6(ttl.into())?;
+        } else {
+            socket.set_ttl(ttl.into())?;
+        }
+    }
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_dscp, Failure};
+
+    #[test]
+    pub fn test_parse_dscp() {
+        assert_eq!(parse_dscp("EF").unwrap(), 46);
+        assert_eq!(parse_dscp("af41").unwrap(), 34);
+        assert_eq!(parse_dscp("AF11").unwrap(), 10);
+        assert_eq!(parse_dscp("CS6").unwrap(), 48);
+        assert_eq!(parse_dscp("be").unwrap(), 0);
+        assert_eq!(parse_dscp("26").unwrap(), 26);
+        assert!(parse_dscp("AF51").is_err());
+        assert!(parse_dscp("64").is_err());
+        assert!(parse_dscp("gold").is_err());
+    }
+
+    #[test]
+    pub fn test_failure_round_trip() {
+        for failure in &[
+            Failure::Unreachable,
+            Failure::TtlExceeded,
+            Failure::TooBig,
+            Failure::ServerError,
+            Failure::ParseError,
+            Failure::CommandFailed,
+        ] {
+            assert_eq!(failure.to_string().parse::<Failure>().unwrap(), *failure);
+        }
+        assert!("ok".parse::<Failure>().is_err());
+    }
+}
@@ -0,0 +1,368 @@
+use super::{Failure, Flavor, PingResult};
+use std::time::{Duration, Instant};
+
+/// Turns lines of ping output into results. Some pings (OpenBSD, NetBSD)
+/// print nothing for a lost probe, so gaps in the sequence numbers of replies
+/// are reported as timeouts too.
+pub struct Parser {
+    flavor: Flavor,
+    next_seq: Option<u64>,
+    /// Replies slower than this count as timeouts
+    timeout: Option<Duration>,
+    /// How long after ping reports a probe unanswered a reply is still waited for
+    grace: Duration,
+    /// Probes ping reported unanswered that are within their grace period
+    overdue: Vec<(u64, Instant)>,
+    /// ICMP errors the BSDs printed without a sequence number, whose probes
+    /// they'll report as timeouts too
+    unmatched_failures: usize,
+}
+
+impl Parser {
+    pub fn new(flavor: Flavor) -> Self {
+        Parser {
+            flavor,
+            next_seq: None,
+            timeout: None,
+            grace: Duration::from_secs(0),
+            overdue: vec![],
+            unmatched_failures: 0,
+        }
+    }
+
+    /// A parser that waits up to `timeout` for replies. Ping reports probes
+    /// unanswered once the next is due, `interval` after they were sent.
+    pub fn with_timeout(flavor: Flavor, timeout: Duration, interval: Duration) -> Self {
+        Parser {
+            timeout: Some(timeout),
+            grace: timeout.checked_sub(interval).unwrap_or_default(),
+            ..Parser::new(flavor)
+        }
+    }
+
+    /// Forget the expected sequence number, so the next reply isn't taken as
+    /// following a run of lost probes.
+    pub fn resync(&mut self) {
+        self.next_seq = None;
+        self.overdue.clear();
+        self.unmatched_failures = 0;
+    }
+
+    pub fn parse_line(&mut self, line: &str) -> Vec<PingResult> {
+        self.parse_line_at(line, Instant::now())
+    }
+
+    fn parse_line_at(&mut self, line: &str, now: Instant) -> Vec<PingResult> {
+        let mut results = vec![];
+        let grace = self.grace;
+        self.overdue.retain(|&(_, since)| {
+            let expired = now.saturating_duration_since(since) >= grace;
+            if expired {
+                results.push(PingResult::Timeout);
+            }
+            !expired
+        });
+        let rtt = parse_rtt(line);
+        let result = match rtt {
+            Some(rtt) if self.timeout.is_some_and(|timeout| rtt > timeout) => PingResult::Timeout,
+            Some(rtt) => PingResult::Pong(rtt),
+            None => match parse_failure(line) {
+                Some(failure) => PingResult::Failed(failure),
+                None if self.is_timeout(line) => PingResult::Timeout,
+                None => return results,
+            },
+        };
+        let seq = match parse_seq(line) {
+            Some(seq) => seq,
+            // Without a sequence number there's nothing to reorder or fill in
+            None => {
+                if self.flavor == Flavor::Bsd {
+                    if let PingResult::Failed(_) = result {
+                        self.unmatched_failures += 1;
+                    }
+                }
+                results.push(result);
+                return self.drop_matched_timeouts(results);
+            }
+        };
+        if rtt.is_some() {
+            if let Some(idx) = self.overdue.iter().position(|&(s, _)| s == seq) {
+                // A slow reply that made it within the timeout
+                self.overdue.remove(idx);
+                results.push(result);
+                return results;
+            }
+        }
+        let next_seq = self.next_seq.unwrap_or(seq);
+        if seq < next_seq {
+            // A late or duplicate reply for a probe we already reported
+            return results;
+        }
+        self.next_seq = Some(seq + 1);
+        results.extend(vec![PingResult::Timeout; (seq - next_seq) as usize]);
+        // Replies slower than the timeout are timeouts straight away, it's
+        // only probes still waiting for one that get the grace period
+        if rtt.is_none() && result == PingResult::Timeout && grace > Duration::from_secs(0) {
+            self.overdue.push((seq, now));
+        } else {
+            results.push(result);
+        }
+        self.drop_matched_timeouts(results)
+    }
+
+    /// Drop the timeouts of probes that already failed with an ICMP error.
+    fn drop_matched_timeouts(&mut self, mut results: Vec<PingResult>) -> Vec<PingResult> {
+        while self.unmatched_failures > 0 {
+            match results.iter().position(|r| *r == PingResult::Timeout) {
+                Some(idx) => {
+                    results.remove(idx);
+                    self.unmatched_failures -= 1;
+                }
+                None => break,
+            }
+        }
+        results
+    }
+
+    fn is_timeout(&self, line: &str) -> bool {
+        match self.flavor {
+            Flavor::Linux => line.starts_with("no answer yet"),
+            Flavor::Bsd => line.starts_with("Request timeout"),
+            // Lost probes are only noticed by the gap in sequence numbers
+            Flavor::Busybox | Flavor::Solaris => false,
+            Flavor::Windows => line.starts_with("Request timed out"),
+        }
+    }
+}
+
+/// Parse the ICMP error a line reports, like iputils' `Destination Host
+/// Unreachable`, `Time to live exceeded` or `Frag needed and DF set`, or
+/// Windows' `TTL expired in transit.`
+fn parse_failure(line: &str) -> Option<Failure> {
+    let line = line.to_lowercase();
+    // Checked first, as some pings call it "unreachable: frag needed"
+    if line.contains("frag needed") || line.contains("needs to be fragmented") {
+        Some(Failure::TooBig)
+    } else if line.contains("unreachable") {
+        Some(Failure::Unreachable)
+    } else if line.contains("time to live exceeded")
+        || line.contains("ttl expired")
+        || line.contains("time exceeded")
+    {
+        Some(Failure::TtlExceeded)
+    } else {
+        None
+    }
+}
+
+/// Parse the `time=12.3 ms` (or Windows' `time<1ms`) part of a reply. Only
+/// the `=12.3 ms` is looked for, so translations like `Zeit=12,3 ms` work too.
+fn parse_rtt(line: &str) -> Option<Duration> {
+    line.match_indices("ms").find_map(|(idx, _)| {
+        let before = line[..idx].trim_end();
+        let start = before.rfind(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))?;
+        // Skips summaries like `min/avg/max = 1.2/3.4/5.6 ms` and `time 3004ms`
+        if !before[start..].starts_with(['=', '<']) {
+            return None;
+        }
+        let ms: f64 = before[start + 1..].replace(',', ".").parse().ok()?;
+        Some(Duration::from_micros((ms * 1000.0).round() as u64))
+    })
+}
+
+/// Parse `icmp_seq=3` from a reply, `icmp_seq 3` from a BSD timeout, or
+/// BusyBox's `seq=3`.
+fn parse_seq(line: &str) -> Option<u64> {
+    let idx = match line.find("icmp_seq") {
+        Some(idx) => idx + 8,
+        None => line.find(" seq=")? + 4,
+    };
+    let digits: String = line[idx..]
+        .chars()
+        .skip_while(|c| *c == '=' || *c == ' ')
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Failure, Flavor, Parser, PingResult};
+    use std::time::{Duration, Instant};
+
+    fn parse(flavor: Flavor, output: &str) -> Vec<PingResult> {
+        let mut parser = Parser::new(flavor);
+        output
+            .lines()
+            .flat_map(|line| parser.parse_line(line))
+            .collect()
+    }
+
+    fn count(results: &[PingResult]) -> (usize, usize) {
+        let pongs = results
+            .iter()
+            .filter(|r| matches!(r, PingResult::Pong(_)))
+            .count();
+        (pongs, results.len() - pongs)
+    }
+
+    #[test]
+    pub fn test_linux() {
+        let results = parse(Flavor::Linux, include_str!("tests/linux.txt"));
+        assert_eq!(count(&results), (4, 2));
+        assert_eq!(results[0], PingResult::Pong(Duration::from_micros(11_300)));
+    }
+
+    #[test]
+    pub fn test_macos() {
+        let results = parse(Flavor::Bsd, include_str!("tests/macos.txt"));
+        assert_eq!(count(&results), (3, 2));
+    }
+
+    #[test]
+    pub fn test_freebsd() {
+        let results = parse(Flavor::Bsd, include_str!("tests/freebsd.txt"));
+        assert_eq!(count(&results), (4, 1));
+        assert_eq!(results[2], PingResult::Timeout);
+    }
+
+    #[test]
+    pub fn test_openbsd() {
+        // OpenBSD prints nothing for lost probes, seq 2 and 3 are missing
+        let results = parse(Flavor::Bsd, include_str!("tests/openbsd.txt"));
+        assert_eq!(count(&results), (3, 2));
+        assert_eq!(results[2], PingResult::Timeout);
+        assert_eq!(results[3], PingResult::Timeout);
+    }
+
+    #[test]
+    pub fn test_netbsd() {
+        let results = parse(Flavor::Bsd, include_str!("tests/netbsd.txt"));
+        assert_eq!(count(&results), (3, 1));
+    }
+
+    #[test]
+    pub fn test_illumos() {
+        // Sequence numbers end in a full stop, seq 2 is missing
+        let results = parse(Flavor::Solaris, include_str!("tests/illumos.txt"));
+        assert_eq!(count(&results), (3, 1));
+        assert_eq!(results[0], PingResult::Pong(Duration::from_micros(4_512)));
+        assert_eq!(results[2], PingResult::Timeout);
+    }
+
+    #[test]
+    pub fn test_busybox() {
+        // BusyBox prints nothing for lost probes, seq 2 is missing
+        let results = parse(Flavor::Busybox, include_str!("tests/busybox.txt"));
+        assert_eq!(count(&results), (4, 1));
+        assert_eq!(results[0], PingResult::Pong(Duration::from_micros(11_284)));
+        assert_eq!(results[2], PingResult::Timeout);
+    }
+
+    #[test]
+    pub fn test_timeout() {
+        let mut parser = Parser::with_timeout(
+            Flavor::Linux,
+            Duration::from_secs(3),
+            Duration::from_secs(1),
+        );
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        // A reply that takes longer than the interval still counts
+        assert!(parser
+            .parse_line_at("no answer yet for icmp_seq=1", at(1000))
+            .is_empty());
+        assert_eq!(
+            parser.parse_line_at(
+                "64 bytes from 1.1.1.1: icmp_seq=1 ttl=58 time=1500 ms",
+                at(1500)
+            ),
+            vec![PingResult::Pong(Duration::from_millis(1500))]
+        );
+        // One that never comes is a timeout once the grace period is over
+        assert!(parser
+            .parse_line_at("no answer yet for icmp_seq=2", at(2000))
+            .is_empty());
+        assert_eq!(
+            parser.parse_line_at(
+                "64 bytes from 1.1.1.1: icmp_seq=3 ttl=58 time=3500 ms",
+                at(4500)
+            ),
+            vec![PingResult::Timeout, PingResult::Timeout]
+        );
+    }
+
+    #[test]
+    pub fn test_translated() {
+        let mut parser = Parser::new(Flavor::Linux);
+        assert_eq!(
+            parser.parse_line("64 Bytes von 1.1.1.1: icmp_seq=1 ttl=57 Zeit=12,3 ms"),
+            vec![PingResult::Pong(Duration::from_micros(12_300))]
+        );
+        let mut parser = Parser::new(Flavor::Windows);
+        assert_eq!(
+            parser.parse_line("Antwort von 1.1.1.1: Bytes=32 Zeit<1ms TTL=57"),
+            vec![PingResult::Pong(Duration::from_millis(1))]
+        );
+        assert!(parser
+            .parse_line("rtt min/avg/max/mdev = 10.1/11.2/12.3/0.5 ms")
+            .is_empty());
+        assert!(parser
+            .parse_line("4 packets transmitted, 4 received, 0% packet loss, time 3004ms")
+            .is_empty());
+    }
+
+    #[test]
+    pub fn test_too_big() {
+        let mut parser = Parser::new(Flavor::Linux);
+        parser.parse_line("64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=1.2 ms");
+        // The sequence gap before it is still filled in
+        assert_eq!(
+            parser.parse_line("From 10.0.0.1 icmp_seq=3 Frag needed and DF set (mtu = 1400)"),
+            vec![PingResult::Timeout, PingResult::Failed(Failure::TooBig)]
+        );
+        let mut parser = Parser::new(Flavor::Windows);
+        assert_eq!(
+            parser.parse_line("Packet needs to be fragmented but DF set."),
+            vec![PingResult::Failed(Failure::TooBig)]
+        );
+    }
+
+    #[test]
+    pub fn test_failures() {
+        let mut parser = Parser::new(Flavor::Linux);
+        assert_eq!(
+            parser.parse_line("From 10.0.0.1 icmp_seq=1 Destination Host Unreachable"),
+            vec![PingResult::Failed(Failure::Unreachable)]
+        );
+        assert_eq!(
+            parser.parse_line("From 10.0.0.1 icmp_seq=2 Time to live exceeded"),
+            vec![PingResult::Failed(Failure::TtlExceeded)]
+        );
+        let mut parser = Parser::new(Flavor::Windows);
+        assert_eq!(
+            parser.parse_line("Reply from 10.0.0.1: TTL expired in transit."),
+            vec![PingResult::Failed(Failure::TtlExceeded)]
+        );
+        // macOS reports the error, then the same probe timing out
+        let mut parser = Parser::new(Flavor::Bsd);
+        assert_eq!(
+            parser.parse_line("92 bytes from 10.0.0.1: Destination Host Unreachable"),
+            vec![PingResult::Failed(Failure::Unreachable)]
+        );
+        assert!(parser
+            .parse_line("Request timeout for icmp_seq 0")
+            .is_empty());
+        assert_eq!(
+            parser.parse_line("Request timeout for icmp_seq 1"),
+            vec![PingResult::Timeout]
+        );
+    }
+
+    #[test]
+    pub fn test_windows() {
+        let results = parse(Flavor::Windows, include_str!("tests/windows.txt"));
+        assert_eq!(count(&results), (3, 1));
+        assert_eq!(results[2], PingResult::Pong(Duration::from_millis(1)));
+    }
+}
@@ -0,0 +1,197 @@
+use super::{socket_for, Failure, Options, PingResult};
+use crate::clock::SleepDetector;
+use anyhow::{anyhow, Result};
+use socket2::{Protocol, SockAddr, Type};
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A proxy to time TCP connects through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Proxy {
+    kind: Kind,
+    addr: SocketAddr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Socks5,
+    /// An HTTP proxy that supports CONNECT
+    Http,
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Kind::Socks5 => write!(f, "socks5://{}", self.addr),
+            Kind::Http => write!(f, "http://{}", self.addr),
+        }
+    }
+}
+
+impl Proxy {
+    /// Parse a proxy given as `socks5://host:port` or `http://host:port`.
+    pub fn parse(input: &str) -> Result<Proxy> {
+        let invalid = || {
+            anyhow!(
+                "Invalid proxy {:?}, expected socks5://host:port or http://host:port",
+                input
+            )
+        };
+        let (kind, rest) = if let Some(rest) = input.strip_prefix("socks5://") {
+            (Kind::Socks5, rest)
+        } else if let Some(rest) = input.strip_prefix("http://") {
+            (Kind::Http, rest)
+        } else {
+            return Err(invalid());
+        };
+        let addr = rest
+            .trim_end_matches('/')
+            .to_socket_addrs()
+            .map_err(|_| invalid())?
+            .next()
+            .ok_or_else(invalid)?;
+        Ok(Proxy { kind, addr })
+    }
+}
+
+/// Time connecting to `host` on `port` through `proxy` every interval, from
+/// connecting to the proxy until it reports the connection made. The proxy
+/// resolves `host`. Only the source address, TTL and timeout of `options`
+/// apply, to the connection to the proxy.
+pub fn ping(
+    proxy: Proxy,
+    host: String,
+    port: u16,
+    options: &Options,
+) -> mpsc::Receiver<PingResult> {
+    let options = options.clone();
+    let interval = options.interval;
+    // Like the other engines, give up on a probe when the next one is due
+    let timeout = options.timeout.unwrap_or(interval);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let result = match connect(&proxy, &host, port, &options, timeout) {
+                Ok(rtt) => PingResult::Pong(rtt),
+                // The proxy answered, but couldn't reach the target
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                    PingResult::Failed(Failure::Unreachable)
+                }
+                Err(_) => PingResult::Timeout,
+            };
+            // A connect that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    rx
+}
+
+fn connect(
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+    options: &Options,
+    timeout: Duration,
+) -> io::Result<Duration> {
+    let started = Instant::now();
+    let socket = socket_for(proxy.addr, Type::stream(), Protocol::tcp(), options)?;
+    socket.connect_timeout(&SockAddr::from(proxy.addr), timeout)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+    let mut stream = socket.into_tcp_stream();
+    match proxy.kind {
+        Kind::Socks5 => socks5_connect(&mut stream, host, port)?,
+        Kind::Http => http_connect(&mut stream, host, port)?,
+    }
+    Ok(started.elapsed())
+}
+
+fn refused(reason: String) -> io::Error {
+    io::Error::new(ErrorKind::ConnectionRefused, reason)
+}
+
+/// Ask a SOCKS5 proxy to connect to `host`, following RFC 1928.
+fn socks5_connect(stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+    // Version 5, offering only "no authentication"
+    stream.write_all(&[5, 1, 0])?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice)?;
+    if choice != [5, 0] {
+        return Err(refused("the proxy wants authentication".to_string()));
+    }
+    let name = host.as_bytes();
+    if name.len() > 255 {
+        return Err(refused(format!("{} is too long for SOCKS5", host)));
+    }
+    // CONNECT to a domain name, which the proxy resolves
+    let mut request = vec![5, 1, 0, 3, name.len() as u8];
+    request.extend_from_slice(name);
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(refused(format!("SOCKS5 error {}", reply[1])));
+    }
+    // Skip the address the proxy connected from
+    let len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        _ => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            usize::from(len[0])
+        }
+    };
+    stream.read_exact(&mut vec![0u8; len + 2])
+}
+
+/// Ask an HTTP proxy to connect to `host` with a CONNECT request.
+fn http_connect(stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+    let target = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    write!(stream, "CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target)?;
+    // Read up to the end of the headers, a byte at a time so nothing past
+    // them is taken
+    let mut response = vec![];
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte)?;
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.split_whitespace().nth(1).unwrap_or_default();
+    if status.starts_with('2') {
+        Ok(())
+    } else {
+        Err(refused(format!("the proxy answered {}", status)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Kind, Proxy};
+
+    #[test]
+    pub fn test_parse() {
+        let proxy = Proxy::parse("socks5://127.0.0.1:1080").unwrap();
+        assert_eq!(proxy.kind, Kind::Socks5);
+        assert_eq!(proxy.addr, "127.0.0.1:1080".parse().unwrap());
+        assert_eq!(proxy.to_string(), "socks5://127.0.0.1:1080");
+        assert_eq!(Proxy::parse("http://[::1]:3128/").unwrap().kind, Kind::Http);
+        assert!(Proxy::parse("127.0.0.1:1080").is_err());
+        assert!(Proxy::parse("socks5://127.0.0.1").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use super::{Options, PingResult};
+use crate::clock::SleepDetector;
+use anyhow::{anyhow, Result};
+use quinn::{ClientConfigBuilder, Endpoint, VarInt};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+use tokio::runtime;
+
+/// The ALPN protocol offered, which CDNs need before they'll finish a handshake.
+const ALPN_H3: &[u8] = b"h3";
+
+/// Time a QUIC handshake with `name` at `addr` every interval, offering
+/// HTTP/3 like a browser would. Only the source address and timeout of
+/// `options` apply.
+pub fn ping(name: &str, addr: SocketAddr, options: &Options) -> Result<mpsc::Receiver<PingResult>> {
+    // quinn needs tokio, one single threaded runtime per target is plenty
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let mut client = ClientConfigBuilder::default();
+    client.protocols(&[ALPN_H3]);
+    let mut builder = Endpoint::builder();
+    builder.default_client_config(client.build());
+    let local = match (options.source, addr) {
+        (Some(source), _) => source,
+        (None, SocketAddr::V4(_)) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        (None, SocketAddr::V6(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let (endpoint, _) = {
+        let _guard = runtime.enter();
+        builder
+            .bind(&SocketAddr::new(local, 0))
+            .map_err(|e| anyhow!("Could not open a QUIC endpoint for {}: {}", addr, e))?
+    };
+    let name = name.to_string();
+    let interval = options.interval;
+    // Like the other engines, give up on a probe when the next one is due
+    let timeout = options.timeout.unwrap_or(interval);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let handshake = runtime.block_on(async {
+                let connecting = endpoint.connect(&addr, &name).ok()?;
+                let connection = tokio::time::timeout(timeout, connecting).await.ok()?.ok()?;
+                let rtt = started.elapsed();
+                connection.connection.close(VarInt::from_u32(0), b"");
+                Some(rtt)
+            });
+            let result = match handshake {
+                Some(rtt) => PingResult::Pong(rtt),
+                None => PingResult::Timeout,
+            };
+            // A handshake that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    Ok(rx)
+}
@@ -0,0 +1,113 @@
+use super::Flavor;
+use anyhow::{anyhow, Result};
+use std::process::{Command, Stdio};
+
+/// A machine to run ping on over SSH, so the chart shows latency from there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remote {
+    /// `user@host` or just `host`, as ssh takes it
+    destination: String,
+    port: Option<u16>,
+    /// Which ping the remote machine has
+    pub flavor: Flavor,
+}
+
+impl Remote {
+    /// Connect to a machine given as `ssh://[user@]host[:port]` to find out
+    /// which ping it has.
+    pub fn connect(url: &str) -> Result<Remote> {
+        let (destination, port) = parse_url(url)?;
+        let mut remote = Remote {
+            destination,
+            port,
+            flavor: Flavor::Linux,
+        };
+        let output = remote
+            .ssh()
+            .arg("uname -s")
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| anyhow!("Could not run ssh: {}", e))?;
+        // ssh itself exits with 255, the remote command with anything else
+        if output.status.code() == Some(255) {
+            return Err(anyhow!(
+                "Could not connect to {}: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        remote.flavor = match String::from_utf8_lossy(&output.stdout).trim() {
+            "Linux" => Flavor::Linux,
+            "Darwin" | "FreeBSD" | "DragonFly" | "OpenBSD" | "NetBSD" => Flavor::Bsd,
+            "SunOS" => Flavor::Solaris,
+            // Windows' OpenSSH runs commands with cmd.exe, which has no uname
+            _ if !output.status.success() => Flavor::Windows,
+            other => return Err(anyhow!("Don't know how to ping from {} on {}", other, url)),
+        };
+        Ok(remote)
+    }
+
+    /// A command running `program` on the remote machine. Arguments added to
+    /// it are passed on to the program.
+    pub(super) fn command(&self, program: &str) -> Command {
+        let mut cmd = self.ssh();
+        // The parser expects English, cmd.exe doesn't take variables like this
+        if self.flavor != Flavor::Windows {
+            cmd.arg("LC_ALL=C");
+        }
+        cmd.arg(program);
+        cmd
+    }
+
+    fn ssh(&self) -> Command {
+        let mut cmd = Command::new("ssh");
+        // Fail instead of asking for a password the chart would hide
+        cmd.args(["-T", "-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            cmd.args(["-p", &port.to_string()]);
+        }
+        cmd.args([&self.destination, "--"]);
+        cmd
+    }
+}
+
+fn parse_url(url: &str) -> Result<(String, Option<u16>)> {
+    let invalid = || anyhow!("Invalid --via {:?}, expected ssh://[user@]host[:port]", url);
+    if !url.starts_with("ssh://") {
+        return Err(invalid());
+    }
+    let rest = url[6..].trim_end_matches('/');
+    let host_start = rest.find('@').map_or(0, |idx| idx + 1);
+    let (destination, port) = match rest[host_start..].rfind(':') {
+        Some(idx) => {
+            let idx = host_start + idx;
+            let port = rest[idx + 1..].parse().map_err(|_| invalid())?;
+            (&rest[..idx], Some(port))
+        }
+        None => (rest, None),
+    };
+    if destination.len() == host_start {
+        return Err(invalid());
+    }
+    Ok((destination.to_string(), port))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_url;
+
+    #[test]
+    pub fn test_parse_url() {
+        assert_eq!(
+            parse_url("ssh://admin@jumphost").unwrap(),
+            ("admin@jumphost".to_string(), None)
+        );
+        assert_eq!(
+            parse_url("ssh://jumphost:2222/").unwrap(),
+            ("jumphost".to_string(), Some(2222))
+        );
+        assert!(parse_url("admin@jumphost").is_err());
+        assert!(parse_url("ssh://admin@").is_err());
+        assert!(parse_url("ssh://jumphost:ssh").is_err());
+    }
+}
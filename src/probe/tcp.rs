@@ -0,0 +1,47 @@
+use super::{Failure, Options, PingResult};
+use crate::clock::SleepDetector;
+use socket2::{Protocol, SockAddr, Type};
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time a TCP handshake with `addr` every interval, for networks that filter
+/// ICMP. Only the source address, TTL and timeout of `options` apply.
+pub fn ping(addr: SocketAddr, options: &Options) -> mpsc::Receiver<PingResult> {
+    let options = options.clone();
+    let interval = options.interval;
+    // Like the other engines, give up on a probe when the next one is due
+    let timeout = options.timeout.unwrap_or(interval);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let result = match handshake(addr, &options, timeout) {
+                Ok(rtt) => PingResult::Pong(rtt),
+                // A reset means the host answered, but nothing listens on the port
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                    PingResult::Failed(Failure::Unreachable)
+                }
+                Err(_) => PingResult::Timeout,
+            };
+            // A handshake that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    rx
+}
+
+/// Connect to `addr` and return how long the SYN took to be answered. The
+/// connection is closed again straight away.
+fn handshake(addr: SocketAddr, options: &Options, timeout: Duration) -> io::Result<Duration> {
+    let socket = super::socket_for(addr, Type::stream(), Protocol::tcp(), options)?;
+    let started = Instant::now();
+    socket.connect_timeout(&SockAddr::from(addr), timeout)?;
+    Ok(started.elapsed())
+}
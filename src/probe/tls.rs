@@ -0,0 +1,76 @@
+use super::{socket_for, Options, PingResult};
+use crate::clock::SleepDetector;
+use anyhow::{anyhow, Result};
+use rustls::{ClientConfig, ClientSession, NoClientSessionStorage, Session};
+use socket2::{Protocol, SockAddr, Type};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use webpki::DNSNameRef;
+
+/// Time the TLS handshake with `name` at `addr` every interval, leaving out
+/// the TCP connect before it. Only the source address, TTL and timeout of
+/// `options` apply.
+pub fn ping(name: &str, addr: SocketAddr, options: &Options) -> Result<mpsc::Receiver<PingResult>> {
+    let name = DNSNameRef::try_from_ascii_str(name)
+        .map_err(|_| {
+            anyhow!(
+                "TLS needs a hostname to check the certificate of, not {}",
+                name
+            )
+        })?
+        .to_owned();
+    let mut config = ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    // A resumed session skips most of the handshake we want to time
+    config.session_persistence = Arc::new(NoClientSessionStorage {});
+    config.enable_tickets = false;
+    let config = Arc::new(config);
+    let options = options.clone();
+    let interval = options.interval;
+    // Like the other engines, give up on a probe when the next one is due
+    let timeout = options.timeout.unwrap_or(interval);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let result = match handshake(&config, name.as_ref(), addr, &options, timeout) {
+                Ok(rtt) => PingResult::Pong(rtt),
+                Err(_) => PingResult::Timeout,
+            };
+            // A handshake that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    Ok(rx)
+}
+
+/// Connect to `addr`, then return how long the TLS handshake over the
+/// connection took, including checking the certificate.
+fn handshake(
+    config: &Arc<ClientConfig>,
+    name: DNSNameRef,
+    addr: SocketAddr,
+    options: &Options,
+    timeout: Duration,
+) -> io::Result<Duration> {
+    let socket = socket_for(addr, Type::stream(), Protocol::tcp(), options)?;
+    socket.connect_timeout(&SockAddr::from(addr), timeout)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.set_write_timeout(Some(timeout))?;
+    let mut stream = socket.into_tcp_stream();
+    let mut session = ClientSession::new(config, name);
+    let started = Instant::now();
+    while session.is_handshaking() {
+        session.complete_io(&mut stream)?;
+    }
+    Ok(started.elapsed())
+}
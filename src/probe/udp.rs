@@ -0,0 +1,60 @@
+use super::icmp::PAYLOAD;
+use super::{socket_for, Options, PingResult};
+use crate::clock::SleepDetector;
+use socket2::{Protocol, SockAddr, Type};
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Send a datagram to `addr` every interval and time whatever comes back: an
+/// echo from the service, or the ICMP port unreachable error from a closed
+/// port. Only the source address, TTL and timeout of `options` apply.
+pub fn ping(addr: SocketAddr, options: &Options) -> mpsc::Receiver<PingResult> {
+    let options = options.clone();
+    let interval = options.interval;
+    // Like the other engines, give up on a probe when the next one is due
+    let timeout = options.timeout.unwrap_or(interval);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let result = match round_trip(addr, &options, timeout) {
+                Ok(rtt) => PingResult::Pong(rtt),
+                Err(_) => PingResult::Timeout,
+            };
+            // A reply that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    rx
+}
+
+/// Send one datagram from a fresh socket, so nothing left over from an
+/// earlier probe can be taken for its answer, and wait up to `timeout` for
+/// one.
+fn round_trip(addr: SocketAddr, options: &Options, timeout: Duration) -> io::Result<Duration> {
+    let socket = socket_for(addr, Type::dgram(), Protocol::udp(), options)?;
+    // A connected socket is told about ICMP errors for what it sent
+    socket.connect(&SockAddr::from(addr))?;
+    socket.set_read_timeout(Some(timeout))?;
+    let started = Instant::now();
+    socket.send(PAYLOAD)?;
+    let mut buf = [0u8; 1500];
+    match socket.recv(&mut buf) {
+        Ok(_) => Ok(started.elapsed()),
+        // Windows reports the port unreachable as a reset
+        Err(e)
+            if e.kind() == ErrorKind::ConnectionRefused
+                || e.kind() == ErrorKind::ConnectionReset =>
+        {
+            Ok(started.elapsed())
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,193 @@
+use super::detect::Mechanism;
+use super::icmp::PAYLOAD;
+use super::{Failure, Options, PingResult};
+use crate::clock::SleepDetector;
+use anyhow::{anyhow, Result};
+use socket2::SockAddr;
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::ptr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use winapi::ctypes::c_void;
+use winapi::shared::ws2ipdef::SOCKADDR_IN6;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::ipexport::{
+    IPAddr, ICMPV6_ECHO_REPLY, ICMP_ECHO_REPLY, IP_DEST_HOST_UNREACHABLE, IP_DEST_NET_UNREACHABLE,
+    IP_DEST_PORT_UNREACHABLE, IP_DEST_PROT_UNREACHABLE, IP_OPTION_INFORMATION, IP_PACKET_TOO_BIG,
+    IP_SUCCESS, IP_TTL_EXPIRED_TRANSIT,
+};
+use winapi::um::winnt::HANDLE;
+
+// winapi has no bindings for icmpapi.h
+#[link(name = "iphlpapi")]
+extern "system" {
+    fn IcmpCreateFile() -> HANDLE;
+    fn Icmp6CreateFile() -> HANDLE;
+    fn IcmpCloseHandle(handle: HANDLE) -> i32;
+    fn IcmpSendEcho(
+        handle: HANDLE,
+        destination: IPAddr,
+        request_data: *mut c_void,
+        request_size: u16,
+        request_options: *mut IP_OPTION_INFORMATION,
+        reply_buffer: *mut c_void,
+        reply_size: u32,
+        timeout: u32,
+    ) -> u32;
+    fn Icmp6SendEcho2(
+        handle: HANDLE,
+        event: HANDLE,
+        apc_routine: *mut c_void,
+        apc_context: *mut c_void,
+        source: *mut SOCKADDR_IN6,
+        destination: *mut SOCKADDR_IN6,
+        request_data: *mut c_void,
+        request_size: u16,
+        request_options: *mut IP_OPTION_INFORMATION,
+        reply_buffer: *mut c_void,
+        reply_size: u32,
+        timeout: u32,
+    ) -> u32;
+}
+
+/// The reply buffer needs room for either family's reply struct, our payload
+/// echoed back, an ICMP error and an IO_STATUS_BLOCK.
+const REPLY_SIZE: usize = mem::size_of::<ICMP_ECHO_REPLY>()
+    + mem::size_of::<ICMPV6_ECHO_REPLY>()
+    + PAYLOAD.len()
+    + 8
+    + 16;
+
+/// Windows' default TTL, used when `--ttl` isn't given.
+const DEFAULT_TTL: u8 = 128;
+
+pub(super) struct Handle(HANDLE);
+
+// The ICMP API can be called from any thread
+unsafe impl Send for Handle {}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            IcmpCloseHandle(self.0);
+        }
+    }
+}
+
+pub(super) fn open(is_v6: bool) -> io::Result<Handle> {
+    let handle = unsafe {
+        if is_v6 {
+            Icmp6CreateFile()
+        } else {
+            IcmpCreateFile()
+        }
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(Handle(handle))
+    }
+}
+
+/// Ping `addr` with IcmpSendEcho, or Icmp6SendEcho2 for IPv6, which time
+/// replies the same whatever language Windows is set to.
+pub fn ping(addr: IpAddr, options: &Options) -> Result<(Mechanism, mpsc::Receiver<PingResult>)> {
+    if options.source.is_some() {
+        return Err(anyhow!("The ICMP API can't choose a source address"));
+    }
+    let handle = open(addr.is_ipv6())
+        .map_err(|e| anyhow!("Could not open the ICMP API for {}: {}", addr, e))?;
+    let ttl = options.ttl.unwrap_or(DEFAULT_TTL);
+    let tos = options.tos.unwrap_or(0);
+    let interval = options.interval;
+    // Like the other engines, give up on a probe when the next one is due
+    let timeout = options.timeout.unwrap_or(interval);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut request_options: IP_OPTION_INFORMATION = unsafe { mem::zeroed() };
+        request_options.Ttl = ttl;
+        request_options.Tos = tos;
+        let mut reply = vec![0u8; REPLY_SIZE];
+        let mut sleep = SleepDetector::new();
+        loop {
+            let started = Instant::now();
+            let result = match send_echo(&handle, addr, &mut request_options, &mut reply, timeout) {
+                IP_SUCCESS => PingResult::Pong(started.elapsed()),
+                IP_DEST_NET_UNREACHABLE
+                | IP_DEST_HOST_UNREACHABLE
+                | IP_DEST_PROT_UNREACHABLE
+                | IP_DEST_PORT_UNREACHABLE => PingResult::Failed(Failure::Unreachable),
+                IP_TTL_EXPIRED_TRANSIT => PingResult::Failed(Failure::TtlExceeded),
+                IP_PACKET_TOO_BIG => PingResult::Failed(Failure::TooBig),
+                _ => PingResult::Timeout,
+            };
+            // A reply that waited out a sleep would count the sleep
+            if sleep.check().is_none() && tx.send(result).is_err() {
+                return;
+            }
+            thread::sleep(interval.checked_sub(started.elapsed()).unwrap_or_default());
+        }
+    });
+    Ok((Mechanism::IcmpApi, rx))
+}
+
+/// Send one echo request and wait up to `timeout` for the reply, returning
+/// its IP_STATUS.
+fn send_echo(
+    handle: &Handle,
+    addr: IpAddr,
+    options: &mut IP_OPTION_INFORMATION,
+    reply: &mut [u8],
+    timeout: Duration,
+) -> u32 {
+    let timeout = timeout.as_millis() as u32;
+    let request = PAYLOAD.as_ptr() as *mut _;
+    let request_size = PAYLOAD.len() as u16;
+    unsafe {
+        match addr {
+            IpAddr::V4(ip) => {
+                let replies = IcmpSendEcho(
+                    handle.0,
+                    // IPAddr is in network byte order
+                    u32::from_ne_bytes(ip.octets()),
+                    request,
+                    request_size,
+                    options,
+                    reply.as_mut_ptr() as *mut _,
+                    reply.len() as u32,
+                    timeout,
+                );
+                if replies == 0 {
+                    return GetLastError();
+                }
+                ptr::read_unaligned(reply.as_ptr() as *const ICMP_ECHO_REPLY).Status
+            }
+            IpAddr::V6(_) => {
+                let source = SockAddr::from(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0));
+                let destination = SockAddr::from(SocketAddr::new(addr, 0));
+                let replies = Icmp6SendEcho2(
+                    handle.0,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    source.as_ptr() as *mut SOCKADDR_IN6,
+                    destination.as_ptr() as *mut SOCKADDR_IN6,
+                    request,
+                    request_size,
+                    options,
+                    reply.as_mut_ptr() as *mut _,
+                    reply.len() as u32,
+                    timeout,
+                );
+                if replies == 0 {
+                    return GetLastError();
+                }
+                ptr::read_unaligned(reply.as_ptr() as *const ICMPV6_ECHO_REPLY).Status
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Split a `--prom` target given as `http://server:9090|query`.
+pub fn parse_target(input: &str) -> Result<(String, String)> {
+    let invalid = || {
+        anyhow!(
+            "Invalid --prom {:?}, expected http://server:9090|query",
+            input
+        )
+    };
+    let idx = input.find('|').ok_or_else(invalid)?;
+    let (server, query) = (input[..idx].trim(), input[idx + 1..].trim());
+    if !(server.starts_with("http://") || server.starts_with("https://")) || query.is_empty() {
+        return Err(invalid());
+    }
+    Ok((server.trim_end_matches('/').to_string(), query.to_string()))
+}
+
+/// Run `query` as an instant query on the Prometheus at `server`, returning
+/// its value. Queries with a vector result take the first series.
+pub fn query(server: &str, query: &str, timeout: Duration) -> Result<f64> {
+    let response: Value = ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .build()
+        .get(&format!("{}/api/v1/query", server))
+        .query("query", query)
+        .call()?
+        .into_json()?;
+    parse_response(&response).ok_or_else(|| anyhow!("{} has no value", query))
+}
+
+/// The value in a query response, which Prometheus gives as a string so it
+/// can say `NaN` and `+Inf`.
+fn parse_response(response: &Value) -> Option<f64> {
+    let data = &response["data"];
+    let sample = match data["resultType"].as_str()? {
+        "scalar" => &data["result"],
+        "vector" => &data["result"][0]["value"],
+        _ => return None,
+    };
+    sample[1].as_str()?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_response, parse_target};
+    use serde_json::json;
+
+    #[test]
+    pub fn test_parse_target() {
+        assert_eq!(
+            parse_target("http://prom:9090/|up{job=\"api\"}").unwrap(),
+            (
+                "http://prom:9090".to_string(),
+                "up{job=\"api\"}".to_string()
+            )
+        );
+        assert!(parse_target("http://prom:9090").is_err());
+        assert!(parse_target("prom:9090|up").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_response() {
+        let vector = json!({
+            "status": "success",
+            "data": {
+                "resultType": "vector",
+                "result": [{"metric": {}, "value": [1614000000.0, "0.25"]}]
+            }
+        });
+        assert_eq!(parse_response(&vector), Some(0.25));
+        let scalar = json!({"data": {"resultType": "scalar", "result": [1614000000.0, "3"]}});
+        assert_eq!(parse_response(&scalar), Some(3.0));
+        let empty = json!({"data": {"resultType": "vector", "result": []}});
+        assert_eq!(parse_response(&empty), None);
+    }
+}
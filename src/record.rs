@@ -0,0 +1,204 @@
+use crate::replay::{self, Recorded, Recording};
+use crate::samplelog::Sample;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection, OpenFlags};
+use std::path::Path;
+use std::time::Duration;
+
+/// Sessions, their targets and every sample, in the order recordings are
+/// made. Several sessions can be recorded to the same file.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY,
+    started_ms INTEGER NOT NULL,
+    interval_ms INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS targets (
+    id INTEGER PRIMARY KEY,
+    session_id INTEGER NOT NULL REFERENCES sessions (id),
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS samples (
+    target_id INTEGER NOT NULL REFERENCES targets (id),
+    time_ms INTEGER NOT NULL,
+    seq INTEGER NOT NULL,
+    latency_us INTEGER,
+    status TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS samples_by_time ON samples (target_id, time_ms);
+";
+
+/// Records a session to an SQLite database for `--record`.
+pub struct Recorder {
+    conn: Connection,
+    session_id: i64,
+    /// Row ids of the targets, by host id, added as they first get a sample
+    target_ids: Vec<Option<i64>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, started: DateTime<Local>, interval: Duration) -> Result<Self> {
+        let conn = Connection::open(path)
+            .map_err(|e| anyhow!("Could not open {}: {}", path.display(), e))?;
+        // Samples trickle in one at a time, so skip waiting for each to reach
+        // the disk. The database survives gping being killed, if not the OS.
+        conn.pragma_update(None, "journal_mode", &"WAL")?;
+        conn.pragma_update(None, "synchronous", &"NORMAL")?;
+        Recorder::new(conn, started, interval)
+    }
+
+    fn new(conn: Connection, started: DateTime<Local>, interval: Duration) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        conn.execute(
+            "INSERT INTO sessions (started_ms, interval_ms) VALUES (?1, ?2)",
+            params![started.timestamp_millis(), interval.as_millis() as i64],
+        )?;
+        Ok(Recorder {
+            session_id: conn.last_insert_rowid(),
+            conn,
+            target_ids: vec![],
+        })
+    }
+
+    pub fn record(&mut self, host_id: usize, sample: &Sample) -> Result<()> {
+        let target_id = self.target_id(host_id, sample.target)?;
+        self.conn.execute(
+            "INSERT INTO samples (target_id, time_ms, seq, latency_us, status)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                target_id,
+                sample.time.timestamp_millis(),
+                sample.seq as i64,
+                sample.latency.map(|latency| latency.as_micros() as i64),
+                sample.status
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn target_id(&mut self, host_id: usize, name: &str) -> Result<i64> {
+        if self.target_ids.len() <= host_id {
+            self.target_ids.resize(host_id + 1, None);
+        }
+        if let Some(target_id) = self.target_ids[host_id] {
+            return Ok(target_id);
+        }
+        self.conn.execute(
+            "INSERT INTO targets (session_id, position, name) VALUES (?1, ?2, ?3)",
+            params![self.session_id, host_id as i64, name],
+        )?;
+        let target_id = self.conn.last_insert_rowid();
+        self.target_ids[host_id] = Some(target_id);
+        Ok(target_id)
+    }
+}
+
+/// Read back the latest session recorded to `path`.
+pub fn load(path: &Path) -> Result<Recording> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| anyhow!("Could not open {}: {}", path.display(), e))?;
+    load_latest(&conn).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))
+}
+
+fn load_latest(conn: &Connection) -> Result<Recording> {
+    let session_id: Option<i64> =
+        conn.query_row("SELECT MAX(id) FROM sessions", [], |row| row.get(0))?;
+    let session_id = session_id.ok_or_else(|| anyhow!("no session was recorded"))?;
+    // Targets without a sample weren't recorded, so they're numbered in the
+    // order of their positions
+    let mut target_ids = vec![];
+    let mut recording = Recording::default();
+    let mut targets =
+        conn.prepare("SELECT id, name FROM targets WHERE session_id = ?1 ORDER BY position")?;
+    let mut rows = targets.query(params![session_id])?;
+    while let Some(row) = rows.next()? {
+        target_ids.push(row.get::<_, i64>(0)?);
+        recording.targets.push(row.get(1)?);
+    }
+    let mut samples = conn.prepare(
+        "SELECT samples.target_id, time_ms, latency_us, status
+         FROM samples JOIN targets ON targets.id = samples.target_id
+         WHERE session_id = ?1
+         ORDER BY time_ms, samples.rowid",
+    )?;
+    let mut rows = samples.query(params![session_id])?;
+    while let Some(row) = rows.next()? {
+        let target_id: i64 = row.get(0)?;
+        let status: String = row.get(3)?;
+        recording.samples.push(Recorded {
+            target: target_ids.iter().position(|&id| id == target_id).unwrap(),
+            time_ms: row.get(1)?,
+            result: replay::result(row.get(2)?, &status),
+        });
+    }
+    Ok(recording)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_latest, Recorder};
+    use crate::probe::PingResult;
+    use crate::samplelog::Sample;
+    use chrono::Local;
+    use rusqlite::Connection;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_record() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut recorder = Recorder::new(conn, Local::now(), Duration::from_millis(200)).unwrap();
+        for (host_id, target, latency) in [
+            (1, "10.0.0.1", None),
+            (0, "example.com", Some(Duration::from_micros(12345))),
+            (0, "example.com", Some(Duration::from_micros(11000))),
+        ] {
+            let sample = Sample {
+                time: Local::now(),
+                target,
+                ip: None,
+                seq: 1,
+                latency,
+                status: if latency.is_some() { "ok" } else { "timeout" },
+            };
+            recorder.record(host_id, &sample).unwrap();
+        }
+        let targets: Vec<(i64, String)> = recorder
+            .conn
+            .prepare("SELECT position, name FROM targets ORDER BY position")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            targets,
+            vec![(0, "example.com".to_string()), (1, "10.0.0.1".to_string())]
+        );
+        let total: i64 = recorder
+            .conn
+            .query_row("SELECT SUM(latency_us) FROM samples", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(total, 23345);
+
+        // A later session to the same file is what's played back
+        let mut recorder = Recorder::new(recorder.conn, Local::now(), Duration::default()).unwrap();
+        let sample = Sample {
+            time: Local::now(),
+            target: "example.org",
+            ip: None,
+            seq: 1,
+            latency: Some(Duration::from_micros(900)),
+            status: "ok",
+        };
+        recorder.record(0, &sample).unwrap();
+        let recording = load_latest(&recorder.conn).unwrap();
+        assert_eq!(recording.targets, vec!["example.org"]);
+        assert_eq!(recording.samples.len(), 1);
+        assert_eq!(
+            recording.samples[0].result,
+            PingResult::Pong(Duration::from_micros(900))
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use crate::probe::{Failure, PingResult};
+use crate::record;
+use crate::samplelog;
+use anyhow::Result;
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often playback looks at the controls while waiting for a sample.
+const TICK: Duration = Duration::from_millis(50);
+const MAX_SPEED: f64 = 64.0;
+
+/// A session recorded with `--record` or `--log`, to play back.
+#[derive(Debug, Default)]
+pub struct Recording {
+    pub targets: Vec<String>,
+    /// In the order they were taken
+    pub samples: Vec<Recorded>,
+}
+
+#[derive(Debug)]
+pub struct Recorded {
+    /// Index into `Recording::targets`
+    pub target: usize,
+    /// Milliseconds since the epoch
+    pub time_ms: i64,
+    pub result: PingResult,
+}
+
+/// Load the recording at `path`: a CSV file from `--log`, or the latest
+/// session of an SQLite database from `--record`.
+pub fn load(path: &Path) -> Result<Recording> {
+    if path.extension().is_some_and(|extension| extension == "csv") {
+        samplelog::read_csv(path)
+    } else {
+        record::load(path)
+    }
+}
+
+/// A sample as it was logged, with its latency in microseconds.
+pub fn result(latency_us: Option<i64>, status: &str) -> PingResult {
+    match (status, latency_us) {
+        ("ok", Some(latency_us)) => PingResult::Pong(Duration::from_micros(latency_us as u64)),
+        _ => status
+            .parse::<Failure>()
+            .map_or(PingResult::Timeout, PingResult::Failed),
+    }
+}
+
+/// Pause, speed and jumps, changed with keys while playing back.
+#[derive(Debug)]
+pub struct Controls {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    paused: bool,
+    speed: f64,
+    /// How far to jump ahead, next time playback looks
+    skip: Duration,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            state: Mutex::new(State {
+                paused: false,
+                speed: 1.0,
+                skip: Duration::default(),
+            }),
+        }
+    }
+}
+
+impl Controls {
+    /// Returns whether playback is now paused.
+    pub fn toggle_pause(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.paused = !state.paused;
+        state.paused
+    }
+
+    /// Double the speed, up to a limit. Returns the new speed.
+    pub fn faster(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        state.speed = (state.speed * 2.0).min(MAX_SPEED);
+        state.speed
+    }
+
+    /// Halve the speed, down to a limit. Returns the new speed.
+    pub fn slower(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        state.speed = (state.speed / 2.0).max(1.0 / MAX_SPEED);
+        state.speed
+    }
+
+    /// Skip `by` of the recording, sending its samples straight away.
+    pub fn jump(&self, by: Duration) {
+        self.state.lock().unwrap().skip += by;
+    }
+
+    /// Whether playback is paused, its speed and how far to skip, which is
+    /// only skipped once.
+    fn take(&self) -> (bool, f64, Duration) {
+        let mut state = self.state.lock().unwrap();
+        let skip = std::mem::take(&mut state.skip);
+        (state.paused, state.speed, skip)
+    }
+}
+
+/// Send each of `samples` to the stream of its target at the pace they were
+/// recorded, as `controls` say. Returns early once the streams are gone
+/// because gping is quitting.
+pub fn play(samples: Vec<Recorded>, streams: Vec<mpsc::Sender<PingResult>>, controls: &Controls) {
+    // Where playback is in the recording, in milliseconds since the epoch
+    let mut position = samples.first().map_or(0f64, |sample| sample.time_ms as f64);
+    for sample in samples {
+        loop {
+            let (paused, speed, skip) = controls.take();
+            position += skip.as_secs_f64() * 1000.0;
+            let behind = sample.time_ms as f64 - position;
+            if behind <= 0.0 {
+                break;
+            }
+            if paused {
+                thread::sleep(TICK);
+                continue;
+            }
+            let wait = (behind / speed).min(TICK.as_secs_f64() * 1000.0);
+            thread::sleep(Duration::from_secs_f64(wait / 1000.0));
+            position += wait * speed;
+        }
+        if streams[sample.target].send(sample.result).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{play, result, Controls, Recorded};
+    use crate::probe::{Failure, PingResult};
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    pub fn test_result() {
+        assert_eq!(
+            result(Some(1500), "ok"),
+            PingResult::Pong(Duration::from_micros(1500))
+        );
+        assert_eq!(result(None, "timeout"), PingResult::Timeout);
+        assert_eq!(
+            result(None, "unreachable"),
+            PingResult::Failed(Failure::Unreachable)
+        );
+        assert_eq!(result(None, "gone fishing"), PingResult::Timeout);
+    }
+
+    #[test]
+    pub fn test_controls() {
+        let controls = Controls::default();
+        assert!(controls.toggle_pause());
+        assert!(!controls.toggle_pause());
+        assert_eq!(controls.faster(), 2.0);
+        assert_eq!(controls.slower(), 1.0);
+        controls.jump(Duration::from_secs(60));
+        assert_eq!(controls.take(), (false, 1.0, Duration::from_secs(60)));
+        assert_eq!(controls.take().2, Duration::default());
+    }
+
+    #[test]
+    pub fn test_play() {
+        let (tx0, rx0) = mpsc::channel();
+        let (tx1, rx1) = mpsc::channel();
+        let samples = vec![
+            Recorded {
+                target: 0,
+                time_ms: 1_000_000,
+                result: PingResult::Timeout,
+            },
+            Recorded {
+                target: 1,
+                time_ms: 1_000_100,
+                result: PingResult::Pong(Duration::from_millis(3)),
+            },
+            Recorded {
+                target: 0,
+                time_ms: 1_060_000,
+                result: PingResult::Failed(Failure::TooBig),
+            },
+        ];
+        let controls = Controls::default();
+        // A minute of the recording is jumped over rather than waited for
+        controls.jump(Duration::from_secs(60));
+        let start = Instant::now();
+        play(samples, vec![tx0, tx1], &controls);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            rx0.iter().collect::<Vec<_>>(),
+            vec![PingResult::Timeout, PingResult::Failed(Failure::TooBig)]
+        );
+        assert_eq!(rx1.iter().count(), 1);
+    }
+}
@@ -0,0 +1,450 @@
+use crate::probe::Family;
+use crate::source;
+use anyhow::{anyhow, Result};
+use dns_lookup::{lookup_addr, lookup_host};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+
+/// Turns target hostnames into addresses, consulting a table of aliases
+/// before asking the nameserver.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    nameserver: Nameserver,
+    aliases: HashMap<String, String>,
+    /// Addresses given for names with `--resolve`, which win over aliases and
+    /// are never completed to
+    pins: HashMap<String, String>,
+    /// Only return addresses of this family, for `-4` and `-6`
+    family: Option<Family>,
+    dns: Dns,
+}
+
+/// The resolver asking a `Nameserver::Server`, built when it's first needed
+/// and then shared by clones, as each one starts its own runtime.
+#[derive(Clone, Default)]
+struct Dns(Arc<Mutex<Option<Arc<trust_dns_resolver::Resolver>>>>);
+
+impl fmt::Debug for Dns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Dns")
+    }
+}
+
+impl Dns {
+    fn get(&self, server: &Server) -> Result<Arc<trust_dns_resolver::Resolver>> {
+        let mut resolver = self.0.lock().unwrap();
+        if resolver.is_none() {
+            *resolver = Some(Arc::new(server.resolver_with(ResolverOpts::default())?));
+        }
+        Ok(resolver.clone().unwrap())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Nameserver {
+    /// The operating system's resolver, honouring /etc/hosts, nsswitch etc.
+    System,
+    /// DNS queries sent straight to this server.
+    Server(Server),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Udp,
+    /// DNS-over-TLS
+    Tls,
+    /// DNS-over-HTTPS
+    Https,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Server {
+    pub protocol: Protocol,
+    pub addr: SocketAddr,
+    /// Name the server's certificate is checked against, for TLS and HTTPS
+    pub tls_name: String,
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.protocol {
+            Protocol::Udp => write!(f, "{}", self.addr),
+            Protocol::Tls => write!(f, "tls://{} ({})", self.tls_name, self.addr),
+            Protocol::Https => write!(f, "https://{} ({})", self.tls_name, self.addr),
+        }
+    }
+}
+
+impl Resolver {
+    pub fn new(nameserver: Nameserver) -> Self {
+        Resolver {
+            nameserver,
+            aliases: HashMap::new(),
+            pins: HashMap::new(),
+            family: None,
+            dns: Dns::default(),
+        }
+    }
+
+    pub fn restrict_to(&mut self, family: Family) {
+        self.family = Some(family);
+    }
+
+    /// Add aliases mapping a name to the hostname or address to use for it.
+    pub fn add_aliases(&mut self, aliases: Vec<(String, String)>) {
+        for (alias, target) in aliases {
+            self.aliases.entry(alias).or_insert(target);
+        }
+    }
+
+    /// Pin names to the addresses to use for them.
+    pub fn pin(&mut self, pins: Vec<(String, String)>) {
+        self.pins.extend(pins);
+    }
+
+    /// Expand a partial name to the one alias containing it, so `prod` can
+    /// stand for `prod-db`. Addresses, URLs, and names that resolve as they
+    /// are or match no alias are returned as is.
+    pub fn complete(&self, input: &str) -> Result<String> {
+        if self.pins.contains_key(input)
+            || self.aliases.contains_key(input)
+            || input.parse::<IpAddr>().is_ok()
+            || scoped_ipv6(input).is_some()
+            || source::is_url(input)
+        {
+            return Ok(input.to_string());
+        }
+        let needle = input.to_lowercase();
+        let mut candidates: Vec<_> = self
+            .aliases
+            .keys()
+            .filter(|alias| alias.to_lowercase().contains(&needle))
+            .collect();
+        candidates.sort();
+        // Looked up only when it could be completed, as that's slow
+        if !candidates.is_empty() && self.nameserver.lookup(input, &self.dns).is_ok() {
+            return Ok(input.to_string());
+        }
+        match candidates.len() {
+            0 => Ok(input.to_string()),
+            1 => Ok(candidates[0].clone()),
+            _ => Err(anyhow!(
+                "{} is ambiguous, it could be any of: {}",
+                input,
+                candidates
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    pub fn lookup(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let target = self
+            .pins
+            .get(host)
+            .or_else(|| self.aliases.get(host))
+            .map(String::as_str)
+            .unwrap_or(host);
+        let ips = self.nameserver.lookup(target, &self.dns)?;
+        let family = match self.family {
+            Some(family) => family,
+            None => return Ok(ips),
+        };
+        let ips: Vec<_> = ips.into_iter().filter(|ip| family.matches(ip)).collect();
+        if ips.is_empty() {
+            return Err(anyhow!("{} has no {} addresses", host, family));
+        }
+        Ok(ips)
+    }
+
+    pub fn reverse(&self, ip: IpAddr) -> Result<String> {
+        self.nameserver.reverse(ip, &self.dns)
+    }
+}
+
+/// Split an IPv6 literal with a zone index, like `fe80::1%eth0`, into the
+/// address and the zone. The zone can't be represented in an `IpAddr`, so such
+/// targets must be pinged by their original string.
+pub fn scoped_ipv6(host: &str) -> Option<(Ipv6Addr, &str)> {
+    let idx = host.find('%')?;
+    let ip = host[..idx].parse().ok()?;
+    let zone = &host[idx + 1..];
+    if zone.is_empty() {
+        return None;
+    }
+    Some((ip, zone))
+}
+
+impl Nameserver {
+    fn lookup(&self, host: &str, dns: &Dns) -> Result<Vec<IpAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+        if let Some((ip, _)) = scoped_ipv6(host) {
+            return Ok(vec![IpAddr::V6(ip)]);
+        }
+        let ips = match self {
+            Nameserver::System => {
+                lookup_host(host).map_err(|_| anyhow!("Could not resolve hostname {}", host))?
+            }
+            Nameserver::Server(server) => {
+                let response = dns.get(server)?.lookup_ip(host).map_err(|e| {
+                    anyhow!("Could not resolve hostname {} via {}: {}", host, server, e)
+                })?;
+                response.iter().collect()
+            }
+        };
+        if ips.is_empty() {
+            return Err(anyhow!("Hostname {} has no addresses", host));
+        }
+        Ok(ips)
+    }
+
+    /// Find the name an address points back to with a PTR lookup.
+    fn reverse(&self, ip: IpAddr, dns: &Dns) -> Result<String> {
+        let name = match self {
+            Nameserver::System => lookup_addr(&ip)?,
+            Nameserver::Server(server) => dns
+                .get(server)?
+                .reverse_lookup(ip)?
+                .iter()
+                .next()
+                .ok_or_else(|| anyhow!("No PTR record for {}", ip))?
+                .to_string(),
+        };
+        Ok(name.trim_end_matches('.').to_string())
+    }
+}
+
+impl Server {
+    /// A resolver for timing lookups, which asks the server every time rather
+    /// than answering from a cache, and doesn't retry.
+    pub fn timing_resolver(&self, timeout: Duration) -> Result<trust_dns_resolver::Resolver> {
+        self.resolver_with(ResolverOpts {
+            timeout,
+            attempts: 1,
+            cache_size: 0,
+            ..ResolverOpts::default()
+        })
+    }
+
+    fn resolver_with(&self, options: ResolverOpts) -> Result<trust_dns_resolver::Resolver> {
+        let ips = [self.addr.ip()];
+        let port = self.addr.port();
+        let tls_name = self.tls_name.clone();
+        let group = match self.protocol {
+            Protocol::Udp => NameServerConfigGroup::from_ips_clear(&ips, port, true),
+            Protocol::Tls => NameServerConfigGroup::from_ips_tls(&ips, port, tls_name, true),
+            Protocol::Https => NameServerConfigGroup::from_ips_https(&ips, port, tls_name, true),
+        };
+        let config = ResolverConfig::from_parts(None, vec![], group);
+        Ok(trust_dns_resolver::Resolver::new(config, options)?)
+    }
+}
+
+/// Parse a `host:ip` override, pinning `host` to `ip` like curl's `--resolve`.
+pub fn parse_override(input: &str) -> Result<(String, String)> {
+    let invalid = || anyhow!("Invalid override {:?}, expected host:ip", input);
+    let idx = input.find(':').ok_or_else(invalid)?;
+    let (host, ip) = (&input[..idx], &input[idx + 1..]);
+    let ip = ip.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() || ip.parse::<IpAddr>().is_err() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), ip.to_string()))
+}
+
+/// Split a `host:port` target, with IPv6 addresses in brackets like
+/// `[2001:db8::1]:443`.
+pub fn split_port(input: &str) -> Result<(String, u16)> {
+    let invalid = || anyhow!("Invalid target {:?}, expected host:port", input);
+    let idx = input.rfind(':').ok_or_else(invalid)?;
+    let host = input[..idx].trim_start_matches('[').trim_end_matches(']');
+    let port = input[idx + 1..].parse().map_err(|_| invalid())?;
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Parse a `name@server` query, where the server is given as for
+/// [`parse_server`].
+pub fn parse_query(input: &str) -> Result<(String, Server)> {
+    let invalid = || anyhow!("Invalid query {:?}, expected name@server", input);
+    let idx = input.find('@').ok_or_else(invalid)?;
+    let name = &input[..idx];
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    Ok((name.to_string(), parse_server(&input[idx + 1..])?))
+}
+
+/// Parse a DNS server given as `1.1.1.1`, `1.1.1.1:5353`, `[::1]:53`, or as
+/// an encrypted endpoint like `tls://dns.google` or
+/// `https://cloudflare-dns.com`. Encrypted endpoints given by name are
+/// bootstrapped with the system resolver.
+pub fn parse_server(input: &str) -> Result<Server> {
+    let (protocol, rest) = if let Some(rest) = input.strip_prefix("tls://") {
+        (Protocol::Tls, rest)
+    } else if let Some(rest) = input.strip_prefix("https://") {
+        (Protocol::Https, rest)
+    } else {
+        (Protocol::Udp, input)
+    };
+    let default_port = match protocol {
+        Protocol::Udp => 53,
+        Protocol::Tls => 853,
+        Protocol::Https => 443,
+    };
+    // Any path on a DoH URL is ignored, the resolver always uses /dns-query
+    let rest = rest.split('/').next().unwrap_or_default();
+    let invalid = || anyhow!("Invalid DNS server address {:?}", input);
+
+    let (host, addr) = if let Ok(ip) = rest.parse::<IpAddr>() {
+        (ip.to_string(), SocketAddr::new(ip, default_port))
+    } else if let Ok(addr) = rest.parse::<SocketAddr>() {
+        (addr.ip().to_string(), addr)
+    } else if protocol == Protocol::Udp {
+        return Err(invalid());
+    } else {
+        let (name, port) = match rest.rfind(':') {
+            Some(idx) => (
+                &rest[..idx],
+                rest[idx + 1..].parse().map_err(|_| invalid())?,
+            ),
+            None => (rest, default_port),
+        };
+        let ip = Resolver::new(Nameserver::System).lookup(name)?[0];
+        (name.to_string(), SocketAddr::new(ip, port))
+    };
+    Ok(Server {
+        protocol,
+        addr,
+        tls_name: host,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        parse_override, parse_query, parse_server, scoped_ipv6, split_port, Nameserver, Protocol,
+        Resolver,
+    };
+    use crate::probe::Family;
+
+    #[test]
+    pub fn test_scoped_ipv6() {
+        let (ip, zone) = scoped_ipv6("fe80::1%eth0").unwrap();
+        assert_eq!(ip.to_string(), "fe80::1");
+        assert_eq!(zone, "eth0");
+        assert!(scoped_ipv6("fe80::1").is_none());
+        assert!(scoped_ipv6("fe80::1%").is_none());
+        assert!(scoped_ipv6("example.com%eth0").is_none());
+    }
+
+    #[test]
+    pub fn test_parse_override() {
+        let (host, ip) = parse_override("example.com:192.0.2.1").unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(ip, "192.0.2.1");
+        assert_eq!(
+            parse_override("example.com:[2001:db8::1]").unwrap().1,
+            "2001:db8::1"
+        );
+        assert!(parse_override("example.com").is_err());
+        assert!(parse_override("example.com:not-an-ip").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_query() {
+        let (name, server) = parse_query("example.com@1.1.1.1").unwrap();
+        assert_eq!(name, "example.com");
+        assert_eq!(server.addr, "1.1.1.1:53".parse().unwrap());
+        assert_eq!(server.protocol, Protocol::Udp);
+        let (_, server) = parse_query("example.com@[2606:4700::1111]:5353").unwrap();
+        assert_eq!(server.addr, "[2606:4700::1111]:5353".parse().unwrap());
+        assert!(parse_query("example.com").is_err());
+        assert!(parse_query("@1.1.1.1").is_err());
+    }
+
+    #[test]
+    pub fn test_split_port() {
+        assert_eq!(
+            split_port("example.com:443").unwrap(),
+            ("example.com".to_string(), 443)
+        );
+        assert_eq!(
+            split_port("[2001:db8::1]:22").unwrap(),
+            ("2001:db8::1".to_string(), 22)
+        );
+        assert!(split_port("example.com").is_err());
+        assert!(split_port("example.com:https").is_err());
+        assert!(split_port(":443").is_err());
+    }
+
+    #[test]
+    pub fn test_complete() {
+        let mut resolver = Resolver::new(Nameserver::System);
+        resolver.add_aliases(vec![
+            ("prod-db".to_string(), "10.0.0.5".to_string()),
+            ("staging-db".to_string(), "10.0.1.5".to_string()),
+        ]);
+        assert_eq!(resolver.complete("prod").unwrap(), "prod-db");
+        assert_eq!(resolver.complete("example.com").unwrap(), "example.com");
+        assert!(resolver.complete("db").is_err());
+        // Names that resolve as they are aren't completed
+        resolver.add_aliases(vec![("localhost-dev".to_string(), "10.0.2.5".to_string())]);
+        assert_eq!(resolver.complete("localhost").unwrap(), "localhost");
+        // Pinned names are used as they are, and win over aliases
+        resolver.pin(vec![
+            ("prod".to_string(), "10.0.3.5".to_string()),
+            ("staging-db".to_string(), "10.0.4.5".to_string()),
+        ]);
+        assert_eq!(resolver.complete("prod").unwrap(), "prod");
+        assert_eq!(resolver.lookup("prod").unwrap()[0].to_string(), "10.0.3.5");
+        assert_eq!(
+            resolver.lookup("staging-db").unwrap()[0].to_string(),
+            "10.0.4.5"
+        );
+        assert_eq!(
+            resolver.lookup("prod-db").unwrap()[0].to_string(),
+            "10.0.0.5"
+        );
+    }
+
+    #[test]
+    pub fn test_restrict_to() {
+        let mut resolver = Resolver::new(Nameserver::System);
+        resolver.restrict_to(Family::V6);
+        assert_eq!(resolver.lookup("::1").unwrap()[0].to_string(), "::1");
+        assert!(resolver.lookup("127.0.0.1").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_server() {
+        assert_eq!(
+            parse_server("1.1.1.1").unwrap().addr.to_string(),
+            "1.1.1.1:53"
+        );
+        assert_eq!(parse_server("1.1.1.1:5353").unwrap().addr.port(), 5353);
+        assert_eq!(parse_server("::1").unwrap().addr.to_string(), "[::1]:53");
+        assert!(parse_server("dns.example.com").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_encrypted_server() {
+        let server = parse_server("tls://1.1.1.1").unwrap();
+        assert_eq!(server.protocol, Protocol::Tls);
+        assert_eq!(server.addr.to_string(), "1.1.1.1:853");
+        let server = parse_server("https://1.1.1.1/dns-query").unwrap();
+        assert_eq!(server.protocol, Protocol::Https);
+        assert_eq!(server.addr.port(), 443);
+        assert_eq!(server.tls_name, "1.1.1.1");
+    }
+}
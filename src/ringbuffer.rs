@@ -39,7 +39,7 @@ impl<T: Copy + Default> FixedRingBuffer<T> {
         &self.buf[self.head..self.buf.len()]
     }
 
-    pub fn iter(&self) -> std::slice::Iter<T> {
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
         self.as_slice().iter()
     }
 }
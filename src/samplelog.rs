@@ -0,0 +1,529 @@
+use crate::duration;
+use crate::replay::{self, Recorded, Recording};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, SecondsFormat};
+use serde_json::json;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const CSV_HEADER: &str = "timestamp,target,seq,latency_us,status";
+
+/// One probe of a target, as it's logged.
+#[derive(Debug)]
+pub struct Sample<'a> {
+    pub time: DateTime<Local>,
+    pub target: &'a str,
+    /// What the target resolved to, if it's a host
+    pub ip: Option<&'a str>,
+    /// Counting the target's probes from 1
+    pub seq: u64,
+    /// None unless the probe was answered
+    pub latency: Option<Duration>,
+    /// `ok`, `timeout` or what went wrong, like `unreachable`
+    pub status: &'a str,
+}
+
+/// When `--log-rotate` moves the log aside to start another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    /// Once the log would grow past this many bytes
+    Size(u64),
+    /// Once the log has been written to for this long
+    Every(Duration),
+}
+
+impl FromStr for Limit {
+    type Err = anyhow::Error;
+
+    /// Sizes have an upper case unit, like `500K`, `100M` or `1GB`, and
+    /// times a lower case one, like `30m`, `12h` or `1d`.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "--log-rotate is a size like 100M or a time like 1d, not {}",
+                s
+            )
+        };
+        let number = s.trim_end_matches('B');
+        let split = number
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(number.len());
+        let (value, unit) = number.split_at(split);
+        let bytes = match unit {
+            "K" => Some(1 << 10),
+            "M" => Some(1 << 20),
+            "G" => Some(1 << 30),
+            "" if s.ends_with('B') => Some(1),
+            _ => None,
+        };
+        let limit = match (bytes, unit) {
+            (Some(bytes), _) => {
+                let value: f64 = value.parse().map_err(|_| invalid())?;
+                Limit::Size((value * bytes as f64) as u64)
+            }
+            (None, "d") => {
+                let days: f64 = value.parse().map_err(|_| invalid())?;
+                Limit::Every(Duration::from_secs_f64(days * 24.0 * 3600.0))
+            }
+            (None, _) => Limit::Every(duration::parse(s).map_err(|_| invalid())?),
+        };
+        match limit {
+            Limit::Size(0) => Err(invalid()),
+            Limit::Every(every) if every == Duration::default() => Err(invalid()),
+            limit => Ok(limit),
+        }
+    }
+}
+
+/// How `--log-rotate` and `--log-keep` look after the log.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rotation {
+    pub limit: Limit,
+    /// How many logs that were moved aside to keep, deleting older ones
+    pub keep: usize,
+}
+
+/// Every sample appended to a CSV file for `--log`, so sessions can be
+/// analysed afterwards.
+pub struct CsvLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    rotation: Option<Rotation>,
+    /// When the log was opened and how big it is, to know when to rotate it
+    opened: Instant,
+    size: u64,
+}
+
+impl CsvLog {
+    /// Open `path` to append to, writing the header if it's new or empty.
+    pub fn open(path: &Path, rotation: Option<Rotation>) -> Result<Self> {
+        let (writer, size) = CsvLog::append(path)?;
+        Ok(CsvLog {
+            path: path.to_path_buf(),
+            writer,
+            rotation,
+            opened: Instant::now(),
+            size,
+        })
+    }
+
+    fn append(path: &Path) -> Result<(BufWriter<File>, u64)> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("Could not open {}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        let mut size = writer.get_ref().metadata()?.len();
+        if size == 0 {
+            writeln!(writer, "{}", CSV_HEADER)?;
+            writer.flush()?;
+            size = CSV_HEADER.len() as u64 + 1;
+        }
+        Ok((writer, size))
+    }
+
+    /// Flushed straight away so the file is complete however gping stops.
+    pub fn write(&mut self, sample: &Sample) -> Result<()> {
+        let line = format!("{}\n", csv_row(sample));
+        if self.due(line.len() as u64) {
+            self.rotate()?;
+        }
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Whether to rotate before writing `len` more bytes. A log with only
+    /// its header is never rotated, however big a line is.
+    fn due(&self, len: u64) -> bool {
+        let rotation = match self.rotation {
+            Some(rotation) => rotation,
+            None => return false,
+        };
+        let has_samples = self.size > CSV_HEADER.len() as u64 + 1;
+        match rotation.limit {
+            Limit::Size(limit) => has_samples && self.size + len > limit,
+            Limit::Every(every) => has_samples && self.opened.elapsed() >= every,
+        }
+    }
+
+    /// Move the log aside, named for when that happened, and start another,
+    /// deleting the oldest logs beyond what's kept.
+    fn rotate(&mut self) -> Result<()> {
+        let keep = self.rotation.map_or(0, |rotation| rotation.keep);
+        self.writer.flush()?;
+        let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut rotated = rotated_path(&self.path, &stamp);
+        // Tiny limits can rotate more than once a second
+        for n in 1.. {
+            if !rotated.exists() {
+                break;
+            }
+            rotated = rotated_path(&self.path, &format!("{}-{}", stamp, n));
+        }
+        fs::rename(&self.path, &rotated)
+            .map_err(|e| anyhow!("Could not move {} aside: {}", self.path.display(), e))?;
+        let (writer, size) = CsvLog::append(&self.path)?;
+        self.writer = writer;
+        self.size = size;
+        self.opened = Instant::now();
+        let mut logs = rotated_logs(&self.path)?;
+        logs.sort();
+        for old in &logs[..logs.len().saturating_sub(keep)] {
+            fs::remove_file(old)
+                .map_err(|e| anyhow!("Could not delete {}: {}", old.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// What comes before and after the stamp in the names of logs rotated from
+/// `path`, like `out.` and `.csv` for `out.csv`.
+fn rotated_name(path: &Path) -> (String, String) {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map_or_else(String::new, |extension| {
+        format!(".{}", extension.to_string_lossy())
+    });
+    (format!("{}.", stem), extension)
+}
+
+/// Where `path` is moved to when it's rotated, like `out.20210301-100000.csv`
+/// for `out.csv`. Stamps sort in the order logs were rotated.
+fn rotated_path(path: &Path, stamp: &str) -> PathBuf {
+    let (prefix, suffix) = rotated_name(path);
+    path.with_file_name(format!("{}{}{}", prefix, stamp, suffix))
+}
+
+/// Logs that were rotated from `path`, found by their names.
+fn rotated_logs(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let (prefix, suffix) = rotated_name(path);
+    let mut logs = vec![];
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let stamp = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(&suffix));
+        if let Some(stamp) = stamp {
+            if !stamp.is_empty() && stamp.chars().all(|c| c.is_ascii_digit() || c == '-') {
+                logs.push(path.with_file_name(name));
+            }
+        }
+    }
+    Ok(logs)
+}
+
+/// Formats `--output` can stream samples in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    /// A JSON object per line
+    JsonLines,
+}
+
+impl FromStr for Output {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" => Ok(Output::JsonLines),
+            _ => Err(anyhow!("--output can only be jsonl, not {}", s)),
+        }
+    }
+}
+
+/// Every sample written as a line of JSON, for `--output jsonl`.
+pub struct JsonLines {
+    writer: Box<dyn Write>,
+}
+
+impl JsonLines {
+    pub fn new(writer: Box<dyn Write>) -> Self {
+        JsonLines { writer }
+    }
+
+    /// Flushed straight away so readers like jq see each sample as it comes.
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        writeln!(self.writer, "{}", json_line(sample))?;
+        self.writer.flush()
+    }
+}
+
+fn timestamp(sample: &Sample) -> String {
+    sample.time.to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+fn json_line(sample: &Sample) -> String {
+    json!({
+        "timestamp": timestamp(sample),
+        "host": sample.target,
+        "ip": sample.ip,
+        "seq": sample.seq,
+        "latency_us": sample.latency.map(|latency| latency.as_micros() as u64),
+        "status": sample.status,
+    })
+    .to_string()
+}
+
+fn csv_row(sample: &Sample) -> String {
+    format!(
+        "{},{},{},{},{}",
+        timestamp(sample),
+        csv_field(sample.target),
+        sample.seq,
+        sample
+            .latency
+            .map_or_else(String::new, |latency| latency.as_micros().to_string()),
+        csv_field(sample.status)
+    )
+}
+
+/// Quote a field if it has commas, quotes or line breaks, like targets that
+/// are watched commands can.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Read back a CSV file written for `--log`, with its targets in the order
+/// they first appear.
+pub fn read_csv(path: &Path) -> Result<Recording> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+    parse_csv(&text).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))
+}
+
+fn parse_csv(text: &str) -> Result<Recording> {
+    let mut recording = Recording::default();
+    for (idx, record) in csv_records(text).into_iter().enumerate() {
+        // Logs appended to by several sessions have a header at the top only
+        if idx == 0 && record.join(",") == CSV_HEADER {
+            continue;
+        }
+        let line = idx + 1;
+        let (time, name, latency_us, status) = match record.as_slice() {
+            [time, name, _seq, latency_us, status] => (time, name, latency_us, status),
+            _ => return Err(anyhow!("line {} does not have 5 fields", line)),
+        };
+        let time = DateTime::parse_from_rfc3339(time)
+            .map_err(|e| anyhow!("line {} has a bad timestamp: {}", line, e))?;
+        let latency_us = match latency_us.as_str() {
+            "" => None,
+            latency_us => Some(
+                latency_us
+                    .parse()
+                    .map_err(|_| anyhow!("line {} has a bad latency", line))?,
+            ),
+        };
+        let target = match recording.targets.iter().position(|target| target == name) {
+            Some(target) => target,
+            None => {
+                recording.targets.push(name.clone());
+                recording.targets.len() - 1
+            }
+        };
+        recording.samples.push(Recorded {
+            target,
+            time_ms: time.timestamp_millis(),
+            result: replay::result(latency_us, status),
+        });
+    }
+    Ok(recording)
+}
+
+/// Split CSV into records of fields, undoing what `csv_field` quotes.
+fn csv_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        csv_field, csv_row, json_line, parse_csv, rotated_logs, CsvLog, Limit, Rotation, Sample,
+        CSV_HEADER,
+    };
+    use crate::probe::{Failure, PingResult};
+    use chrono::{Local, TimeZone};
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_csv_row() {
+        let time = Local.with_ymd_and_hms(2021, 3, 1, 10, 0, 0).unwrap()
+            + chrono::Duration::milliseconds(250);
+        let row = csv_row(&Sample {
+            time,
+            target: "example.com",
+            ip: Some("93.184.216.34"),
+            seq: 7,
+            latency: Some(Duration::from_micros(12345)),
+            status: "ok",
+        });
+        assert!(row.ends_with(",example.com,7,12345,ok"));
+        assert!(row.starts_with("2021-03-01T10:00:00.250"));
+        let row = csv_row(&Sample {
+            time,
+            target: "example.com",
+            ip: None,
+            seq: 8,
+            latency: None,
+            status: "timeout",
+        });
+        assert!(row.ends_with(",example.com,8,,timeout"));
+    }
+
+    #[test]
+    pub fn test_json_line() {
+        let line = json_line(&Sample {
+            time: Local.with_ymd_and_hms(2021, 3, 1, 10, 0, 0).unwrap()
+                + chrono::Duration::milliseconds(250),
+            target: "example.com",
+            ip: Some("93.184.216.34"),
+            seq: 7,
+            latency: None,
+            status: "timeout",
+        });
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["host"], "example.com");
+        assert_eq!(value["ip"], "93.184.216.34");
+        assert_eq!(value["seq"], 7);
+        assert!(value["latency_us"].is_null());
+        assert_eq!(value["status"], "timeout");
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    pub fn test_parse_csv() {
+        let time = Local.with_ymd_and_hms(2021, 3, 1, 10, 0, 0).unwrap()
+            + chrono::Duration::milliseconds(250);
+        let command = "jq -r '.a, .b' \"x.json\"";
+        let rows: Vec<String> = vec![
+            (command, Some(Duration::from_micros(12345)), "ok"),
+            ("10.0.0.1", None, "timeout"),
+            (command, None, "parse error"),
+        ]
+        .into_iter()
+        .map(|(target, latency, status)| {
+            csv_row(&Sample {
+                time,
+                target,
+                ip: None,
+                seq: 1,
+                latency,
+                status,
+            })
+        })
+        .collect();
+        let text = format!("{}\n{}\n", CSV_HEADER, rows.join("\n"));
+        let recording = parse_csv(&text).unwrap();
+        assert_eq!(recording.targets, vec![command, "10.0.0.1"]);
+        let samples: Vec<_> = recording
+            .samples
+            .iter()
+            .map(|sample| (sample.target, sample.result))
+            .collect();
+        assert_eq!(
+            samples,
+            vec![
+                (0, PingResult::Pong(Duration::from_micros(12345))),
+                (1, PingResult::Timeout),
+                (0, PingResult::Failed(Failure::ParseError)),
+            ]
+        );
+        assert_eq!(recording.samples[0].time_ms, time.timestamp_millis());
+        assert!(parse_csv("not,a,log\n").is_err());
+    }
+
+    #[test]
+    pub fn test_limit() {
+        assert_eq!("100M".parse::<Limit>().unwrap(), Limit::Size(100 << 20));
+        assert_eq!("1GB".parse::<Limit>().unwrap(), Limit::Size(1 << 30));
+        assert_eq!("500K".parse::<Limit>().unwrap(), Limit::Size(500 << 10));
+        assert_eq!("4096B".parse::<Limit>().unwrap(), Limit::Size(4096));
+        assert_eq!(
+            "30m".parse::<Limit>().unwrap(),
+            Limit::Every(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(
+            "1d".parse::<Limit>().unwrap(),
+            Limit::Every(Duration::from_secs(24 * 3600))
+        );
+        assert!("0M".parse::<Limit>().is_err());
+        assert!("100T".parse::<Limit>().is_err());
+        assert!("MB".parse::<Limit>().is_err());
+    }
+
+    #[test]
+    pub fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("gping-test-rotate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.csv");
+        let rotation = Rotation {
+            limit: Limit::Size(CSV_HEADER.len() as u64 + 100),
+            keep: 2,
+        };
+        let mut log = CsvLog::open(&path, Some(rotation)).unwrap();
+        let sample = Sample {
+            time: Local::now(),
+            target: "example.com",
+            ip: None,
+            seq: 1,
+            latency: Some(Duration::from_micros(12345)),
+            status: "ok",
+        };
+        // Each log has room for one sample
+        for _ in 0..5 {
+            log.write(&sample).unwrap();
+        }
+        let logs = rotated_logs(&path).unwrap();
+        let log = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(logs.len(), 2);
+        assert!(log.starts_with(CSV_HEADER));
+        assert_eq!(log.lines().count(), 2);
+    }
+
+    #[test]
+    pub fn test_csv_field() {
+        assert_eq!(csv_field("10.0.0.1"), "10.0.0.1");
+        assert_eq!(
+            csv_field(r#"jq -r '.a, .b' "x.json""#),
+            r#""jq -r '.a, .b' ""x.json""""#
+        );
+    }
+}
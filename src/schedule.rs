@@ -0,0 +1,230 @@
+use crate::config::ScheduleConfig;
+use crate::duration;
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// When a measurement window starts, as the first five fields of a crontab
+/// line: `minute hour day-of-month month day-of-week`. Each field is `*`, a
+/// number or a range like `1-5`, optionally with a step like `*/15`, or a
+/// comma separated list of those. Sunday is 0 or 7.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day fields were `*`, as cron starts on either day
+    /// matching when both are restricted
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// Parse one field into a bitmask of the values it allows.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let invalid = || anyhow!("Invalid cron field {:?}, expected {}-{}", field, min, max);
+    let number = |value: &str| -> Result<u32> {
+        let value = value.parse().map_err(|_| invalid())?;
+        if value < min || value > max {
+            return Err(invalid());
+        }
+        Ok(value)
+    };
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(slash) => (
+                &part[..slash],
+                part[slash + 1..].parse().map_err(|_| invalid())?,
+            ),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (first, last) = if range == "*" {
+            (min, max)
+        } else if let Some(dash) = range.find('-') {
+            (number(&range[..dash])?, number(&range[dash + 1..])?)
+        } else {
+            let value = number(range)?;
+            // Like cron, `5/10` starts at 5 and carries on to the end
+            (value, if part.contains('/') { max } else { value })
+        };
+        if first > last {
+            return Err(invalid());
+        }
+        for value in (first..=last).step_by(step) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let fields: Vec<_> = input.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow!(
+                "Invalid schedule {:?}, expected minute hour day-of-month month day-of-week",
+                input
+            ));
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if weekdays & 1 << 7 != 0 {
+            weekdays |= 1;
+        }
+        Ok(Cron {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+impl Cron {
+    /// Whether a window starts on the minute of `time`.
+    pub fn matches(&self, time: NaiveDateTime) -> bool {
+        let has = |mask: u64, value: u32| mask & 1 << value != 0;
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+        let on_day = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        on_day
+            && has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, time.month())
+    }
+}
+
+/// A `[[schedule.window]]`: probing starts whenever `cron` matches and
+/// carries on for `length`.
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub cron: Cron,
+    pub length: Duration,
+}
+
+/// The windows gping probes in, running headless, instead of all the time.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    windows: Vec<Window>,
+}
+
+impl Schedule {
+    pub fn from_config(config: &ScheduleConfig) -> Result<Self> {
+        let windows = config
+            .window
+            .iter()
+            .map(|window| {
+                let length = duration::parse(&window.duration)?;
+                if length < Duration::from_secs(60) {
+                    return Err(anyhow!(
+                        "A scheduled window must last at least a minute, not {:?}",
+                        window.duration
+                    ));
+                }
+                Ok(Window {
+                    cron: window.cron.parse()?,
+                    length,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Schedule { windows })
+    }
+
+    /// When the window that's open at `now` closes, or `None` if none is.
+    /// With overlapping windows, probing carries on until the last closes.
+    pub fn open_until(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let minute = now.with_second(0)?.with_nanosecond(0)?;
+        self.windows
+            .iter()
+            .filter_map(|window| {
+                let length = chrono::Duration::from_std(window.length).ok()?;
+                // Windows that opened up to `length` ago may still be open
+                (0..=length.num_minutes())
+                    .map(|ago| minute - chrono::Duration::minutes(ago))
+                    .filter(|&start| window.cron.matches(start))
+                    .map(|start| start + length)
+                    .find(|&end| end > now)
+            })
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cron, Schedule, Window};
+    use chrono::NaiveDate;
+    use std::time::Duration;
+
+    fn at(day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+        // 1 March 2021 was a Monday
+        NaiveDate::from_ymd_opt(2021, 3, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    pub fn test_parse_cron() {
+        let nightly: Cron = "0 3 * * *".parse().unwrap();
+        assert!(nightly.matches(at(1, 3, 0)));
+        assert!(!nightly.matches(at(1, 3, 1)));
+        assert!(!nightly.matches(at(1, 15, 0)));
+
+        let quarterly: Cron = "*/15 9-17 * * 1-5".parse().unwrap();
+        assert!(quarterly.matches(at(1, 9, 45)));
+        assert!(!quarterly.matches(at(1, 9, 50)));
+        assert!(!quarterly.matches(at(6, 9, 45)));
+
+        // Either day field matches when both are given, and 7 is Sunday
+        let either: Cron = "0 0 15 * 7".parse().unwrap();
+        assert!(either.matches(at(7, 0, 0)));
+        assert!(either.matches(at(15, 0, 0)));
+        assert!(!either.matches(at(8, 0, 0)));
+
+        let listed: Cron = "5,20/20 * * * *".parse().unwrap();
+        assert!(listed.matches(at(1, 0, 5)));
+        assert!(listed.matches(at(1, 0, 40)));
+        assert!(!listed.matches(at(1, 0, 21)));
+
+        assert!("0 3 * *".parse::<Cron>().is_err());
+        assert!("60 3 * * *".parse::<Cron>().is_err());
+        assert!("*/0 3 * * *".parse::<Cron>().is_err());
+        assert!("0 5-3 * * *".parse::<Cron>().is_err());
+    }
+
+    #[test]
+    pub fn test_open_until() {
+        let schedule = Schedule {
+            windows: vec![
+                Window {
+                    cron: "0 3 * * *".parse().unwrap(),
+                    length: Duration::from_secs(30 * 60),
+                },
+                Window {
+                    cron: "15 3 * * 1".parse().unwrap(),
+                    length: Duration::from_secs(60 * 60),
+                },
+            ],
+        };
+        assert_eq!(schedule.open_until(at(2, 2, 59)), None);
+        assert_eq!(schedule.open_until(at(2, 3, 0)), Some(at(2, 3, 30)));
+        assert_eq!(schedule.open_until(at(2, 3, 29)), Some(at(2, 3, 30)));
+        assert_eq!(schedule.open_until(at(2, 3, 30)), None);
+        // On Mondays the second window keeps it open for longer
+        assert_eq!(schedule.open_until(at(1, 3, 20)), Some(at(1, 4, 15)));
+        assert_eq!(schedule.open_until(at(1, 4, 0)), Some(at(1, 4, 15)));
+    }
+}
@@ -0,0 +1,405 @@
+use crate::alert::Severity;
+use crate::config::{OpsgenieConfig, PagerDutyConfig, SmtpConfig};
+use crate::watch;
+use anyhow::Result;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use notify_rust::Notification;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Down,
+    Up,
+    Triggered,
+    Resolved,
+}
+
+impl Kind {
+    fn is_resolution(self) -> bool {
+        self == Kind::Up || self == Kind::Resolved
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Down => "down",
+            Kind::Up => "up",
+            Kind::Triggered => "triggered",
+            Kind::Resolved => "resolved",
+        }
+    }
+}
+
+/// Latency statistics of the host at the time of the event, in microseconds.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub min: u64,
+    pub max: u64,
+    pub p95: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertEvent {
+    /// Identifies the condition across its trigger and resolve events
+    pub dedup_key: String,
+    pub host: String,
+    pub kind: Kind,
+    pub severity: Severity,
+    pub message: String,
+    pub stats: Stats,
+}
+
+/// Where alert events are delivered. Every sink runs off the UI thread and
+/// ignores delivery failures: a broken sink shouldn't stop pinging.
+#[derive(Debug, Default)]
+pub struct Sinks {
+    pub desktop: bool,
+    pub webhook: Option<String>,
+    pub on_down: Option<String>,
+    pub on_up: Option<String>,
+    pub smtp: Option<SmtpConfig>,
+    pub pagerduty: Option<PagerDutyConfig>,
+    pub opsgenie: Option<OpsgenieConfig>,
+}
+
+impl Sinks {
+    pub fn send(&self, event: AlertEvent) {
+        if self.desktop && (event.kind == Kind::Down || event.kind == Kind::Up) {
+            let event = event.clone();
+            thread::spawn(move || desktop(&event));
+        }
+        if let Some(ref url) = self.webhook {
+            let url = url.clone();
+            let event = event.clone();
+            thread::spawn(move || webhook(&url, &event));
+        }
+        if let Some(ref smtp) = self.smtp {
+            let smtp = smtp.clone();
+            let event = event.clone();
+            thread::spawn(move || email(&smtp, &event).unwrap_or(()));
+        }
+        if let Some(ref pagerduty) = self.pagerduty {
+            let pagerduty = pagerduty.clone();
+            let event = event.clone();
+            thread::spawn(move || self::pagerduty(&pagerduty, &event).unwrap_or(()));
+        }
+        if let Some(ref opsgenie) = self.opsgenie {
+            let opsgenie = opsgenie.clone();
+            let event = event.clone();
+            thread::spawn(move || self::opsgenie(&opsgenie, &event).unwrap_or(()));
+        }
+        let hook = match event.kind {
+            Kind::Down => self.on_down.clone(),
+            Kind::Up => self.on_up.clone(),
+            _ => None,
+        };
+        if let Some(hook) = hook {
+            thread::spawn(move || run_hook(&hook, &event));
+        }
+    }
+}
+
+/// The most rings in a group of a bell cue, as more can't be counted by ear.
+const MAX_RINGS: usize = 5;
+
+/// How many hosts have a bell cue of their own.
+pub const DISTINCT_CUES: usize = MAX_RINGS * MAX_RINGS;
+
+const RING_SPACING: Duration = Duration::from_millis(300);
+/// The pause between the two groups of a cue, and between cues.
+const GROUP_SPACING: Duration = Duration::from_millis(1000);
+
+/// The cue of the host at `position`, counting from 0, as two groups of
+/// rings like a two-digit number: 1 and 1 for the first host, 1 and 2 for the
+/// second, up to 5 and 5 for the 25th. `None` past those.
+pub fn cue(position: usize) -> Option<(usize, usize)> {
+    if position < DISTINCT_CUES {
+        Some((position / MAX_RINGS + 1, position % MAX_RINGS + 1))
+    } else {
+        None
+    }
+}
+
+/// Rings of the terminal bell waiting to be rung, for the draw loop to ring
+/// so they don't end up in the middle of the chart being drawn.
+#[derive(Debug, Default)]
+pub struct Bell {
+    rings: VecDeque<Instant>,
+}
+
+impl Bell {
+    /// Queue the cue of the host at `position`, after any still ringing.
+    pub fn ring(&mut self, position: usize, now: Instant) {
+        let (first, second) = match cue(position) {
+            Some(cue) => cue,
+            None => return,
+        };
+        let mut at = match self.rings.back() {
+            Some(&last) => last + GROUP_SPACING,
+            None => now,
+        };
+        for group in [first, second] {
+            for _ in 0..group {
+                self.rings.push_back(at);
+                at += RING_SPACING;
+            }
+            at += GROUP_SPACING - RING_SPACING;
+        }
+    }
+
+    /// When the next ring is due.
+    pub fn next(&self) -> Option<Instant> {
+        self.rings.front().copied()
+    }
+
+    /// Take the rings that are due at `now`, returning whether there were any.
+    pub fn due(&mut self, now: Instant) -> bool {
+        let count = self.rings.iter().take_while(|&&at| at <= now).count();
+        self.rings.drain(..count);
+        count > 0
+    }
+}
+
+fn desktop(event: &AlertEvent) {
+    Notification::new()
+        .summary(&format!("{} is {}", event.host, event.kind.as_str()))
+        .body(&event.message)
+        .appname("gping")
+        .show()
+        .map(|_| ())
+        .unwrap_or(());
+}
+
+fn webhook(url: &str, event: &AlertEvent) {
+    ureq::post(url)
+        .send_json(webhook_payload(url, event))
+        .map(|_| ())
+        .unwrap_or(());
+}
+
+fn email(smtp: &SmtpConfig, event: &AlertEvent) -> Result<()> {
+    let mut builder = Message::builder().from(smtp.from.parse()?).subject(format!(
+        "gping [{}]: {} {}",
+        event.severity.as_str(),
+        event.host,
+        event.kind.as_str()
+    ));
+    for to in &smtp.to {
+        builder = builder.to(to.parse()?);
+    }
+    let message = builder.body(format!(
+        "{}\n\nmin {}us, max {}us, p95 {}us\n",
+        event.message, event.stats.min, event.stats.max, event.stats.p95
+    ))?;
+    let mut transport = if smtp.starttls {
+        SmtpTransport::starttls_relay(&smtp.server)?
+    } else {
+        SmtpTransport::relay(&smtp.server)?
+    };
+    if let Some(port) = smtp.port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&smtp.username, &smtp.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport.build().send(&message)?;
+    Ok(())
+}
+
+const PAGERDUTY_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+fn pagerduty(config: &PagerDutyConfig, event: &AlertEvent) -> Result<()> {
+    let body = if event.kind.is_resolution() {
+        json!({
+            "routing_key": config.routing_key,
+            "event_action": "resolve",
+            "dedup_key": event.dedup_key,
+        })
+    } else {
+        let severity = match event.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        };
+        json!({
+            "routing_key": config.routing_key,
+            "event_action": "trigger",
+            "dedup_key": event.dedup_key,
+            "payload": {
+                "summary": format!("{} {}: {}", event.host, event.kind.as_str(), event.message),
+                "source": event.host,
+                "severity": severity,
+                "component": "gping",
+            },
+        })
+    };
+    ureq::post(PAGERDUTY_URL).send_json(body)?;
+    Ok(())
+}
+
+fn opsgenie(config: &OpsgenieConfig, event: &AlertEvent) -> Result<()> {
+    let base = if config.eu {
+        "https://api.eu.opsgenie.com/v2/alerts"
+    } else {
+        "https://api.opsgenie.com/v2/alerts"
+    };
+    let auth = format!("GenieKey {}", config.api_key);
+    if event.kind.is_resolution() {
+        let alias = percent_encode(&event.dedup_key);
+        let url = format!("{}/{}/close?identifierType=alias", base, alias);
+        ureq::post(&url)
+            .set("Authorization", &auth)
+            .send_json(json!({ "note": event.message }))?;
+    } else {
+        let priority = match event.severity {
+            Severity::Info => "P5",
+            Severity::Warning => "P3",
+            Severity::Critical => "P1",
+        };
+        ureq::post(base)
+            .set("Authorization", &auth)
+            .send_json(json!({
+                "message": format!("{} {}", event.host, event.kind.as_str()),
+                "alias": event.dedup_key,
+                "description": event.message,
+                "priority": priority,
+                "source": "gping",
+            }))?;
+    }
+    Ok(())
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Run `hook` through the shell, so it can use pipes and quotes, with the
+/// event in its environment. Its output is dropped, as it would otherwise be
+/// drawn over the chart.
+fn run_hook(hook: &str, event: &AlertEvent) {
+    if hook.trim().is_empty() {
+        return;
+    }
+    let (cmd, args) = match watch::command_line(hook, true) {
+        Some(command) => command,
+        None => return,
+    };
+    Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .env("GPING_HOST", &event.host)
+        .env("GPING_EVENT", event.kind.as_str())
+        .env("GPING_MESSAGE", &event.message)
+        .env("GPING_MIN_US", event.stats.min.to_string())
+        .env("GPING_MAX_US", event.stats.max.to_string())
+        .env("GPING_P95_US", event.stats.p95.to_string())
+        .status()
+        .map(|_| ())
+        .unwrap_or(());
+}
+
+/// Slack and Discord expect their own message shape, anything else gets the
+/// structured event.
+fn webhook_payload(url: &str, event: &AlertEvent) -> Value {
+    let text = format!(
+        "gping [{}]: {} {}: {}",
+        event.severity.as_str(),
+        event.host,
+        event.kind.as_str(),
+        event.message
+    );
+    if url.contains("hooks.slack.com") {
+        json!({ "text": text })
+    } else if url.contains("discord") && url.contains("/api/webhooks/") {
+        json!({ "content": text })
+    } else {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        json!({
+            "host": event.host,
+            "event": event.kind.as_str(),
+            "severity": event.severity.as_str(),
+            "message": event.message,
+            "timestamp": timestamp,
+            "stats": {
+                "min_us": event.stats.min,
+                "max_us": event.stats.max,
+                "p95_us": event.stats.p95,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cue, percent_encode, webhook_payload, AlertEvent, Bell, Kind};
+    use crate::alert::Severity;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    pub fn test_cue() {
+        assert_eq!(cue(0), Some((1, 1)));
+        assert_eq!(cue(4), Some((1, 5)));
+        assert_eq!(cue(5), Some((2, 1)));
+        assert_eq!(cue(24), Some((5, 5)));
+        assert_eq!(cue(25), None);
+    }
+
+    #[test]
+    pub fn test_bell() {
+        let start = Instant::now();
+        let mut bell = Bell::default();
+        // Ring, pause, ring ring
+        bell.ring(1, start);
+        assert_eq!(bell.next(), Some(start));
+        assert!(bell.due(start));
+        assert!(!bell.due(start + Duration::from_millis(500)));
+        assert_eq!(bell.next(), Some(start + Duration::from_millis(1000)));
+        assert!(bell.due(start + Duration::from_millis(1300)));
+        assert_eq!(bell.next(), None);
+        // Hosts with no cue of their own don't ring
+        bell.ring(25, start);
+        assert_eq!(bell.next(), None);
+    }
+
+    #[test]
+    pub fn test_webhook_payload() {
+        let event = AlertEvent {
+            dedup_key: "gping/example.com/down".to_string(),
+            host: "example.com".to_string(),
+            kind: Kind::Down,
+            severity: Severity::Critical,
+            message: "Stopped replying".to_string(),
+            stats: Default::default(),
+        };
+        let slack = webhook_payload("https://hooks.slack.com/services/x", &event);
+        assert_eq!(
+            slack["text"],
+            "gping [critical]: example.com down: Stopped replying"
+        );
+        let generic = webhook_payload("https://example.com/hook", &event);
+        assert_eq!(generic["host"], "example.com");
+        assert_eq!(generic["event"], "down");
+    }
+
+    #[test]
+    pub fn test_percent_encode() {
+        assert_eq!(percent_encode("gping/host/a>1s"), "gping%2Fhost%2Fa%3E1s");
+    }
+}
@@ -0,0 +1,655 @@
+use crate::jsonpath::Selector;
+use crate::load;
+use crate::poll;
+use crate::probe::{self, Mechanism, PingResult};
+use crate::prom;
+use crate::resolve;
+use crate::tail;
+use crate::watch;
+use anyhow::{anyhow, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::process::{Child, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Something graphed as one line of the chart, like the round trip of a
+/// ping to a host or how long a watched command takes to run. Each sample is
+/// a duration, so all sources share the chart's axis.
+pub trait DataSource: fmt::Debug + Send + Sync {
+    /// What the line is labelled with, e.g. `example.com:443`.
+    fn name(&self) -> String;
+    /// What's being done to take samples, shown before the name.
+    fn action(&self) -> &'static str {
+        "Pinging"
+    }
+    /// Whether the host is resolved to an address before starting, and again
+    /// with `--resolve-interval`. Otherwise the address is given up front.
+    fn resolves(&self) -> bool {
+        true
+    }
+    /// Whether samples come at the source's own pace rather than every
+    /// interval, so `--fast` and `--burst` don't apply.
+    fn own_pace(&self) -> bool {
+        false
+    }
+    /// Whether samples are taken with ICMP, so there has to be a way to send it.
+    fn uses_icmp(&self) -> bool {
+        false
+    }
+    /// Start taking samples of `target`, the host's address, every interval
+    /// of `options`. Sampling stops once the receiver is dropped, and the
+    /// source can end it by dropping the sender.
+    fn start(
+        &self,
+        target: &str,
+        options: &probe::Options,
+        notify: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>>;
+}
+
+/// Something a source found out about its host besides a sample.
+#[derive(Debug)]
+pub enum Notice {
+    /// How pings are being sent
+    Transport(Mechanism),
+    /// The status of a response from a URL
+    Status(u16),
+    /// Something worth marking on the chart
+    Annotation(String),
+    /// The last line a watched command printed
+    Output(String),
+    /// A watched command exited with this code, which wasn't 0
+    ExitCode(i32),
+}
+
+/// Where a source sends its notices.
+pub type Notify = Box<dyn Fn(Notice) + Send>;
+
+/// How a host given on the command line or added later is probed, by
+/// requesting it if it's a URL or pinging it otherwise.
+pub fn of(host: &str) -> Arc<dyn DataSource> {
+    if is_url(host) {
+        Arc::new(Http {
+            url: host.to_string(),
+        })
+    } else {
+        Arc::new(Ping {
+            host: host.to_string(),
+        })
+    }
+}
+
+pub fn is_url(host: &str) -> bool {
+    host.starts_with("http://") || host.starts_with("https://")
+}
+
+fn with_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// ICMP echo requests.
+#[derive(Debug)]
+pub struct Ping {
+    pub host: String,
+}
+
+impl DataSource for Ping {
+    fn name(&self) -> String {
+        self.host.clone()
+    }
+    fn uses_icmp(&self) -> bool {
+        true
+    }
+    fn start(
+        &self,
+        target: &str,
+        options: &probe::Options,
+        notify: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        let (mechanism, stream) = probe::ping(target.to_string(), options)?;
+        notify(Notice::Transport(mechanism));
+        Ok(stream)
+    }
+}
+
+/// TCP handshakes with a port, for `--tcp`.
+#[derive(Debug)]
+pub struct Tcp {
+    pub host: String,
+    pub port: u16,
+}
+
+impl DataSource for Tcp {
+    fn name(&self) -> String {
+        with_port(&self.host, self.port)
+    }
+    fn start(
+        &self,
+        target: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        probe::ping_tcp(target, self.port, options)
+    }
+}
+
+/// TCP connects to a port through a proxy, for `--tcp` with `--proxy`. The
+/// proxy resolves the host.
+#[derive(Debug)]
+pub struct Proxied {
+    pub host: String,
+    pub port: u16,
+    pub proxy: probe::Proxy,
+}
+
+impl DataSource for Proxied {
+    fn name(&self) -> String {
+        format!("{} via {}", with_port(&self.host, self.port), self.proxy)
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn start(
+        &self,
+        _: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        Ok(probe::ping_proxied(
+            &self.proxy,
+            &self.host,
+            self.port,
+            options,
+        ))
+    }
+}
+
+/// Datagrams to a port and their answers, for `--udp`.
+#[derive(Debug)]
+pub struct Udp {
+    pub host: String,
+    pub port: u16,
+}
+
+impl DataSource for Udp {
+    fn name(&self) -> String {
+        with_port(&self.host, self.port)
+    }
+    fn start(
+        &self,
+        target: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        probe::ping_udp(target, self.port, options)
+    }
+}
+
+/// TLS handshakes on a port, after connecting to it, for `--tls`.
+#[derive(Debug)]
+pub struct Tls {
+    pub host: String,
+    pub port: u16,
+}
+
+impl DataSource for Tls {
+    fn name(&self) -> String {
+        with_port(&self.host, self.port)
+    }
+    fn start(
+        &self,
+        target: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        probe::ping_tls(&self.host, target, self.port, options)
+    }
+}
+
+/// QUIC handshakes with a port, for `--quic`.
+#[derive(Debug)]
+pub struct Quic {
+    pub host: String,
+    pub port: u16,
+}
+
+impl DataSource for Quic {
+    fn name(&self) -> String {
+        with_port(&self.host, self.port)
+    }
+    fn start(
+        &self,
+        target: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        probe::ping_quic(&self.host, target, self.port, options)
+    }
+}
+
+/// ARP requests on the local network, for `--arp`. The host is an address
+/// already.
+#[derive(Debug)]
+pub struct Arp {
+    pub host: String,
+}
+
+impl DataSource for Arp {
+    fn name(&self) -> String {
+        format!("ARP {}", self.host)
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn start(
+        &self,
+        target: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        probe::ping_arp(target, options)
+    }
+}
+
+/// HTTP requests, for hosts given as URLs. Each request resolves the URL.
+#[derive(Debug)]
+pub struct Http {
+    pub url: String,
+}
+
+impl DataSource for Http {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn start(
+        &self,
+        _: &str,
+        options: &probe::Options,
+        notify: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        probe::ping_http(&self.url, options, move |status| {
+            notify(Notice::Status(status))
+        })
+    }
+}
+
+/// Lookups of a name on a DNS server, for `--dns`. The queries go to the
+/// server, so the name isn't resolved first.
+#[derive(Debug)]
+pub struct Dns {
+    pub name: String,
+    pub server: resolve::Server,
+}
+
+impl DataSource for Dns {
+    fn name(&self) -> String {
+        format!("{}@{}", self.name, self.server)
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn start(
+        &self,
+        _: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        probe::ping_dns(&self.name, &self.server, options)
+    }
+}
+
+/// How long a command takes to run, or a number it prints, for `--watch`.
+/// Runs that fail are marked as failures, and runs killed by `--watch-timeout`
+/// count as timeouts.
+#[derive(Debug)]
+pub struct Watch {
+    pub command: String,
+    /// Run `command`, and `diff_hook`, through the shell
+    pub shell: bool,
+    pub context: watch::Context,
+    pub value: watch::Value,
+    /// How long to wait after each run before the next
+    pub interval: Duration,
+    /// The most output to keep from each run
+    pub output_limit: usize,
+    /// Send the last line of each run's output as a notice
+    pub show_output: bool,
+    /// Kill runs that take longer, counting them as timeouts
+    pub timeout: Option<Duration>,
+    /// Mark the chart when the output changes
+    pub diff: bool,
+    /// A command to run when the output changes
+    pub diff_hook: Option<String>,
+    /// Turns shared with the other watched commands, for
+    /// `--watch-concurrency`
+    pub slots: Option<watch::Slots>,
+}
+
+impl DataSource for Watch {
+    fn name(&self) -> String {
+        self.command.clone()
+    }
+    fn action(&self) -> &'static str {
+        "Running"
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn own_pace(&self) -> bool {
+        true
+    }
+    fn start(
+        &self,
+        _: &str,
+        _: &probe::Options,
+        notify: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        let (cmd, cmd_args) = watch::command_line(&self.command, self.shell)
+            .ok_or_else(|| anyhow!("Must specify a command to watch"))?;
+        let shell = self.shell;
+        let context = self.context.clone();
+        let value = self.value.clone();
+        let interval = self.interval;
+        let output_limit = self.output_limit;
+        let timeout = self.timeout;
+        let show_output = self.show_output;
+        let diff = self.diff;
+        let diff_hook = self.diff_hook.clone();
+        let slots = self.slots.clone();
+        let command = self.command.clone();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || -> Result<()> {
+            let mut last_hash = None;
+            let mut child_command = context.command(&cmd, &cmd_args);
+            let mut hooks: Vec<Child> = vec![];
+            loop {
+                // Hooks that have finished are reaped, marking any that failed
+                hooks.retain_mut(|hook| match hook.try_wait() {
+                    Ok(None) => true,
+                    Ok(Some(status)) => {
+                        if !status.success() {
+                            notify(Notice::Annotation(format!("diff hook {}", status)));
+                        }
+                        false
+                    }
+                    Err(_) => false,
+                });
+                let slot = slots.as_ref().map(watch::Slots::take);
+                let start = Instant::now();
+                let run = watch::run(&mut child_command, output_limit, timeout)?;
+                drop(slot);
+                let output = match run {
+                    Some(output) => output,
+                    None => {
+                        tx.send(PingResult::Timeout)?;
+                        thread::sleep(interval);
+                        continue;
+                    }
+                };
+                tx.send(value.sample(&output, start.elapsed()))?;
+                if !output.status.success() {
+                    // Killed by a signal, which has no code
+                    notify(Notice::ExitCode(output.status.code().unwrap_or(1)));
+                }
+                if show_output {
+                    if let Some(line) = output.last_line() {
+                        notify(Notice::Output(line));
+                    }
+                }
+                if diff {
+                    let mut hasher = DefaultHasher::new();
+                    output.stdout.hash(&mut hasher);
+                    let hash = hasher.finish();
+                    if last_hash.is_some_and(|last| last != hash) {
+                        notify(Notice::Annotation("output changed".to_string()));
+                        if let Some(ref hook) = diff_hook {
+                            if let Some((hook_cmd, hook_args)) = watch::command_line(hook, shell) {
+                                // Left to run alongside the watched command,
+                                // with its output kept off the chart
+                                let spawned = context
+                                    .command(&hook_cmd, &hook_args)
+                                    .env("GPING_WATCH", &command)
+                                    .stdin(Stdio::null())
+                                    .stdout(Stdio::null())
+                                    .stderr(Stdio::null())
+                                    .spawn();
+                                match spawned {
+                                    Ok(child) => hooks.push(child),
+                                    Err(e) => notify(Notice::Annotation(format!(
+                                        "diff hook failed: {}",
+                                        e
+                                    ))),
+                                }
+                            }
+                        }
+                    }
+                    last_hash = Some(hash);
+                }
+                thread::sleep(interval);
+            }
+        });
+        Ok(rx)
+    }
+}
+
+/// Samples with one label fed in line by line, for `--stdin` and `--pipe`.
+#[derive(Debug)]
+pub struct Feed {
+    pub label: String,
+    samples: Mutex<Option<mpsc::Receiver<PingResult>>>,
+}
+
+impl Feed {
+    pub fn new(label: String, samples: mpsc::Receiver<PingResult>) -> Self {
+        Feed {
+            label,
+            samples: Mutex::new(Some(samples)),
+        }
+    }
+}
+
+impl DataSource for Feed {
+    fn name(&self) -> String {
+        self.label.clone()
+    }
+    fn action(&self) -> &'static str {
+        "Reading"
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn own_pace(&self) -> bool {
+        true
+    }
+    /// The samples come as they're fed in, so they can only be started once.
+    fn start(&self, _: &str, _: &probe::Options, _: Notify) -> Result<mpsc::Receiver<PingResult>> {
+        self.samples
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow!("{} was already started", self.label))
+    }
+}
+
+/// The value of a PromQL query, run every interval, for `--prom`. Values are
+/// taken as seconds, the unit Prometheus measures time in.
+#[derive(Debug)]
+pub struct Prom {
+    /// Like `http://prometheus:9090`
+    pub server: String,
+    pub query: String,
+}
+
+impl DataSource for Prom {
+    fn name(&self) -> String {
+        self.query.clone()
+    }
+    fn action(&self) -> &'static str {
+        "Querying"
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn start(
+        &self,
+        _: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        let server = self.server.clone();
+        let query = self.query.clone();
+        let timeout = options.timeout.unwrap_or(options.interval);
+        Ok(poll::every(options.interval, move || {
+            let secs = prom::query(&server, &query, timeout).ok();
+            poll::millis(secs.map(|secs| secs * 1000.0))
+        }))
+    }
+}
+
+/// A number in a JSON document fetched every interval, for `--json-poll`.
+/// Values are taken as milliseconds.
+#[derive(Debug)]
+pub struct JsonPoll {
+    pub url: String,
+    pub selector: Selector,
+}
+
+impl DataSource for JsonPoll {
+    fn name(&self) -> String {
+        format!("{} {}", self.url, self.selector)
+    }
+    fn action(&self) -> &'static str {
+        "Polling"
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn start(
+        &self,
+        _: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        let url = self.url.clone();
+        let selector = self.selector.clone();
+        let agent = ureq::AgentBuilder::new()
+            .timeout(options.timeout.unwrap_or(options.interval))
+            .build();
+        Ok(poll::every(options.interval, move || {
+            let document: Option<serde_json::Value> = agent
+                .get(&url)
+                .call()
+                .ok()
+                .and_then(|response| response.into_json().ok());
+            poll::millis(document.and_then(|document| selector.number(&document)))
+        }))
+    }
+}
+
+/// How loaded this machine is, sampled every interval for `--load`. The
+/// load average or CPU percentage is taken as milliseconds.
+#[derive(Debug)]
+pub struct Load {
+    pub metric: load::Metric,
+}
+
+impl DataSource for Load {
+    fn name(&self) -> String {
+        self.metric.to_string()
+    }
+    fn action(&self) -> &'static str {
+        "Sampling"
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn start(
+        &self,
+        _: &str,
+        options: &probe::Options,
+        _: Notify,
+    ) -> Result<mpsc::Receiver<PingResult>> {
+        let mut sampler = load::Sampler::new(self.metric);
+        Ok(poll::every(options.interval, move || {
+            poll::millis(sampler.sample().ok())
+        }))
+    }
+}
+
+/// Numbers in a column of rows appended to a file, for `--tail`.
+#[derive(Debug)]
+pub struct Tail {
+    pub path: PathBuf,
+    /// Counting from 1
+    pub column: usize,
+}
+
+impl DataSource for Tail {
+    fn name(&self) -> String {
+        format!("{} column {}", self.path.display(), self.column)
+    }
+    fn action(&self) -> &'static str {
+        "Tailing"
+    }
+    fn resolves(&self) -> bool {
+        false
+    }
+    fn own_pace(&self) -> bool {
+        true
+    }
+    fn start(&self, _: &str, _: &probe::Options, _: Notify) -> Result<mpsc::Receiver<PingResult>> {
+        tail::follow(self.path.clone(), self.column)
+            .map_err(|e| anyhow!("Could not follow {}: {}", self.path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{of, Arp, DataSource, Dns, Proxied, Tcp};
+    use crate::probe::Proxy;
+    use crate::resolve::parse_server;
+
+    #[test]
+    pub fn test_names() {
+        let tcp = Tcp {
+            host: "2001:db8::1".to_string(),
+            port: 443,
+        };
+        assert_eq!(tcp.name(), "[2001:db8::1]:443");
+        let proxied = Proxied {
+            host: "example.com".to_string(),
+            port: 443,
+            proxy: Proxy::parse("socks5://127.0.0.1:1080").unwrap(),
+        };
+        assert_eq!(
+            proxied.name(),
+            "example.com:443 via socks5://127.0.0.1:1080"
+        );
+        let arp = Arp {
+            host: "192.168.1.1".to_string(),
+        };
+        assert_eq!(arp.name(), "ARP 192.168.1.1");
+        let dns = Dns {
+            name: "example.com".to_string(),
+            server: parse_server("1.1.1.1").unwrap(),
+        };
+        assert_eq!(dns.name(), format!("example.com@{}", dns.server));
+    }
+
+    #[test]
+    pub fn test_of() {
+        assert!(!of("https://example.com/").resolves());
+        assert!(of("example.com").uses_icmp());
+    }
+}
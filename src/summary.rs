@@ -0,0 +1,218 @@
+use histogram::Histogram;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// How a target did over the whole session, printed when gping exits.
+#[derive(Debug)]
+pub struct Row {
+    pub target: String,
+    pub sent: u64,
+    pub received: u64,
+    /// The rest are None without any replies
+    pub min: Option<Duration>,
+    pub avg: Option<Duration>,
+    pub max: Option<Duration>,
+    pub p50: Option<Duration>,
+    pub p95: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub stddev: Option<Duration>,
+}
+
+impl Row {
+    /// The statistics of a target that was probed `sent` times, from the
+    /// round trips of its replies in microseconds.
+    pub fn new(target: &str, sent: u64, replies: &Histogram) -> Self {
+        let received = replies.entries();
+        // The histogram has nothing to say without replies
+        let micros =
+            |micros: Option<u64>| micros.filter(|_| received > 0).map(Duration::from_micros);
+        Row {
+            target: target.to_string(),
+            sent,
+            received,
+            min: micros(replies.minimum().ok()),
+            avg: micros(replies.mean().ok()),
+            max: micros(replies.maximum().ok()),
+            p50: micros(replies.percentile(50.0).ok()),
+            p95: micros(replies.percentile(95.0).ok()),
+            p99: micros(replies.percentile(99.0).ok()),
+            stddev: micros(replies.stddev()),
+        }
+    }
+
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            0f64
+        } else {
+            self.sent.saturating_sub(self.received) as f64 * 100f64 / self.sent as f64
+        }
+    }
+}
+
+/// A table of `rows` like ping's closing statistics, followed by how long the
+/// session lasted.
+pub fn table(rows: &[Row], elapsed: Duration) -> String {
+    let header = [
+        "target", "sent", "recv", "loss", "min", "avg", "max", "p95", "stddev",
+    ];
+    let mut cells = vec![header
+        .iter()
+        .map(|cell| cell.to_string())
+        .collect::<Vec<_>>()];
+    for row in rows {
+        cells.push(vec![
+            row.target.clone(),
+            row.sent.to_string(),
+            row.received.to_string(),
+            format!("{:.1}%", row.loss()),
+            millis(row.min),
+            millis(row.avg),
+            millis(row.max),
+            millis(row.p95),
+            millis(row.stddev),
+        ]);
+    }
+    let mut table = align(cells);
+    table.push_str(&format!("session {}", format_elapsed(elapsed)));
+    table
+}
+
+/// Lay `cells` out in columns, a line to a row, with the first column on the
+/// left and the rest lined up on the right.
+pub fn align(cells: Vec<Vec<String>>) -> String {
+    let columns = cells.first().map_or(0, Vec::len);
+    let widths: Vec<_> = (0..columns)
+        .map(|column| {
+            cells
+                .iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut table = String::new();
+    for row in cells {
+        let line: Vec<_> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, &width))| {
+                // Targets read best on the left, numbers lined up on the right
+                if column == 0 {
+                    format!("{:<width$}", cell, width = width)
+                } else {
+                    format!("{:>width$}", cell, width = width)
+                }
+            })
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// The same statistics as `table`, for `--report-json`, with durations in
+/// microseconds and null for targets that never replied.
+pub fn report(rows: &[Row], elapsed: Duration) -> Value {
+    let micros = |duration: Option<Duration>| duration.map(|duration| duration.as_micros() as u64);
+    let targets: Vec<_> = rows
+        .iter()
+        .map(|row| {
+            json!({
+                "target": row.target,
+                "sent": row.sent,
+                "received": row.received,
+                "loss_percent": row.loss(),
+                "min_us": micros(row.min),
+                "avg_us": micros(row.avg),
+                "max_us": micros(row.max),
+                "stddev_us": micros(row.stddev),
+                "percentiles_us": {
+                    "p50": micros(row.p50),
+                    "p95": micros(row.p95),
+                    "p99": micros(row.p99),
+                },
+            })
+        })
+        .collect();
+    json!({
+        "duration_secs": elapsed.as_secs_f64(),
+        "targets": targets,
+    })
+}
+
+pub fn millis(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("{:.1}ms", duration.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+/// Like `1h 2m 5s`, leaving out leading zeroes.
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, secs)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_elapsed, report, table, Row};
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_table() {
+        let us = |us| Some(Duration::from_micros(us));
+        let rows = vec![
+            Row {
+                target: "example.com".to_string(),
+                sent: 60,
+                received: 57,
+                min: us(11_200),
+                avg: us(12_040),
+                max: us(30_100),
+                p50: us(11_900),
+                p95: us(14_260),
+                p99: us(29_800),
+                stddev: us(2_100),
+            },
+            Row {
+                target: "10.0.0.1".to_string(),
+                sent: 3,
+                received: 0,
+                min: None,
+                avg: None,
+                max: None,
+                p50: None,
+                p95: None,
+                p99: None,
+                stddev: None,
+            },
+        ];
+        assert_eq!(
+            table(&rows, Duration::from_secs(61)),
+            "\
+target       sent  recv    loss     min     avg     max     p95  stddev
+example.com    60    57    5.0%  11.2ms  12.0ms  30.1ms  14.3ms   2.1ms
+10.0.0.1        3     0  100.0%       -       -       -       -       -
+session 1m 1s"
+        );
+        let report = report(&rows, Duration::from_secs(61));
+        assert_eq!(report["duration_secs"], 61.0);
+        assert_eq!(report["targets"][0]["percentiles_us"]["p99"], 29_800);
+        assert_eq!(report["targets"][1]["loss_percent"], 100.0);
+        assert!(report["targets"][1]["min_us"].is_null());
+    }
+
+    #[test]
+    pub fn test_format_elapsed() {
+        assert_eq!(format_elapsed(Duration::from_millis(4500)), "4s");
+        assert_eq!(format_elapsed(Duration::from_secs(3725)), "1h 2m 5s");
+    }
+}
@@ -0,0 +1,82 @@
+use crate::probe::PingResult;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How often to look for new rows once the end of the file is reached.
+const POLL: Duration = Duration::from_millis(200);
+
+/// Follow the file at `path` from its current end like `tail -f`, sending
+/// the number in the 1-based `column` of each row appended to it. Rows
+/// without a number there, like headers, are skipped. If the file is
+/// truncated or replaced by a shorter one, it's read again from the start.
+pub fn follow(path: PathBuf, column: usize) -> io::Result<mpsc::Receiver<PingResult>> {
+    let mut file = File::open(&path)?;
+    let mut position = file.seek(SeekFrom::End(0))?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || -> io::Result<()> {
+        let mut reader = BufReader::new(file);
+        let mut row = String::new();
+        loop {
+            let read = reader.read_line(&mut row)?;
+            // Wait for the rest of a row that's still being written
+            if read == 0 || !row.ends_with('\n') {
+                if fs::metadata(&path).is_ok_and(|metadata| metadata.len() < position) {
+                    reader = BufReader::new(File::open(&path)?);
+                    position = 0;
+                    row.clear();
+                }
+                thread::sleep(POLL);
+                continue;
+            }
+            position += row.len() as u64;
+            if let Some(value) = value(&row, column) {
+                if tx.send(PingResult::Pong(value)).is_err() {
+                    return Ok(());
+                }
+            }
+            row.clear();
+        }
+    });
+    Ok(rx)
+}
+
+/// The number of milliseconds in `column` of a row. Rows are split on commas
+/// if they have any, like CSV, and on whitespace otherwise, like most logs.
+fn value(row: &str, column: usize) -> Option<Duration> {
+    let row = row.trim();
+    let field = if row.contains(',') {
+        row.split(',').nth(column.checked_sub(1)?)?
+    } else {
+        row.split_whitespace().nth(column.checked_sub(1)?)?
+    };
+    let value: f64 = field.trim().trim_matches('"').parse().ok()?;
+    if !value.is_finite() || value < 0.0 {
+        return None;
+    }
+    Some(Duration::from_micros((value * 1000.0).round() as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::value;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_value() {
+        assert_eq!(
+            value("2021-03-01T10:00:00,api,\"12.5\"\n", 3),
+            Some(Duration::from_micros(12500))
+        );
+        assert_eq!(
+            value("10:00:00 GET /health 200 7\n", 5),
+            Some(Duration::from_millis(7))
+        );
+        assert_eq!(value("time,target,latency_ms\n", 3), None);
+        assert_eq!(value("1,2\n", 3), None);
+        assert_eq!(value("1,2\n", 0), None);
+    }
+}
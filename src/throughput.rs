@@ -0,0 +1,64 @@
+use anyhow::Result;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+/// A completed download, timed from sending the request to the last byte.
+#[derive(Debug, Clone, Copy)]
+pub struct Transfer {
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Transfer {
+    pub fn bits_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        (self.bytes * 8) as f64 / secs
+    }
+}
+
+/// Download up to `size` bytes of `url`. Servers that honour the Range header
+/// stop there, otherwise the rest of the body is dropped with the connection.
+pub fn measure(url: &str, size: u64) -> Result<Transfer> {
+    let start = Instant::now();
+    let response = ureq::get(url)
+        .set("Range", &format!("bytes=0-{}", size.saturating_sub(1)))
+        .call()?;
+    let bytes = io::copy(&mut response.into_reader().take(size), &mut io::sink())?;
+    Ok(Transfer {
+        bytes,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Format a rate like `12.5 Mbit/s`.
+pub fn format_rate(bits_per_sec: f64) -> String {
+    let units = ["bit/s", "kbit/s", "Mbit/s", "Gbit/s"];
+    let mut rate = bits_per_sec;
+    let mut unit = 0;
+    while rate >= 1000.0 && unit < units.len() - 1 {
+        rate /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", rate, units[unit])
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_rate, Transfer};
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_bits_per_sec() {
+        let transfer = Transfer {
+            bytes: 100_000,
+            elapsed: Duration::from_millis(100),
+        };
+        assert_eq!(transfer.bits_per_sec(), 8_000_000.0);
+        assert_eq!(format_rate(transfer.bits_per_sec()), "8.0 Mbit/s");
+        assert_eq!(format_rate(950.0), "950.0 bit/s");
+        assert_eq!(format_rate(2.5e12), "2500.0 Gbit/s");
+    }
+}
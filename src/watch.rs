@@ -0,0 +1,365 @@
+use crate::jsonpath::Selector;
+use crate::probe::{Failure, PingResult};
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check whether a command with `--watch-timeout` has exited.
+const WAIT_POLL: Duration = Duration::from_millis(10);
+
+/// What's graphed for each run of a watched command.
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// How long the command took
+    Elapsed,
+    /// The number the last line of its output starts with, as milliseconds,
+    /// for `--watch-output`
+    LastLine,
+    /// The number the capture group of `--extract` finds in its output, as
+    /// milliseconds
+    Extract(Regex),
+    /// The number at the path of `--extract-json` in its output, as
+    /// milliseconds
+    Json(Selector),
+}
+
+impl Value {
+    /// The sample for a run that took `elapsed`. Failed runs, and output
+    /// without a number, are failures of their own rather than timeouts.
+    pub fn sample(&self, output: &CmdOutput, elapsed: Duration) -> PingResult {
+        if !output.status.success() {
+            return PingResult::Failed(Failure::CommandFailed);
+        }
+        let number = match self {
+            Value::Elapsed => return PingResult::Pong(elapsed),
+            Value::LastLine => last_number(&output.stdout),
+            Value::Extract(regex) => extract(regex, &output.stdout),
+            Value::Json(selector) => serde_json::from_slice(&output.stdout)
+                .ok()
+                .and_then(|document| selector.number(&document)),
+        };
+        match number {
+            Some(ms) if ms.is_finite() && ms >= 0.0 => {
+                PingResult::Pong(Duration::from_micros((ms * 1000.0).round() as u64))
+            }
+            _ => PingResult::Failed(Failure::ParseError),
+        }
+    }
+}
+
+/// The program and arguments to run for `command`. It's split on whitespace
+/// unless `shell` is set, when it's handed to `sh -c`, or `cmd /C` on
+/// Windows, so pipes, quotes and redirections work.
+pub fn command_line(command: &str, shell: bool) -> Option<(String, Vec<String>)> {
+    if shell {
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        return Some((
+            shell.to_string(),
+            vec![flag.to_string(), command.to_string()],
+        ));
+    }
+    let mut words = command
+        .split_ascii_whitespace()
+        .map(|word| word.to_string());
+    Some((words.next()?, words.collect()))
+}
+
+/// Parse `--extract`, which needs exactly one capture group.
+pub fn parse_extract(pattern: &str) -> Result<Regex> {
+    let regex = Regex::new(pattern)?;
+    if regex.captures_len() != 2 {
+        return Err(anyhow!(
+            "--extract needs exactly one capture group, like 'time=([0-9.]+)'"
+        ));
+    }
+    Ok(regex)
+}
+
+fn extract(regex: &Regex, stdout: &[u8]) -> Option<f64> {
+    let stdout = String::from_utf8_lossy(stdout);
+    regex
+        .captures(&stdout)?
+        .get(1)?
+        .as_str()
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn last_number(stdout: &[u8]) -> Option<f64> {
+    last_line(stdout)?.split_whitespace().next()?.parse().ok()
+}
+
+#[derive(Debug)]
+pub struct CmdOutput {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl CmdOutput {
+    /// The last line with something on it, from stdout or else stderr.
+    pub fn last_line(&self) -> Option<String> {
+        last_line(&self.stdout).or_else(|| last_line(&self.stderr))
+    }
+}
+
+fn last_line(output: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(|line| line.trim())
+        .rev()
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string())
+}
+
+/// Where watched commands, and their diff hooks, run and what they find in
+/// their environment besides gping's own.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub dir: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+}
+
+impl Context {
+    pub fn command(&self, program: &str, args: &[String]) -> Command {
+        let mut command = Command::new(program);
+        command.args(args).envs(self.env.iter().cloned());
+        if let Some(ref dir) = self.dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+}
+
+/// Parse a `--watch-env` variable given as `KEY=value`.
+pub fn parse_env(var: &str) -> Result<(String, String)> {
+    match var.find('=') {
+        Some(idx) if idx > 0 => Ok((var[..idx].to_string(), var[idx + 1..].to_string())),
+        _ => Err(anyhow!("Expected KEY=value, not {}", var)),
+    }
+}
+
+/// Parse `--watch-concurrency`, the most watched commands to run at once:
+/// `sequential` for one at a time, `parallel` for no limit, or a number.
+pub fn parse_concurrency(input: &str) -> Result<usize> {
+    match input {
+        "sequential" => Ok(1),
+        "parallel" => Ok(usize::MAX),
+        _ => match input.parse() {
+            Ok(limit) if limit > 0 => Ok(limit),
+            _ => Err(anyhow!(
+                "Expected sequential, parallel or a number above 0, not {}",
+                input
+            )),
+        },
+    }
+}
+
+/// Turns at running watched commands, shared between them so that heavy
+/// commands don't skew each other's timings by running together.
+#[derive(Debug, Clone)]
+pub struct Slots(Arc<(Mutex<usize>, Condvar)>);
+
+impl Slots {
+    pub fn new(limit: usize) -> Self {
+        Slots(Arc::new((Mutex::new(limit), Condvar::new())))
+    }
+
+    /// Wait for a turn, which lasts until the slot is dropped.
+    pub fn take(&self) -> Slot {
+        let (free, freed) = &*self.0;
+        let mut free = free.lock().unwrap();
+        while *free == 0 {
+            free = freed.wait(free).unwrap();
+        }
+        *free -= 1;
+        Slot(self.clone())
+    }
+}
+
+pub struct Slot(Slots);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let (free, freed) = &*(self.0).0;
+        *free.lock().unwrap() += 1;
+        freed.notify_one();
+    }
+}
+
+/// Run a watched command with stdin detached, keeping at most `limit` bytes
+/// of stdout and stderr. Anything past the limit is read and discarded so the
+/// child never blocks on a full pipe. A command still running after
+/// `timeout` is killed, and None returned.
+pub fn run(
+    command: &mut Command,
+    limit: usize,
+    timeout: Option<Duration>,
+) -> io::Result<Option<CmdOutput>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stdout_thread = thread::spawn(move || read_capped(stdout, limit));
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_thread = thread::spawn(move || read_capped(stderr, limit));
+    let status = match timeout {
+        Some(timeout) => match wait_timeout(&mut child, timeout)? {
+            Some(status) => status,
+            None => {
+                // It may have exited since, which is fine. The readers are
+                // left behind as its own children could keep the pipes open.
+                let _ = child.kill();
+                child.wait()?;
+                return Ok(None);
+            }
+        },
+        None => child.wait()?,
+    };
+    let stdout = stdout_thread.join().expect("stdout reader panicked")?;
+    let stderr = stderr_thread.join().expect("stderr reader panicked")?;
+    Ok(Some(CmdOutput {
+        status,
+        stdout,
+        stderr,
+    }))
+}
+
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+        thread::sleep(WAIT_POLL);
+    }
+}
+
+fn read_capped<R: Read>(mut reader: R, limit: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.by_ref().take(limit as u64).read_to_end(&mut buf)?;
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        command_line, extract, last_line, last_number, parse_concurrency, parse_env, parse_extract,
+        read_capped, Slots,
+    };
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_read_capped() {
+        let input: &[u8] = b"0123456789";
+        assert_eq!(read_capped(input, 4).unwrap(), b"0123");
+        assert_eq!(read_capped(input, 100).unwrap(), b"0123456789");
+    }
+
+    #[test]
+    pub fn test_last_line() {
+        assert_eq!(
+            last_line(b"ok\n  3 jobs queued \r\n\n").as_deref(),
+            Some("3 jobs queued")
+        );
+        assert_eq!(last_line(b" \n"), None);
+    }
+
+    #[test]
+    pub fn test_last_number() {
+        assert_eq!(last_number(b"queue depth:\n42 items\n\n"), Some(42.0));
+        assert_eq!(last_number(b"12.5\r\n"), Some(12.5));
+        assert_eq!(last_number(b"42\ndone\n"), None);
+        assert_eq!(last_number(b""), None);
+    }
+
+    #[test]
+    pub fn test_command_line() {
+        assert_eq!(
+            command_line("curl -s  localhost/health", false),
+            Some((
+                "curl".to_string(),
+                vec!["-s".to_string(), "localhost/health".to_string()]
+            ))
+        );
+        assert_eq!(command_line(" ", false), None);
+        let (program, args) = command_line("dig +short example.com | wc -l", true).unwrap();
+        assert_eq!(args.last().unwrap(), "dig +short example.com | wc -l");
+        assert_eq!(program, if cfg!(windows) { "cmd" } else { "sh" });
+    }
+
+    #[test]
+    pub fn test_parse_env() {
+        assert_eq!(
+            parse_env("API_URL=http://localhost:8080/?a=b").unwrap(),
+            (
+                "API_URL".to_string(),
+                "http://localhost:8080/?a=b".to_string()
+            )
+        );
+        assert_eq!(
+            parse_env("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), String::new())
+        );
+        assert!(parse_env("=value").is_err());
+        assert!(parse_env("VERBOSE").is_err());
+    }
+
+    #[test]
+    pub fn test_extract() {
+        let regex = parse_extract(r"time=([0-9.]+)").unwrap();
+        assert_eq!(extract(&regex, b"seq=1 ttl=57 time=12.3 ms\n"), Some(12.3));
+        assert_eq!(extract(&regex, b"request timed out\n"), None);
+        assert!(parse_extract(r"time=[0-9.]+").is_err());
+        assert!(parse_extract(r"(\w+)=([0-9.]+)").is_err());
+        assert!(parse_extract(r"time=(").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_concurrency() {
+        assert_eq!(parse_concurrency("sequential").unwrap(), 1);
+        assert_eq!(parse_concurrency("parallel").unwrap(), usize::MAX);
+        assert_eq!(parse_concurrency("3").unwrap(), 3);
+        assert!(parse_concurrency("0").is_err());
+        assert!(parse_concurrency("some").is_err());
+    }
+
+    #[test]
+    pub fn test_slots() {
+        let slots = Slots::new(1);
+        let slot = slots.take();
+        let ran = Arc::new(AtomicBool::new(false));
+        let waiting = {
+            let slots = slots.clone();
+            let ran = Arc::clone(&ran);
+            thread::spawn(move || {
+                let _slot = slots.take();
+                ran.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!ran.load(Ordering::SeqCst));
+        drop(slot);
+        waiting.join().unwrap();
+        assert!(ran.load(Ordering::SeqCst));
+    }
+}